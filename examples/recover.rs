@@ -0,0 +1,38 @@
+use av_format::buffer::AccReader;
+use av_format::muxer::Writer;
+
+use av_mp4::recovery::Recovery;
+
+use log::*;
+
+fn main() {
+    pretty_env_logger::init();
+
+    let in_path = std::env::args().nth(1).unwrap();
+    let out_path = std::env::args().nth(2).unwrap();
+    let reference_path = std::env::args().nth(3);
+
+    let mut recovery = match reference_path {
+        Some(path) => {
+            let mut reference = AccReader::new(std::fs::File::open(&path).unwrap());
+            Recovery::with_reference(&mut reference).unwrap()
+        }
+        None => Recovery::new(),
+    };
+
+    let mut damaged = AccReader::new(std::fs::File::open(&in_path).unwrap());
+    recovery.scan(&mut damaged).unwrap();
+
+    debug!("recovered {} samples", recovery.samples().len());
+
+    for (index, track) in recovery.tracks().iter().enumerate() {
+        if let Some(err) = track.error() {
+            warn!("track {} is left out: {}", index, err);
+        }
+    }
+
+    let out_file = std::fs::File::create(&out_path).unwrap();
+    recovery
+        .write(&mut damaged, Writer::from_seekable(Box::new(out_file)))
+        .unwrap();
+}
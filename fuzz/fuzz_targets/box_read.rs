@@ -0,0 +1,98 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use av_format::buffer::{AccReader, Buffered};
+use libfuzzer_sys::fuzz_target;
+
+use av_mp4::boxes::codec::*;
+use av_mp4::boxes::*;
+
+type Reader = fn(&mut dyn Buffered) -> bool;
+
+macro_rules! readers {
+    ($($read:expr),* $(,)?) => {
+        [$(|reader: &mut dyn Buffered| $read(reader).is_ok()),*]
+    };
+}
+
+// every box reader, the first input byte picks one
+const READERS: &[Reader] = &readers![
+    avc1::AvcSampleEntryBox::read,
+    avcc::AvcConfigurationBox::read,
+    esds::EsdBox::read,
+    |reader| mp4a::Mpeg4AudioSampleEntryBox::read(reader, 0),
+    |reader| mp4a::Mpeg4AudioSampleEntryBox::read(reader, 1),
+    mp4v::Mpeg4VideoSampleEntryBox::read,
+    stsd::SampleDescriptionBox::read,
+    stsd::UnknownSampleEntryBox::read,
+    tmcd::TimecodeSampleEntryBox::read,
+    vpcc::VpCodecConfigurationBox::read,
+    vpxx::Vp9SampleEntryBox::read,
+    wave::SoundDecompressionParamBox::read,
+    chpl::ChapterListBox::read,
+    co64::ChunkLargeOffsetBox::read,
+    cslg::CompositionToDecodeBox::read,
+    ctts::CompositionOffsetBox::read,
+    dinf::DataInformationBox::read,
+    dref::DataReferenceBox::read,
+    dref::UnknownDataEntryBox::read,
+    edts::EditBox::read,
+    elst::EditListBox::read,
+    ftyp::FileTypeBox::read,
+    gmhd::BaseMediaHeaderBox::read,
+    hdlr::HandlerBox::read,
+    hmhd::HintMediaHeaderBox::read,
+    ilst::DataBox::read,
+    ilst::MetadataItemBox::read,
+    ilst::ItemListBox::read,
+    |reader| ilst::ItemStringBox::read(reader, *b"mean"),
+    mdhd::MediaHeaderBox::read,
+    mdia::MediaBox::read,
+    mehd::MovieExtendsHeaderBox::read,
+    meta::MetaBox::read,
+    mfhd::MovieFragmentHeaderBox::read,
+    minf::MediaInformationBox::read,
+    moof::MovieFragmentBox::read,
+    moov::MovieBox::read,
+    mvex::MovieExtendsBox::read,
+    mvhd::MovieHeaderBox::read,
+    nmhd::NullMediaHeaderBox::read,
+    sbgp::SampleToGroupBox::read,
+    sdtp::SampleDependencyTypeBox::read,
+    sgpd::SampleGroupDescriptionBox::read,
+    smhd::SoundMediaHeaderBox::read,
+    stbl::SampleTableBox::read,
+    stco::ChunkOffsetBox::read,
+    sthd::SubtitleMediaHeaderBox::read,
+    stsc::SampleToChunkBox::read,
+    stss::SyncSampleBox::read,
+    stsz::SampleSizeBox::read,
+    stts::TimeToSampleBox::read,
+    subs::SubSampleInformationBox::read,
+    tfdt::TrackFragmentBaseMediaDecodeTimeBox::read,
+    tfhd::TrackFragmentHeaderBox::read,
+    tkhd::TrackHeaderBox::read,
+    traf::TrackFragmentBox::read,
+    trak::TrackBox::read,
+    tref::TrackReferenceBox::read,
+    tref::TrackReferenceTypeBox::read,
+    trex::TrackExtendsBox::read,
+    trun::TrackFragmentRunBox::read,
+    udta::UserDataBox::read,
+    url::DataEntryUrlBox::read,
+    urn::DataEntryUrnBox::read,
+    vmhd::VideoMediaHeaderBox::read,
+];
+
+fuzz_target!(|data: &[u8]| {
+    let (&selector, input) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+
+    let read = READERS[selector as usize % READERS.len()];
+
+    let mut reader = AccReader::new(Cursor::new(input));
+    read(&mut reader);
+});
@@ -0,0 +1,41 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use av_format::buffer::AccReader;
+use av_format::demuxer::{Context as DemuxerCtx, Event};
+use libfuzzer_sys::fuzz_target;
+
+use av_mp4::demuxer::{Mp4Demuxer, ReadStrategy, MP4_DESC};
+
+// constant sample sizes let tiny files declare billions of samples
+const MAX_EVENTS: usize = 10_000;
+
+fuzz_target!(|data: &[u8]| {
+    let (&options, input) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+
+    MP4_DESC.probe(input);
+
+    let mut demuxer = Mp4Demuxer::new();
+    demuxer.set_streaming(options & 1 != 0);
+    if options & 2 != 0 {
+        demuxer.set_read_strategy(ReadStrategy::FileOrder { reorder_window: 4 });
+    }
+
+    let reader = AccReader::new(Cursor::new(input.to_vec()));
+    let mut context = DemuxerCtx::new(Box::new(demuxer), Box::new(reader));
+
+    if context.read_headers().is_err() {
+        return;
+    }
+
+    for _ in 0..MAX_EVENTS {
+        match context.read_event() {
+            Ok(Event::NewPacket(_)) | Ok(Event::Continue) => {}
+            _ => break,
+        }
+    }
+});
@@ -0,0 +1,24 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use av_format::buffer::AccReader;
+use libfuzzer_sys::fuzz_target;
+
+use av_mp4::tree::BoxTree;
+
+// whatever reads as a box tree has to be written back unchanged
+fuzz_target!(|data: &[u8]| {
+    let mut reader = AccReader::new(Cursor::new(data));
+
+    let tree = match BoxTree::read(&mut reader) {
+        Ok(tree) => tree,
+        Err(_) => return,
+    };
+
+    let mut written = Vec::new();
+    tree.write(&mut written).unwrap();
+
+    assert_eq!(tree.total_size(), written.len() as u64);
+    assert_eq!(data, &written[..]);
+});
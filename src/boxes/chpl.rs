@@ -0,0 +1,144 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::*;
+
+use std::io::Write;
+use std::mem::size_of;
+
+pub struct ChapterEntry {
+    /// Start of the chapter in units of 100 nanoseconds.
+    pub start: u64,
+    pub title: String,
+}
+
+/// Nero chapter list.
+pub struct ChapterListBox {
+    full_box: FullBox,
+    pub chapters: Vec<ChapterEntry>,
+}
+
+impl ChapterListBox {
+    pub fn new(chapters: Vec<ChapterEntry>) -> Self {
+        ChapterListBox {
+            full_box: FullBox::new(*b"chpl", 1, 0),
+            chapters,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"chpl")?;
+
+        if full_box.version == 1 {
+            reader.read_u32::<BigEndian>()?;
+        }
+
+        let count = reader.read_u8()?;
+        let mut chapters = Vec::with_capacity(count as usize);
+
+        for _ in 0..count {
+            let start = reader.read_u64::<BigEndian>()?;
+
+            let mut title = vec![0u8; reader.read_u8()? as usize];
+            reader.read_exact(&mut title)?;
+
+            chapters.push(ChapterEntry {
+                start,
+                title: String::from_utf8_lossy(&title).into_owned(),
+            });
+        }
+
+        Ok(ChapterListBox { full_box, chapters })
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.full_box.write(writer, self.total_size())?;
+
+        if self.full_box.version == 1 {
+            writer.write_u32::<BigEndian>(0)?;
+        }
+        writer.write_u8(self.chapters.len().min(u8::MAX as usize) as u8)?;
+
+        for chapter in self.chapters.into_iter().take(u8::MAX as usize) {
+            let title = truncate(&chapter.title, u8::MAX as usize);
+
+            writer.write_u64::<BigEndian>(chapter.start)?;
+            writer.write_u8(title.len() as u8)?;
+            writer.write_all(title.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.full_box.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        let mut size = size_of::<u8>() as u64; // chapter_count
+
+        if self.full_box.version == 1 {
+            size += size_of::<u32>() as u64; // reserved
+        }
+
+        for chapter in self.chapters.iter().take(u8::MAX as usize) {
+            size += size_of::<u64>() as u64 + // start
+                size_of::<u8>() as u64 + // title_length
+                truncate(&chapter.title, u8::MAX as usize).len() as u64;
+        }
+
+        size
+    }
+}
+
+// titles are limited to 255 bytes, cut at a character boundary
+fn truncate(title: &str, len: usize) -> &str {
+    let mut end = title.len().min(len);
+
+    while !title.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    &title[..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{read_test_box, round_trip, test_box};
+
+    fn chapters(contents: &[u8]) -> Vec<u8> {
+        let mut data = contents.to_vec();
+        data.push(1);
+        data.extend(&10_000_000u64.to_be_bytes());
+        data.push(5);
+        data.extend(b"Intro");
+        data
+    }
+
+    #[test]
+    fn version_0() {
+        let data = test_box(b"chpl", &chapters(&[0, 0, 0, 0]));
+        let chpl = read_test_box::<ChapterListBox>(&data).unwrap();
+
+        assert_eq!(chpl.chapters[0].start, 10_000_000);
+        assert_eq!(chpl.chapters[0].title, "Intro");
+
+        // without the reserved field
+        let (written, _) = round_trip(chpl);
+        assert_eq!(written, data);
+    }
+
+    #[test]
+    fn version_1() {
+        let data = test_box(b"chpl", &chapters(&[1, 0, 0, 0, 0, 0, 0, 0]));
+
+        let (written, chpl) = round_trip(ChapterListBox::new(vec![ChapterEntry {
+            start: 10_000_000,
+            title: "Intro".into(),
+        }]));
+
+        assert_eq!(written, data);
+        assert_eq!(chpl.chapters[0].title, "Intro");
+    }
+}
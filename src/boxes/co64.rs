@@ -21,9 +21,14 @@ impl ChunkLargeOffsetBox {
     pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
         let full_box = FullBox::read_named(reader, *b"co64")?;
 
-        let count = reader.read_u32::<BigEndian>()?;
-
-        let mut chunk_offsets = Vec::new();
+        let count = entry_count(
+            reader.read_u32::<BigEndian>()?,
+            8,
+            full_box.remaining_size().saturating_sub(4),
+            *b"co64",
+        )?;
+
+        let mut chunk_offsets = Vec::with_capacity(count);
 
         for _ in 0..count {
             let offset = reader.read_u64::<BigEndian>()?;
@@ -51,6 +51,8 @@ pub struct AvcDecoderConfigurationRecord {
     pub profile_indication: u8,
     pub profile_compatibility: u8,
     pub level_indication: u8,
+    /// Size of the NAL unit length prefixes in the samples, minus one.
+    pub length_size_minus_one: u8,
     pub sequence_parameter_sets: Vec<SequenceParameterSet>,
     pub picture_parameter_sets: Vec<PictureParameterSet>,
 }
@@ -60,7 +62,7 @@ impl AvcDecoderConfigurationRecord {
         let mut header = [0u8; 6];
         buf.read_exact(&mut header)?;
 
-        let _length_minus_one = header[4];
+        let length_size_minus_one = header[4] & 0b11;
         let sps_count = header[5] & 0b0001_1111;
         debug!("sps_count: {:08b}", sps_count);
 
@@ -94,6 +96,7 @@ impl AvcDecoderConfigurationRecord {
             profile_indication: header[1],
             profile_compatibility: header[2],
             level_indication: header[3],
+            length_size_minus_one,
             sequence_parameter_sets,
             picture_parameter_sets,
         })
@@ -106,11 +109,9 @@ impl AvcDecoderConfigurationRecord {
             + size_of::<u8>() as u64 // AVCLevelIndication
             + size_of::<u8>() as u64 // lengthSizeMinusOne
             + size_of::<u8>() as u64 // numOfSequenceParameterSets
-            + size_of::<u16>() as u64 // sequenceParameterSetLength
-            + self.sequence_parameter_sets.iter().map(|sps| sps.0.len() as u64).sum::<u64>()
+            + self.sequence_parameter_sets.iter().map(|sps| 2 + sps.0.len() as u64).sum::<u64>()
             + size_of::<u8>() as u64 // numOfPictureParameterSets
-            + size_of::<u16>() as u64 // pictureParameterSetLength
-            + self.picture_parameter_sets.iter().map(|pps| pps.0.len() as u64).sum::<u64>()
+            + self.picture_parameter_sets.iter().map(|pps| 2 + pps.0.len() as u64).sum::<u64>()
     }
 
     fn write(&self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
@@ -119,19 +120,19 @@ impl AvcDecoderConfigurationRecord {
             self.profile_indication,
             self.profile_compatibility,
             self.level_indication,
-            0b1111_1100 | 3,
-            0b1110_0000 | 1,
+            0b1111_1100 | self.length_size_minus_one,
+            0b1110_0000 | self.sequence_parameter_sets.len() as u8,
         ];
 
         writer.write_all(&header)?;
-        writer.write_u16::<BigEndian>(self.sequence_parameter_sets.len() as u16)?;
         for sps in &self.sequence_parameter_sets {
+            writer.write_u16::<BigEndian>(sps.0.len() as u16)?;
             writer.write_all(&sps.0)?;
         }
 
-        writer.write_u8(1)?;
-        writer.write_u16::<BigEndian>(self.picture_parameter_sets.len() as u16)?;
+        writer.write_u8(self.picture_parameter_sets.len() as u8)?;
         for pps in &self.picture_parameter_sets {
+            writer.write_u16::<BigEndian>(pps.0.len() as u16)?;
             writer.write_all(&pps.0)?;
         }
 
@@ -8,11 +8,21 @@ use std::mem::size_of;
 const ES_DESCR_TAG: u8 = 0x3;
 const DECODER_CONFIG_DESCR_TAG: u8 = 0x4;
 const DECODER_SPECIFIC_DESCR_TAG: u8 = 0x5;
+const SL_CONFIG_DESCR_TAG: u8 = 0x6;
+
+// predefined SL packet header configuration required in MP4 files
+const SL_PREDEFINED_MP4: u8 = 0x2;
+
+const STREAM_DEPENDENCE_FLAG: u8 = 0b1000_0000;
+const URL_FLAG: u8 = 0b0100_0000;
+const OCR_STREAM_FLAG: u8 = 0b0010_0000;
 
 #[derive(Debug)]
 pub struct DecoderConfigDescriptor {
     pub descriptor: Descriptor,
     pub object_type_indication: u8,
+    /// The stream type, upstream flag and reserved bit as stored.
+    pub stream_type: u8,
 
     pub buffer_size_db: u32,
     pub max_bitrate: u32,
@@ -22,11 +32,42 @@ pub struct DecoderConfigDescriptor {
 }
 
 impl DecoderConfigDescriptor {
+    pub fn new(object_type_indication: u8, stream_type: u8, decoder_specific: Vec<u8>) -> Self {
+        DecoderConfigDescriptor {
+            descriptor: Descriptor::new(DECODER_CONFIG_DESCR_TAG),
+            object_type_indication,
+            stream_type,
+            buffer_size_db: 0,
+            max_bitrate: 0,
+            avg_bitrate: 0,
+            decoder_specific,
+        }
+    }
+
+    pub fn write(&self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.descriptor.write(writer, self.size())?;
+
+        writer.write_u8(self.object_type_indication)?;
+        writer.write_u8(self.stream_type)?;
+        writer.write_u24::<BigEndian>(self.buffer_size_db)?;
+        writer.write_u32::<BigEndian>(self.max_bitrate)?;
+        writer.write_u32::<BigEndian>(self.avg_bitrate)?;
+
+        if !self.decoder_specific.is_empty() {
+            let size = self.decoder_specific.len() as u64;
+
+            Descriptor::new(DECODER_SPECIFIC_DESCR_TAG).write(writer, size)?;
+            writer.write_all(&self.decoder_specific)?;
+        }
+
+        Ok(())
+    }
+
     pub fn read(buf: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
         let descriptor = Descriptor::read(buf, DECODER_CONFIG_DESCR_TAG)?;
 
         let object_type_indication = buf.read_u8()?;
-        let _ = buf.read_u8()?;
+        let stream_type = buf.read_u8()?;
         let buffer_size_db = buf.read_u24::<BigEndian>()?;
         let max_bitrate = buf.read_u32::<BigEndian>()?;
         let avg_bitrate = buf.read_u32::<BigEndian>()?;
@@ -35,13 +76,13 @@ impl DecoderConfigDescriptor {
 
         // TODO: maybe parse to supported descriptor directly, instead of storing bytes
         if let Ok(desc) = Descriptor::read(buf, DECODER_SPECIFIC_DESCR_TAG) {
-            decoder_specific.resize(desc.remaining_size() as usize, 0);
-            buf.read_exact(&mut decoder_specific[..])?;
+            decoder_specific = read_bytes(buf, desc.remaining_size())?;
         }
 
         Ok(DecoderConfigDescriptor {
             descriptor,
             object_type_indication,
+            stream_type,
             buffer_size_db,
             max_bitrate,
             avg_bitrate,
@@ -54,35 +95,46 @@ impl DecoderConfigDescriptor {
     }
 
     fn size(&self) -> u64 {
-        size_of::<u8> as u64 +
-        size_of::<u8> as u64 +
-        size_of::<u8> as u64 * 3 +
-        size_of::<u32> as u64 +
-        size_of::<u32> as u64
-    }
-}
-
-fn size_of_length(size: u32) -> u32 {
-    match size {
-        0x0..=0x7F => 1,
-        0x80..=0x3FFF => 2,
-        0x4000..=0x1FFFFF => 3,
-        _ => 4,
+        let decoder_specific = match self.decoder_specific.len() as u64 {
+            0 => 0,
+            size => Descriptor::new(DECODER_SPECIFIC_DESCR_TAG).size(size),
+        };
+
+        size_of::<u8>() as u64 + // object_type_indication
+        size_of::<u8>() as u64 + // stream_type
+        size_of::<u8>() as u64 * 3 + // buffer_size_db
+        size_of::<u32>() as u64 + // max_bitrate
+        size_of::<u32>() as u64 + // avg_bitrate
+        decoder_specific
     }
 }
 
 #[derive(Debug)]
 pub struct Descriptor {
     pub tag: u8,
+    /// Size of the payload following the tag and length.
     pub size: u32,
-    read_size: u8,
 }
 
 impl Descriptor {
+    pub fn new(tag: u8) -> Self {
+        Descriptor { tag, size: 0 }
+    }
+
+    /// Writes the tag and the length of a payload of `size` bytes, which is
+    /// always stored in 4 bytes.
     pub fn write(&self, writer: &mut dyn Write, size: u64) -> Result<(), Mp4BoxError> {
+        let size = size.min(0x0FFF_FFFF) as u32;
+
         writer.write_u8(self.tag)?;
+        writer.write_all(&[
+            0x80 | ((size >> 21) as u8 & 0x7F),
+            0x80 | ((size >> 14) as u8 & 0x7F),
+            0x80 | ((size >> 7) as u8 & 0x7F),
+            size as u8 & 0x7F,
+        ])?;
 
-        todo!();
+        Ok(())
     }
 
     pub fn peek(buf: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
@@ -91,7 +143,7 @@ impl Descriptor {
 
         let mut size = 0u32;
         for i in 0..4 {
-            let bytes = peek(buf, 1 + i)?;
+            let bytes = peek(buf, 2 + i)?;
             let b = bytes[1 + i];
 
             size = (size << 7) | (b & 0b0111_1111) as u32;
@@ -101,11 +153,7 @@ impl Descriptor {
             }
         }
 
-        Ok(Descriptor {
-            tag,
-            size,
-            read_size: 0,
-        })
+        Ok(Descriptor { tag, size })
     }
 
     pub fn read(buf: &mut dyn Buffered, expected: u8) -> Result<Self, Mp4BoxError> {
@@ -115,11 +163,9 @@ impl Descriptor {
             return Err(Mp4BoxError::UnexpectedTag(expected, tag));
         }
 
-        let mut len = 1;
         let mut size = 0u32;
         for _ in 0..4 {
             let b = buf.read_u8()?;
-            len += 1;
 
             size = (size << 7) | (b & 0b0111_1111) as u32;
 
@@ -128,20 +174,19 @@ impl Descriptor {
             }
         }
 
-        Ok(Descriptor {
-            tag,
-            size,
-            read_size: len,
-        })
+        Ok(Descriptor { tag, size })
     }
 
+    // the length field excludes the tag and itself
     pub fn remaining_size(&self) -> u64 {
-        self.size as u64 - self.read_size as u64
+        self.size as u64
     }
 
+    /// The size of the descriptor with a payload of `size` bytes.
     pub fn size(&self, size: u64) -> u64 {
-        size_of::<u8> as u64 +
-        size_of_length(size as u32) as u64
+        size_of::<u8>() as u64 + // tag
+        size_of::<u32>() as u64 + // length
+        size
     }
 }
 
@@ -149,12 +194,53 @@ impl Descriptor {
 pub struct EsDescriptor {
     pub descriptor: Descriptor,
     pub es_id: u16,
+    /// The stream dependence, URL and OCR stream flags and the stream
+    /// priority.
+    pub flags: u8,
+    pub depends_on_es_id: u16,
+    pub url: Vec<u8>,
+    pub ocr_es_id: u16,
     pub decoder_description: DecoderConfigDescriptor,
 }
 
 impl EsDescriptor {
+    pub fn new(es_id: u16, decoder_description: DecoderConfigDescriptor) -> Self {
+        EsDescriptor {
+            descriptor: Descriptor::new(ES_DESCR_TAG),
+            es_id,
+            flags: 0,
+            depends_on_es_id: 0,
+            url: Vec::new(),
+            ocr_es_id: 0,
+            decoder_description,
+        }
+    }
+
     pub fn write(&self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
-        self.descriptor.write(writer, self.total_size())?;
+        self.descriptor.write(writer, self.size())?;
+
+        writer.write_u16::<BigEndian>(self.es_id)?;
+        writer.write_u8(self.flags)?;
+
+        if self.flags & STREAM_DEPENDENCE_FLAG != 0 {
+            writer.write_u16::<BigEndian>(self.depends_on_es_id)?;
+        }
+
+        if self.flags & URL_FLAG != 0 {
+            let url = self.url();
+
+            writer.write_u8(url.len() as u8)?;
+            writer.write_all(url)?;
+        }
+
+        if self.flags & OCR_STREAM_FLAG != 0 {
+            writer.write_u16::<BigEndian>(self.ocr_es_id)?;
+        }
+
+        self.decoder_description.write(writer)?;
+
+        Descriptor::new(SL_CONFIG_DESCR_TAG).write(writer, 1)?;
+        writer.write_u8(SL_PREDEFINED_MP4)?;
 
         Ok(())
     }
@@ -164,17 +250,21 @@ impl EsDescriptor {
         let es_id = buf.read_u16::<BigEndian>()?;
         let flags = buf.read_u8()?;
 
-        if (flags & 0b0000_0001) != 0 {
-            skip(buf, 2)?;
+        let mut depends_on_es_id = 0;
+        let mut url = Vec::new();
+        let mut ocr_es_id = 0;
+
+        if flags & STREAM_DEPENDENCE_FLAG != 0 {
+            depends_on_es_id = buf.read_u16::<BigEndian>()?;
         }
 
-        if (flags & 0b0000_0010) != 0 {
+        if flags & URL_FLAG != 0 {
             let len = buf.read_u8()?;
-            skip(buf, len as _)?;
+            url = read_bytes(buf, len as u64)?;
         }
 
-        if (flags & 0b0000_0100) != 0 {
-            skip(buf, 2)?;
+        if flags & OCR_STREAM_FLAG != 0 {
+            ocr_es_id = buf.read_u16::<BigEndian>()?;
         }
 
         let decoder_description = DecoderConfigDescriptor::read(buf)?;
@@ -182,18 +272,41 @@ impl EsDescriptor {
         Ok(EsDescriptor {
             descriptor,
             es_id,
+            flags,
+            depends_on_es_id,
+            url,
+            ocr_es_id,
             decoder_description,
         })
     }
 
+    // the URL length is stored in a byte
+    fn url(&self) -> &[u8] {
+        &self.url[..self.url.len().min(u8::MAX as usize)]
+    }
+
     pub fn total_size(&self) -> u64 {
         self.descriptor.size(self.size())
     }
 
     fn size(&self) -> u64 {
-        size_of::<u16> as u64 +
-        size_of::<u8> as u64 +
-        self.decoder_description.total_size()
+        let mut size = size_of::<u16>() as u64 + // es_id
+            size_of::<u8>() as u64; // flags
+
+        if self.flags & STREAM_DEPENDENCE_FLAG != 0 {
+            size += size_of::<u16>() as u64;
+        }
+
+        if self.flags & URL_FLAG != 0 {
+            size += size_of::<u8>() as u64 + self.url().len() as u64;
+        }
+
+        if self.flags & OCR_STREAM_FLAG != 0 {
+            size += size_of::<u16>() as u64;
+        }
+
+        size + self.decoder_description.total_size()
+            + Descriptor::new(SL_CONFIG_DESCR_TAG).size(size_of::<u8>() as u64)
     }
 }
 
@@ -238,6 +351,80 @@ impl EsdBox {
     }
 
     fn size(&self) -> u64 {
-        self.descriptor.size()
+        self.descriptor.total_size()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{read_test_box, round_trip, test_box};
+
+    #[test]
+    fn optional_fields() {
+        let config = DecoderConfigDescriptor::new(0x40, 0x15, vec![0x12, 0x10]);
+        let mut descriptor = EsDescriptor::new(2, config);
+        descriptor.flags = STREAM_DEPENDENCE_FLAG | URL_FLAG | OCR_STREAM_FLAG;
+        descriptor.depends_on_es_id = 3;
+        descriptor.url = b"x".to_vec();
+        descriptor.ocr_es_id = 4;
+
+        let (_, esds) = round_trip(EsdBox::new(descriptor));
+        let descriptor = &esds.descriptor;
+
+        assert_eq!(descriptor.es_id, 2);
+        assert_eq!(descriptor.depends_on_es_id, 3);
+        assert_eq!(descriptor.url, b"x");
+        assert_eq!(descriptor.ocr_es_id, 4);
+        assert_eq!(descriptor.decoder_description.object_type_indication, 0x40);
+        assert_eq!(descriptor.decoder_description.stream_type, 0x15);
+        assert_eq!(
+            descriptor.decoder_description.decoder_specific,
+            [0x12, 0x10]
+        );
+    }
+
+    #[test]
+    fn single_byte_lengths() {
+        let bytes = test_box(
+            b"esds",
+            &[
+                0, 0, 0, 0, // version and flags
+                3, 25, 0, 1, 0, // ES descriptor
+                4, 17, 0x40, 0x15, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, // decoder config
+                5, 2, 0x12, 0x10, // decoder specific info
+                6, 1, 2, // SL config
+            ],
+        );
+        let esds = read_test_box::<EsdBox>(&bytes).unwrap();
+        let config = &esds.descriptor.decoder_description;
+
+        assert_eq!(esds.descriptor.es_id, 1);
+        assert_eq!(config.object_type_indication, 0x40);
+        assert_eq!(config.decoder_specific, [0x12, 0x10]);
+    }
+
+    #[test]
+    fn truncated() {
+        let config = DecoderConfigDescriptor::new(0x40, 0x15, Vec::new());
+        let mut bytes = Vec::new();
+        EsdBox::new(EsDescriptor::new(1, config))
+            .write(&mut bytes)
+            .unwrap();
+
+        for len in 12..bytes.len() - 8 {
+            assert!(read_test_box::<EsdBox>(&bytes[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn unexpected_tag() {
+        let bytes = test_box(b"esds", &[0, 0, 0, 0, 4, 1, 0]);
+
+        assert!(matches!(
+            read_test_box::<EsdBox>(&bytes),
+            Err(Mp4BoxError::UnexpectedTag(ES_DESCR_TAG, 4))
+        ));
     }
 }
@@ -0,0 +1,85 @@
+use crate::*;
+
+use super::esds::EsdBox;
+use super::wave::SoundDecompressionParamBox;
+
+use std::io::Write;
+
+#[derive(Debug)]
+pub struct Mpeg4AudioSampleEntryBox {
+    pub audio_sample_entry: AudioSampleEntry,
+    pub esds: Option<EsdBox>,
+    /// QuickTime files nest `esds` in here.
+    pub wave: Option<SoundDecompressionParamBox>,
+}
+
+impl Mpeg4AudioSampleEntryBox {
+    pub fn new(channel_count: u16, sample_rate: u32, esds: EsdBox) -> Self {
+        Mpeg4AudioSampleEntryBox {
+            audio_sample_entry: AudioSampleEntry::new(
+                *b"mp4a",
+                1,
+                channel_count,
+                16,
+                sample_rate << 16,
+            ),
+            esds: Some(esds),
+            wave: None,
+        }
+    }
+
+    pub fn esds(&self) -> Option<&EsdBox> {
+        self.esds
+            .as_ref()
+            .or_else(|| self.wave.as_ref()?.esds.as_ref())
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.audio_sample_entry.write(writer, self.total_size())?;
+
+        if let Some(esds) = self.esds {
+            esds.write(writer)?;
+        }
+        if let Some(wave) = self.wave {
+            wave.write(writer)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.audio_sample_entry.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        self.esds.as_ref().map(|e| e.total_size()).unwrap_or(0)
+            + self.wave.as_ref().map(|w| w.total_size()).unwrap_or(0)
+    }
+
+    pub fn read(buf: &mut dyn Buffered, stsd_version: u8) -> Result<Self, Mp4BoxError> {
+        let audio_sample_entry = AudioSampleEntry::read(buf, stsd_version)?;
+
+        let mut esds = None;
+        let mut wave = None;
+
+        let iter = BoksIterator::new(buf, audio_sample_entry.remaining_size());
+        while let Some((pos, boks)) = iter.next(buf)? {
+            debug!("{}: {:?}", pos, boks);
+
+            match &boks.name {
+                b"esds" => esds = Some(EsdBox::read(buf).context(pos, &boks)?),
+                b"wave" => wave = Some(SoundDecompressionParamBox::read(buf).context(pos, &boks)?),
+                _ => {
+                    diagnostic!(at pos, "skipping mp4a box {:?}", boks);
+                    skip(buf, boks.size)?;
+                }
+            }
+        }
+
+        Ok(Mpeg4AudioSampleEntryBox {
+            audio_sample_entry,
+            esds,
+            wave,
+        })
+    }
+}
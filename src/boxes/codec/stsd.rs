@@ -2,7 +2,7 @@ use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::*;
 
-use super::{avc1, vpxx, mp4v};
+use super::{avc1, mp4a, mp4v, tmcd, vpxx};
 
 use std::io::Write;
 use std::mem::size_of;
@@ -11,18 +11,66 @@ pub enum SampleEntry {
     Avc(avc1::AvcSampleEntryBox),
     Vp9(vpxx::Vp9SampleEntryBox),
     Mpeg4(mp4v::Mpeg4VideoSampleEntryBox),
+    Mpeg4Audio(mp4a::Mpeg4AudioSampleEntryBox),
+    Timecode(tmcd::TimecodeSampleEntryBox),
+    Unknown(UnknownSampleEntryBox),
 }
 
 impl SampleEntry {
+    pub fn data_reference_index(&self) -> u16 {
+        let sample_entry = match self {
+            SampleEntry::Avc(avc1) => &avc1.visual_sample_entry.sample_entry,
+            SampleEntry::Vp9(vp9) => &vp9.visual_sample_entry.sample_entry,
+            SampleEntry::Mpeg4(mp4v) => &mp4v.visual_sample_entry.sample_entry,
+            SampleEntry::Mpeg4Audio(mp4a) => &mp4a.audio_sample_entry.sample_entry,
+            SampleEntry::Timecode(tmcd) => &tmcd.sample_entry,
+            SampleEntry::Unknown(entry) => &entry.sample_entry,
+        };
+
+        sample_entry.data_reference_index()
+    }
+
     fn size(&self) -> u64 {
         match self {
             SampleEntry::Avc(avc1) => avc1.total_size(),
             SampleEntry::Vp9(vp9) => vp9.total_size(),
             SampleEntry::Mpeg4(mp4v) => mp4v.total_size(),
+            SampleEntry::Mpeg4Audio(mp4a) => mp4a.total_size(),
+            SampleEntry::Timecode(tmcd) => tmcd.total_size(),
+            SampleEntry::Unknown(entry) => entry.total_size(),
         }
     }
 }
 
+/// Sample entry of a format without dedicated support, e.g. for text or
+/// timed metadata. Everything after the common fields is kept as is.
+pub struct UnknownSampleEntryBox {
+    pub sample_entry: crate::SampleEntry,
+    pub data: Vec<u8>,
+}
+
+impl UnknownSampleEntryBox {
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let sample_entry = crate::SampleEntry::read(reader)?;
+
+        let data = read_bytes(reader, sample_entry.remaining_size())?;
+
+        Ok(UnknownSampleEntryBox { sample_entry, data })
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.sample_entry.write(writer, self.total_size())?;
+
+        writer.write_all(&self.data)?;
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.sample_entry.size(self.data.len() as u64)
+    }
+}
+
 pub struct SampleDescriptionBox {
     full_box: FullBox,
     pub entries: Vec<SampleEntry>,
@@ -43,26 +91,42 @@ impl SampleDescriptionBox {
 
         let mut entries = Vec::new();
 
-        let iter = BoksIterator::new(reader, full_box.remaining_size());
-        while let Some((pos, boks)) = iter.next(reader) {
+        let iter = BoksIterator::new(reader, full_box.remaining_size().saturating_sub(4));
+        while let Some((pos, boks)) = iter.next(reader)? {
             if count == 0 {
+                deviation!(at pos, "{:?} exceeds the sample entry count", boks)?;
                 break;
             }
 
             debug!("{}: {:?}", pos, boks);
 
-            match &boks.name {
-                b"avc1" => entries.push(SampleEntry::Avc(avc1::AvcSampleEntryBox::read(reader)?)),
-                b"vp09" => entries.push(SampleEntry::Vp9(vpxx::Vp9SampleEntryBox::read(reader)?)),
-                b"mp4v" => entries.push(SampleEntry::Mpeg4(mp4v::Mpeg4VideoSampleEntryBox::read(reader)?)),
+            let entry = match &boks.name {
+                b"avc1" => avc1::AvcSampleEntryBox::read(reader).map(SampleEntry::Avc),
+                b"vp09" => vpxx::Vp9SampleEntryBox::read(reader).map(SampleEntry::Vp9),
+                b"mp4v" => mp4v::Mpeg4VideoSampleEntryBox::read(reader).map(SampleEntry::Mpeg4),
+                b"mp4a" => mp4a::Mpeg4AudioSampleEntryBox::read(reader, full_box.version)
+                    .map(SampleEntry::Mpeg4Audio),
+                b"tmcd" => tmcd::TimecodeSampleEntryBox::read(reader).map(SampleEntry::Timecode),
                 _ => {
-                    return Err(Mp4BoxError::UnsupportedSampleEntry(BoxPrint(boks.name)));
+                    debug!("keeping unsupported sample entry {:?}", boks);
+                    UnknownSampleEntryBox::read(reader).map(SampleEntry::Unknown)
                 }
-            }
+            };
+
+            entries.push(entry.nth_context(pos, &boks, entries.len())?);
+
+            // entries may have trailing boxes that are not parsed
+            goto(reader, pos + boks.size)?;
 
             count -= 1;
         }
 
+        if count > 0 {
+            deviation!("{} sample entries are missing", count)?;
+        } else if entries.is_empty() {
+            deviation!("no sample entries")?;
+        }
+
         Ok(SampleDescriptionBox { full_box, entries })
     }
 
@@ -76,6 +140,9 @@ impl SampleDescriptionBox {
                 SampleEntry::Avc(avc1) => avc1.write(writer)?,
                 SampleEntry::Vp9(vp9) => vp9.write(writer)?,
                 SampleEntry::Mpeg4(mp4v) => mp4v.write(writer)?,
+                SampleEntry::Mpeg4Audio(mp4a) => mp4a.write(writer)?,
+                SampleEntry::Timecode(tmcd) => tmcd.write(writer)?,
+                SampleEntry::Unknown(entry) => entry.write(writer)?,
             }
         }
 
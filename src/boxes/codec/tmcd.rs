@@ -0,0 +1,143 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::*;
+
+use std::io::Write;
+use std::mem::size_of;
+
+bitflags::bitflags! {
+    pub struct TimecodeFlags: u32 {
+        const DROP_FRAME = 0x0001;
+        const MAX_24_HOUR = 0x0002;
+        const NEGATIVE_TIMES_OK = 0x0004;
+        /// Samples count in units of the frame duration instead of frames.
+        const COUNTER = 0x0008;
+    }
+}
+
+// mac language code of English, used for the source reel name
+const NAME_LANGUAGE: u16 = 0;
+
+/// QuickTime timecode sample entry, its samples are 32-bit frame numbers.
+pub struct TimecodeSampleEntryBox {
+    pub sample_entry: crate::SampleEntry,
+    pub flags: TimecodeFlags,
+    pub timescale: u32,
+    pub frame_duration: u32,
+    /// Frames per second, rounded up for fractional rates.
+    pub number_of_frames: u8,
+    /// Source reel name from the `name` box.
+    pub name: Option<String>,
+}
+
+impl TimecodeSampleEntryBox {
+    pub fn new(
+        flags: TimecodeFlags,
+        timescale: u32,
+        frame_duration: u32,
+        number_of_frames: u8,
+    ) -> Self {
+        TimecodeSampleEntryBox {
+            sample_entry: crate::SampleEntry::new(*b"tmcd", 1),
+            flags,
+            timescale,
+            frame_duration,
+            number_of_frames,
+            name: None,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let sample_entry = crate::SampleEntry::read(reader)?;
+
+        // reserved
+        reader.read_u32::<BigEndian>()?;
+        let flags = TimecodeFlags::from_bits_truncate(reader.read_u32::<BigEndian>()?);
+        let timescale = reader.read_u32::<BigEndian>()?;
+        let frame_duration = reader.read_u32::<BigEndian>()?;
+        let number_of_frames = reader.read_u8()?;
+        // reserved
+        reader.read_u8()?;
+
+        let mut name = None;
+
+        let children_size = sample_entry
+            .remaining_size()
+            .saturating_sub(Self::fields_size());
+
+        let iter = BoksIterator::new(reader, children_size);
+        while let Some((pos, boks)) = iter.next(reader)? {
+            debug!("{}: {:?}", pos, boks);
+
+            match &boks.name {
+                b"name" => {
+                    let boks = Boks::read_named(reader, *b"name")?;
+
+                    let length = reader.read_u16::<BigEndian>()? as u64;
+                    let _language = reader.read_u16::<BigEndian>()?;
+
+                    let length = length.min(boks.remaining_size().saturating_sub(4));
+                    let bytes = read_bytes(reader, length)?;
+
+                    skip(reader, boks.remaining_size().saturating_sub(4 + length))?;
+
+                    name = Some(String::from_utf8_lossy(&bytes).into_owned());
+                }
+                _ => {
+                    diagnostic!(at pos, "skipping tmcd box {:?}", boks);
+                    skip(reader, boks.size)?;
+                }
+            }
+        }
+
+        Ok(TimecodeSampleEntryBox {
+            sample_entry,
+            flags,
+            timescale,
+            frame_duration,
+            number_of_frames,
+            name,
+        })
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.sample_entry.write(writer, self.total_size())?;
+
+        writer.write_u32::<BigEndian>(0)?;
+        writer.write_u32::<BigEndian>(self.flags.bits())?;
+        writer.write_u32::<BigEndian>(self.timescale)?;
+        writer.write_u32::<BigEndian>(self.frame_duration)?;
+        writer.write_u8(self.number_of_frames)?;
+        writer.write_u8(0)?;
+
+        if let Some(name) = &self.name {
+            Boks::new(*b"name").write(writer, Self::name_size(name))?;
+
+            writer.write_u16::<BigEndian>(name.len() as u16)?;
+            writer.write_u16::<BigEndian>(NAME_LANGUAGE)?;
+            writer.write_all(name.as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn fields_size() -> u64 {
+        size_of::<u32>() as u64 * 4 + 2
+    }
+
+    fn name_size(name: &str) -> u64 {
+        8 + size_of::<u16>() as u64 * 2 + name.len() as u64
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.sample_entry.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        Self::fields_size() + self.name.as_deref().map_or(0, Self::name_size)
+    }
+
+    pub fn is_drop_frame(&self) -> bool {
+        self.flags.contains(TimecodeFlags::DROP_FRAME)
+    }
+}
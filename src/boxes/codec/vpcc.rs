@@ -69,10 +69,9 @@ impl VpCodecConfigurationRecord {
         let transfer_characteristics = header[4];
         let matrix_coefficients = header[5];
 
+        // unused by VP8 and VP9, which is why it is not kept
         let initialization_len = buf.read_u16::<BigEndian>()?;
-        if initialization_len > 0 {
-            panic!("err");
-        }
+        skip(buf, initialization_len as u64)?;
 
         Ok(VpCodecConfigurationRecord {
             profile,
@@ -0,0 +1,86 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::*;
+
+use super::esds::EsdBox;
+
+use std::io::Write;
+use std::mem::size_of;
+
+/// QuickTime decompression parameters of sound sample entries, which wrap
+/// the decoder configuration, e.g. `esds` for AAC.
+#[derive(Debug)]
+pub struct SoundDecompressionParamBox {
+    boks: Boks,
+    /// Original format from `frma`, e.g. `mp4a`.
+    pub format: Option<BoxName>,
+    pub esds: Option<EsdBox>,
+}
+
+impl SoundDecompressionParamBox {
+    pub fn new(format: Option<BoxName>, esds: Option<EsdBox>) -> Self {
+        SoundDecompressionParamBox {
+            boks: Boks::new(*b"wave"),
+            format,
+            esds,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let boks = Boks::read_named(reader, *b"wave")?;
+
+        let mut format = None;
+        let mut esds = None;
+
+        let iter = BoksIterator::new(reader, boks.remaining_size());
+        while let Some((pos, boks)) = iter.next(reader)? {
+            debug!("{}: {:?}", pos, boks);
+
+            match &boks.name {
+                b"frma" => {
+                    Boks::read_named(reader, *b"frma")?;
+                    format = Some(reader.read_u32::<BigEndian>()?.to_be_bytes());
+                }
+                b"esds" => esds = Some(EsdBox::read(reader).context(pos, &boks)?),
+                _ => {
+                    // a copy of the sample entry header and a terminator
+                    debug!("skipping wave box {:?}", boks);
+                    skip(reader, boks.size)?;
+                }
+            }
+        }
+
+        Ok(SoundDecompressionParamBox { boks, format, esds })
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.boks.write(writer, self.total_size())?;
+
+        if let Some(format) = self.format {
+            Boks::new(*b"frma").write(writer, Self::frma_size())?;
+            writer.write_u32::<BigEndian>(u32::from_be_bytes(format))?;
+        }
+        if let Some(esds) = self.esds {
+            esds.write(writer)?;
+        }
+
+        // terminator
+        Boks::new([0; 4]).write(writer, 8)?;
+
+        Ok(())
+    }
+
+    fn frma_size() -> u64 {
+        8 + size_of::<u32>() as u64
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.boks.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        self.format.map_or(0, |_| Self::frma_size())
+            + self.esds.as_ref().map_or(0, |e| e.total_size())
+            + 8
+    }
+}
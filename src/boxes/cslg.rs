@@ -0,0 +1,102 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::*;
+
+use std::io::Write;
+use std::mem::size_of;
+
+pub struct CompositionToDecodeBox {
+    full_box: FullBox,
+    pub composition_to_dts_shift: i64,
+    pub least_decode_to_display_delta: i64,
+    pub greatest_decode_to_display_delta: i64,
+    pub composition_start_time: i64,
+    pub composition_end_time: i64,
+}
+
+impl CompositionToDecodeBox {
+    pub fn new(
+        composition_to_dts_shift: i64,
+        least_decode_to_display_delta: i64,
+        greatest_decode_to_display_delta: i64,
+        composition_start_time: i64,
+        composition_end_time: i64,
+    ) -> Self {
+        let fields = [
+            composition_to_dts_shift,
+            least_decode_to_display_delta,
+            greatest_decode_to_display_delta,
+            composition_start_time,
+            composition_end_time,
+        ];
+
+        let fits_32_bits = fields
+            .iter()
+            .all(|&f| f >= i32::MIN as i64 && f <= i32::MAX as i64);
+
+        CompositionToDecodeBox {
+            full_box: FullBox::new(*b"cslg", if fits_32_bits { 0 } else { 1 }, 0),
+            composition_to_dts_shift,
+            least_decode_to_display_delta,
+            greatest_decode_to_display_delta,
+            composition_start_time,
+            composition_end_time,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"cslg")?;
+
+        let mut fields = [0i64; 5];
+
+        for field in &mut fields {
+            *field = match full_box.version {
+                0 => reader.read_i32::<BigEndian>()? as i64,
+                _ => reader.read_i64::<BigEndian>()?,
+            };
+        }
+
+        Ok(CompositionToDecodeBox {
+            full_box,
+            composition_to_dts_shift: fields[0],
+            least_decode_to_display_delta: fields[1],
+            greatest_decode_to_display_delta: fields[2],
+            composition_start_time: fields[3],
+            composition_end_time: fields[4],
+        })
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.full_box.write(writer, self.total_size())?;
+
+        let fields = [
+            self.composition_to_dts_shift,
+            self.least_decode_to_display_delta,
+            self.greatest_decode_to_display_delta,
+            self.composition_start_time,
+            self.composition_end_time,
+        ];
+
+        for &field in &fields {
+            match self.full_box.version {
+                0 => writer.write_i32::<BigEndian>(field as i32)?,
+                _ => writer.write_i64::<BigEndian>(field)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.full_box.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        let field_size = match self.full_box.version {
+            0 => size_of::<i32>() as u64,
+            _ => size_of::<i64>() as u64,
+        };
+
+        field_size * 5
+    }
+}
@@ -0,0 +1,137 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::*;
+
+use std::io::Write;
+use std::mem::size_of;
+
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct CompositionOffsetEntry {
+    pub count: u32,
+    pub offset: i32,
+}
+
+impl CompositionOffsetEntry {
+    fn to_be(mut self) -> Self {
+        self.count = self.count.to_be();
+        self.offset = self.offset.to_be();
+        self
+    }
+}
+
+unsafe impl bytemuck::Pod for CompositionOffsetEntry {}
+unsafe impl bytemuck::Zeroable for CompositionOffsetEntry {}
+
+pub struct CompositionOffsetBox {
+    full_box: FullBox,
+    pub entries: Vec<CompositionOffsetEntry>,
+}
+
+impl CompositionOffsetBox {
+    pub fn new(entries: Vec<CompositionOffsetEntry>) -> Self {
+        // version 1 is only required when offsets go negative
+        let version = if entries.iter().any(|e| e.offset < 0) {
+            1
+        } else {
+            0
+        };
+
+        CompositionOffsetBox {
+            full_box: FullBox::new(*b"ctts", version, 0),
+            entries,
+        }
+    }
+
+    pub fn version(&self) -> u8 {
+        self.full_box.version
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"ctts")?;
+
+        let count = entry_count(
+            reader.read_u32::<BigEndian>()?,
+            8,
+            full_box.remaining_size().saturating_sub(4),
+            *b"ctts",
+        )?;
+
+        let mut entries = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            let count = reader.read_u32::<BigEndian>()?;
+
+            // version 0 offsets are unsigned, but plenty of muxers write
+            // negative values there anyway, so both are read as signed
+            let offset = reader.read_i32::<BigEndian>()?;
+
+            entries.push(CompositionOffsetEntry { count, offset });
+        }
+
+        Ok(CompositionOffsetBox { full_box, entries })
+    }
+
+    pub fn write(mut self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.full_box.write(writer, self.total_size())?;
+
+        writer.write_u32::<BigEndian>(self.entries.len() as _)?;
+
+        // convert to BE before writing
+        for entry in &mut self.entries {
+            *entry = entry.to_be();
+        }
+
+        writer.write_all(bytemuck::cast_slice(&self.entries))?;
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.full_box.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        size_of::<u32>() as u64
+            + (size_of::<u32>() as u64 + size_of::<i32>() as u64) * self.entries.len() as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{read_test_box, round_trip, test_box};
+
+    fn entries(offsets: &[i32]) -> Vec<CompositionOffsetEntry> {
+        offsets
+            .iter()
+            .map(|&offset| CompositionOffsetEntry { count: 1, offset })
+            .collect()
+    }
+
+    #[test]
+    fn versions() {
+        let (written, ctts) = round_trip(CompositionOffsetBox::new(entries(&[0, 200, 100])));
+        assert_eq!(written[8], 0);
+        assert_eq!(ctts.version(), 0);
+
+        let (written, ctts) = round_trip(CompositionOffsetBox::new(entries(&[0, 200, -100])));
+        assert_eq!(written[8], 1);
+        assert_eq!(ctts.version(), 1);
+        assert_eq!(ctts.entries[2].offset, -100);
+    }
+
+    #[test]
+    fn negative_version_0_offsets() {
+        let data = test_box(
+            b"ctts",
+            &[0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2, 0xff, 0xff, 0xff, 0x9c],
+        );
+        let ctts = read_test_box::<CompositionOffsetBox>(&data).unwrap();
+
+        assert_eq!(ctts.version(), 0);
+        assert_eq!(ctts.entries[0].count, 2);
+        assert_eq!(ctts.entries[0].offset, -100);
+    }
+}
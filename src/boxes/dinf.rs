@@ -17,6 +17,30 @@ impl DataInformationBox {
         }
     }
 
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let boks = Boks::read_named(reader, *b"dinf")?;
+
+        let mut dref = None;
+
+        let iter = BoksIterator::new(reader, boks.remaining_size());
+        while let Some((pos, boks)) = iter.next(reader)? {
+            debug!("{}: {:?}", pos, boks);
+
+            match &boks.name {
+                b"dref" => dref = Some(DataReferenceBox::read(reader).context(pos, &boks)?),
+                _ => {
+                    diagnostic!(at pos, "skipping dinf box {:?}", boks);
+                    skip(reader, boks.size)?;
+                }
+            }
+        }
+
+        Ok(DataInformationBox {
+            boks,
+            dref: require_box(dref, *b"dref")?,
+        })
+    }
+
     pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
         self.boks.write(writer, self.total_size())?;
 
@@ -1,25 +1,142 @@
-use byteorder::{BigEndian, WriteBytesExt};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::*;
 
 use std::io::Write;
 use std::mem::size_of;
 
-use super::url::DataEntryUrlBox;
+use super::url::{DataEntryUrlBox, SELF_CONTAINED};
+use super::urn::DataEntryUrnBox;
+
+/// Data entry of another type, e.g. a QuickTime `alis` alias record.
+pub struct UnknownDataEntryBox {
+    full_box: FullBox,
+    pub data: Vec<u8>,
+}
+
+impl UnknownDataEntryBox {
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read(reader)?;
+
+        let data = read_bytes(reader, full_box.remaining_size())?;
+
+        Ok(UnknownDataEntryBox { full_box, data })
+    }
+
+    pub fn name(&self) -> BoxName {
+        self.full_box.boks.name
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.full_box.write(writer, self.total_size())?;
+
+        writer.write_all(&self.data)?;
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.full_box.size(self.data.len() as u64)
+    }
+}
+
+/// Location of the media data of the samples using a sample entry.
+pub enum DataEntry {
+    Url(DataEntryUrlBox),
+    Urn(DataEntryUrnBox),
+    Unknown(UnknownDataEntryBox),
+}
+
+impl DataEntry {
+    pub fn is_self_contained(&self) -> bool {
+        match self {
+            DataEntry::Url(url) => url.is_self_contained(),
+            DataEntry::Urn(urn) => urn.is_self_contained(),
+            DataEntry::Unknown(entry) => entry.full_box.flags & SELF_CONTAINED != 0,
+        }
+    }
+
+    /// The URL of external media data, None if it cannot be resolved.
+    pub fn location(&self) -> Option<&str> {
+        let location = match self {
+            DataEntry::Url(url) => &url.location,
+            DataEntry::Urn(urn) => &urn.location,
+            DataEntry::Unknown(_) => return None,
+        };
+
+        if location.is_empty() {
+            None
+        } else {
+            Some(location)
+        }
+    }
+
+    fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        match self {
+            DataEntry::Url(url) => url.write(writer),
+            DataEntry::Urn(urn) => urn.write(writer),
+            DataEntry::Unknown(entry) => entry.write(writer),
+        }
+    }
+
+    fn total_size(&self) -> u64 {
+        match self {
+            DataEntry::Url(url) => url.total_size(),
+            DataEntry::Urn(urn) => urn.total_size(),
+            DataEntry::Unknown(entry) => entry.total_size(),
+        }
+    }
+}
 
 pub struct DataReferenceBox {
     full_box: FullBox,
-    pub entries: Vec<DataEntryUrlBox>,
+    pub entries: Vec<DataEntry>,
 }
 
 impl DataReferenceBox {
-    pub fn new(entries: Vec<DataEntryUrlBox>) -> Self {
+    pub fn new(entries: Vec<DataEntry>) -> Self {
         DataReferenceBox {
             full_box: FullBox::new(*b"dref", 0, 0),
             entries,
         }
     }
 
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"dref")?;
+
+        // the entry count is implied by the child boxes
+        reader.read_u32::<BigEndian>()?;
+
+        let mut entries = Vec::new();
+
+        let iter = BoksIterator::new(
+            reader,
+            full_box
+                .remaining_size()
+                .saturating_sub(size_of::<u32>() as u64),
+        );
+        while let Some((pos, boks)) = iter.next(reader)? {
+            debug!("{}: {:?}", pos, boks);
+
+            let entry = match &boks.name {
+                b"url " => DataEntryUrlBox::read(reader).map(DataEntry::Url),
+                b"urn " => DataEntryUrnBox::read(reader).map(DataEntry::Urn),
+                _ => UnknownDataEntryBox::read(reader).map(DataEntry::Unknown),
+            };
+
+            entries.push(entry.nth_context(pos, &boks, entries.len())?);
+        }
+
+        Ok(DataReferenceBox { full_box, entries })
+    }
+
+    /// The entry referred to by the one-based `data_reference_index` of a
+    /// sample entry.
+    pub fn entry(&self, data_reference_index: u16) -> Option<&DataEntry> {
+        self.entries
+            .get((data_reference_index as usize).checked_sub(1)?)
+    }
+
     pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
         self.full_box.write(writer, self.total_size())?;
 
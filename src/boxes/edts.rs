@@ -0,0 +1,58 @@
+use crate::*;
+
+use super::elst::EditListBox;
+
+use std::io::Write;
+
+pub struct EditBox {
+    boks: Boks,
+    pub elst: Option<EditListBox>,
+}
+
+impl EditBox {
+    pub fn new(elst: EditListBox) -> Self {
+        EditBox {
+            boks: Boks::new(*b"edts"),
+            elst: Some(elst),
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let boks = Boks::read_named(reader, *b"edts")?;
+
+        let mut elst = None;
+
+        let iter = BoksIterator::new(reader, boks.remaining_size());
+        while let Some((pos, boks)) = iter.next(reader)? {
+            debug!("{}: {:?}", pos, boks);
+
+            match &boks.name {
+                b"elst" => elst = Some(EditListBox::read(reader).context(pos, &boks)?),
+                _ => {
+                    diagnostic!(at pos, "skipping edts box {:?}", boks);
+                    skip(reader, boks.size)?;
+                }
+            }
+        }
+
+        Ok(EditBox { boks, elst })
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.boks.write(writer, self.total_size())?;
+
+        if let Some(elst) = self.elst {
+            elst.write(writer)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.boks.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        self.elst.as_ref().map(|b| b.total_size()).unwrap_or(0)
+    }
+}
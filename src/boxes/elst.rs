@@ -0,0 +1,133 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::*;
+
+use std::io::Write;
+use std::mem::size_of;
+
+#[derive(Copy, Clone, Debug)]
+pub struct EditListEntry {
+    pub segment_duration: u64,
+    pub media_time: i64,
+    pub media_rate_integer: i16,
+    pub media_rate_fraction: i16,
+}
+
+impl EditListEntry {
+    pub fn new(segment_duration: u64, media_time: i64) -> Self {
+        EditListEntry {
+            segment_duration,
+            media_time,
+            media_rate_integer: 1,
+            media_rate_fraction: 0,
+        }
+    }
+
+    pub fn empty(segment_duration: u64) -> Self {
+        Self::new(segment_duration, -1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.media_time == -1
+    }
+
+    /// Media rate as 16.16 fixed point.
+    pub fn media_rate(&self) -> i32 {
+        ((self.media_rate_integer as i32) << 16) | (self.media_rate_fraction as u16 as i32)
+    }
+}
+
+pub struct EditListBox {
+    full_box: FullBox,
+    pub entries: Vec<EditListEntry>,
+}
+
+impl EditListBox {
+    pub fn new(entries: Vec<EditListEntry>) -> Self {
+        let needs_64_bits = entries.iter().any(|e| {
+            e.segment_duration > u32::MAX as u64
+                || e.media_time > i32::MAX as i64
+                || e.media_time < i32::MIN as i64
+        });
+
+        EditListBox {
+            full_box: FullBox::new(*b"elst", if needs_64_bits { 1 } else { 0 }, 0),
+            entries,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"elst")?;
+
+        let count = entry_count(
+            reader.read_u32::<BigEndian>()?,
+            if full_box.version == 1 { 20 } else { 12 },
+            full_box.remaining_size().saturating_sub(4),
+            *b"elst",
+        )?;
+
+        let mut entries = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            let (segment_duration, media_time) = match full_box.version {
+                1 => (
+                    reader.read_u64::<BigEndian>()?,
+                    reader.read_i64::<BigEndian>()?,
+                ),
+                _ => (
+                    reader.read_u32::<BigEndian>()? as u64,
+                    reader.read_i32::<BigEndian>()? as i64,
+                ),
+            };
+
+            let media_rate_integer = reader.read_i16::<BigEndian>()?;
+            let media_rate_fraction = reader.read_i16::<BigEndian>()?;
+
+            entries.push(EditListEntry {
+                segment_duration,
+                media_time,
+                media_rate_integer,
+                media_rate_fraction,
+            });
+        }
+
+        Ok(EditListBox { full_box, entries })
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.full_box.write(writer, self.total_size())?;
+
+        writer.write_u32::<BigEndian>(self.entries.len() as _)?;
+
+        for entry in &self.entries {
+            match self.full_box.version {
+                1 => {
+                    writer.write_u64::<BigEndian>(entry.segment_duration)?;
+                    writer.write_i64::<BigEndian>(entry.media_time)?;
+                }
+                _ => {
+                    writer.write_u32::<BigEndian>(entry.segment_duration as u32)?;
+                    writer.write_i32::<BigEndian>(entry.media_time as i32)?;
+                }
+            }
+
+            writer.write_i16::<BigEndian>(entry.media_rate_integer)?;
+            writer.write_i16::<BigEndian>(entry.media_rate_fraction)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.full_box.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        let entry_size = match self.full_box.version {
+            1 => size_of::<u64>() as u64 + size_of::<i64>() as u64,
+            _ => size_of::<u32>() as u64 + size_of::<i32>() as u64,
+        } + size_of::<i16>() as u64 * 2;
+
+        size_of::<u32>() as u64 + entry_size * self.entries.len() as u64
+    }
+}
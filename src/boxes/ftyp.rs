@@ -1,4 +1,4 @@
-use byteorder::{BigEndian, WriteBytesExt};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
 use std::borrow::Cow;
 use std::io::Write;
@@ -6,11 +6,39 @@ use std::mem::size_of;
 
 use crate::*;
 
+/// The specifications a brand declares conformance to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BrandFamily {
+    Iso,
+    QuickTime,
+    ThreeGpp,
+    Cmaf,
+    Heif,
+}
+
+impl BrandFamily {
+    pub fn from_brand(brand: &BoxName) -> Option<Self> {
+        match brand {
+            b"isom" | b"iso2" | b"iso3" | b"iso4" | b"iso5" | b"iso6" | b"iso7" | b"iso8"
+            | b"iso9" | b"mp41" | b"mp42" | b"mp71" | b"avc1" | b"dash" | b"msdh" | b"msix"
+            | b"mmp4" | b"M4V " | b"M4A " | b"M4P " | b"M4B " | b"f4v " | b"f4a " => {
+                Some(BrandFamily::Iso)
+            }
+            b"qt  " => Some(BrandFamily::QuickTime),
+            [b'3', b'g', ..] => Some(BrandFamily::ThreeGpp),
+            [b'c', b'm', b'f', _] => Some(BrandFamily::Cmaf),
+            b"mif1" | b"msf1" | b"heic" | b"heix" | b"hevc" | b"hevx" | b"heim" | b"heis"
+            | b"avif" | b"avis" => Some(BrandFamily::Heif),
+            _ => None,
+        }
+    }
+}
+
 pub struct FileTypeBox<'a> {
     boks: Boks,
-    major_brand: BoxName,
-    minor_version: u32,
-    compatible_brands: Cow<'a, [BoxName]>,
+    pub major_brand: BoxName,
+    pub minor_version: u32,
+    pub compatible_brands: Cow<'a, [BoxName]>,
 }
 
 impl<'a> FileTypeBox<'a> {
@@ -27,6 +55,40 @@ impl<'a> FileTypeBox<'a> {
         }
     }
 
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let boks = Boks::read_named(reader, *b"ftyp")?;
+
+        let mut major_brand = [0u8; 4];
+        reader.read_exact(&mut major_brand)?;
+        let minor_version = reader.read_u32::<BigEndian>()?;
+
+        let count = boks.remaining_size().saturating_sub(8) / 4;
+        let mut compatible_brands = Vec::new();
+
+        for _ in 0..count {
+            let mut brand = [0u8; 4];
+            reader.read_exact(&mut brand)?;
+
+            compatible_brands.push(brand);
+        }
+
+        Ok(FileTypeBox {
+            boks,
+            major_brand,
+            minor_version,
+            compatible_brands: compatible_brands.into(),
+        })
+    }
+
+    /// Returns the major brand followed by the compatible brands.
+    pub fn brands(&self) -> impl Iterator<Item = &BoxName> {
+        std::iter::once(&self.major_brand).chain(self.compatible_brands.iter())
+    }
+
+    pub fn has_brand(&self, brand: &BoxName) -> bool {
+        self.brands().any(|b| b == brand)
+    }
+
     pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
         self.boks.write(writer, self.total_size())?;
 
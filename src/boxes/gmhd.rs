@@ -0,0 +1,39 @@
+use crate::*;
+
+use std::io::Write;
+
+// QuickTime base media header, its children (gmin, text, tmcd) are kept as
+// they are
+pub struct BaseMediaHeaderBox {
+    boks: Boks,
+    pub data: Vec<u8>,
+}
+
+impl BaseMediaHeaderBox {
+    pub fn new(data: Vec<u8>) -> Self {
+        BaseMediaHeaderBox {
+            boks: Boks::new(*b"gmhd"),
+            data,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let boks = Boks::read_named(reader, *b"gmhd")?;
+
+        let data = read_bytes(reader, boks.remaining_size())?;
+
+        Ok(BaseMediaHeaderBox { boks, data })
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.boks.write(writer, self.total_size())?;
+
+        writer.write_all(&self.data)?;
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.boks.size(self.data.len() as u64)
+    }
+}
@@ -5,6 +5,59 @@ use crate::*;
 use std::io::Write;
 use std::mem::size_of;
 
+/// Type of media in a track.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HandlerType {
+    Video,
+    Sound,
+    Text,
+    Subtitle,
+    ClosedCaption,
+    Metadata,
+    Hint,
+    Timecode,
+    Other(BoxName),
+}
+
+impl HandlerType {
+    pub fn fourcc(&self) -> BoxName {
+        match self {
+            HandlerType::Video => *b"vide",
+            HandlerType::Sound => *b"soun",
+            HandlerType::Text => *b"text",
+            HandlerType::Subtitle => *b"subt",
+            HandlerType::ClosedCaption => *b"clcp",
+            HandlerType::Metadata => *b"meta",
+            HandlerType::Hint => *b"hint",
+            HandlerType::Timecode => *b"tmcd",
+            HandlerType::Other(name) => *name,
+        }
+    }
+}
+
+impl From<u32> for HandlerType {
+    fn from(handler_type: u32) -> Self {
+        match &handler_type.to_be_bytes() {
+            b"vide" => HandlerType::Video,
+            b"soun" => HandlerType::Sound,
+            b"text" => HandlerType::Text,
+            // sbtl is used by QuickTime
+            b"subt" | b"sbtl" => HandlerType::Subtitle,
+            b"clcp" => HandlerType::ClosedCaption,
+            b"meta" => HandlerType::Metadata,
+            b"hint" => HandlerType::Hint,
+            b"tmcd" => HandlerType::Timecode,
+            name => HandlerType::Other(*name),
+        }
+    }
+}
+
+impl From<HandlerType> for u32 {
+    fn from(handler_type: HandlerType) -> Self {
+        u32::from_be_bytes(handler_type.fourcc())
+    }
+}
+
 pub struct HandlerBox {
     pub full_box: FullBox,
     pub handler_type: u32,
@@ -28,10 +81,19 @@ impl HandlerBox {
 
         let handler_type = BigEndian::read_u32(&bytes[4..]);
 
-        let mut name = Vec::new();
-        let _ = buf.read_until(b'\0', &mut name)?;
+        // the name is null-terminated, but may also just end with the box
+        let mut name = read_bytes(buf, full_box.remaining_size().saturating_sub(20))?;
+
+        if let Some(end) = name.iter().position(|&c| c == 0) {
+            name.truncate(end);
+        }
+
+        // QuickTime writes a counted string instead
+        if name.len() > 1 && name[0] as usize == name.len() - 1 {
+            name.remove(0);
+        }
 
-        let name = String::from_utf8(name)?;
+        let name = String::from_utf8_lossy(&name).into_owned();
 
         Ok(HandlerBox {
             full_box,
@@ -40,6 +102,10 @@ impl HandlerBox {
         })
     }
 
+    pub fn handler(&self) -> HandlerType {
+        self.handler_type.into()
+    }
+
     pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
         self.full_box.write(writer, self.total_size())?;
 
@@ -66,3 +132,46 @@ impl HandlerBox {
         1
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{read_test_box, round_trip, test_box};
+
+    fn hdlr(name: &[u8]) -> Vec<u8> {
+        let mut contents = vec![0; 8];
+        contents.extend(b"soun");
+        contents.extend(&[0; 12]);
+        contents.extend(name);
+
+        test_box(b"hdlr", &contents)
+    }
+
+    #[test]
+    fn names() {
+        for &(name, expected) in &[
+            (&b"SoundHandler\0"[..], "SoundHandler"),
+            (&b"SoundHandler"[..], "SoundHandler"),
+            (&b"\x10Core Media Audio"[..], "Core Media Audio"),
+            (&b"\x10Core Media Audio\0"[..], "Core Media Audio"),
+            (&b"Sound\xffHandler\0"[..], "Sound\u{fffd}Handler"),
+            (&b""[..], ""),
+        ] {
+            let hdlr = read_test_box::<HandlerBox>(&hdlr(name)).unwrap();
+
+            assert_eq!(hdlr.handler(), HandlerType::Sound);
+            assert_eq!(hdlr.name, expected);
+        }
+    }
+
+    #[test]
+    fn write() {
+        let (written, _) = round_trip(HandlerBox::new(
+            u32::from_be_bytes(*b"soun"),
+            "SoundHandler".into(),
+        ));
+
+        assert_eq!(written, hdlr(b"SoundHandler\0"));
+    }
+}
@@ -0,0 +1,68 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::*;
+
+use std::io::Write;
+use std::mem::size_of;
+
+pub struct HintMediaHeaderBox {
+    full_box: FullBox,
+    pub max_pdu_size: u16,
+    pub avg_pdu_size: u16,
+    pub max_bitrate: u32,
+    pub avg_bitrate: u32,
+}
+
+impl HintMediaHeaderBox {
+    pub fn new(max_pdu_size: u16, avg_pdu_size: u16, max_bitrate: u32, avg_bitrate: u32) -> Self {
+        HintMediaHeaderBox {
+            full_box: FullBox::new(*b"hmhd", 0, 0),
+            max_pdu_size,
+            avg_pdu_size,
+            max_bitrate,
+            avg_bitrate,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"hmhd")?;
+
+        let max_pdu_size = reader.read_u16::<BigEndian>()?;
+        let avg_pdu_size = reader.read_u16::<BigEndian>()?;
+        let max_bitrate = reader.read_u32::<BigEndian>()?;
+        let avg_bitrate = reader.read_u32::<BigEndian>()?;
+        let _reserved = reader.read_u32::<BigEndian>()?;
+
+        Ok(HintMediaHeaderBox {
+            full_box,
+            max_pdu_size,
+            avg_pdu_size,
+            max_bitrate,
+            avg_bitrate,
+        })
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.full_box.write(writer, self.total_size())?;
+
+        writer.write_u16::<BigEndian>(self.max_pdu_size)?;
+        writer.write_u16::<BigEndian>(self.avg_pdu_size)?;
+        writer.write_u32::<BigEndian>(self.max_bitrate)?;
+        writer.write_u32::<BigEndian>(self.avg_bitrate)?;
+        writer.write_u32::<BigEndian>(0)?;
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.full_box.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        size_of::<u16>() as u64 + // maxPDUsize
+        size_of::<u16>() as u64 + // avgPDUsize
+        size_of::<u32>() as u64 + // maxbitrate
+        size_of::<u32>() as u64 + // avgbitrate
+        size_of::<u32>() as u64 // reserved
+    }
+}
@@ -0,0 +1,256 @@
+use byteorder::{BigEndian, WriteBytesExt};
+
+use crate::*;
+
+use std::io::Write;
+use std::mem::size_of;
+
+/// Well-known types of `data` atoms.
+pub const DATA_TYPE_BINARY: u32 = 0;
+pub const DATA_TYPE_UTF8: u32 = 1;
+pub const DATA_TYPE_UTF16: u32 = 2;
+pub const DATA_TYPE_JPEG: u32 = 13;
+pub const DATA_TYPE_PNG: u32 = 14;
+pub const DATA_TYPE_SIGNED: u32 = 21;
+pub const DATA_TYPE_UNSIGNED: u32 = 22;
+pub const DATA_TYPE_BMP: u32 = 27;
+
+/// Value of a metadata item.
+pub struct DataBox {
+    boks: Boks,
+    pub data_type: u32,
+    pub locale: u32,
+    pub value: Vec<u8>,
+}
+
+impl DataBox {
+    pub fn new(data_type: u32, value: Vec<u8>) -> Self {
+        DataBox {
+            boks: Boks::new(*b"data"),
+            data_type,
+            locale: 0,
+            value,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let boks = Boks::read_named(reader, *b"data")?;
+
+        let mut bytes = [0u8; 8];
+        reader.read_exact(&mut bytes)?;
+
+        // the first byte is the type set, which is always 0
+        let data_type = BigEndian::read_u24(&bytes[1..]);
+        let locale = BigEndian::read_u32(&bytes[4..]);
+
+        let value = read_bytes(reader, boks.remaining_size().saturating_sub(8))?;
+
+        Ok(DataBox {
+            boks,
+            data_type,
+            locale,
+            value,
+        })
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.boks.write(writer, self.total_size())?;
+
+        writer.write_u32::<BigEndian>(self.data_type)?;
+        writer.write_u32::<BigEndian>(self.locale)?;
+        writer.write_all(&self.value)?;
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.boks.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        size_of::<u32>() as u64 + // data_type
+        size_of::<u32>() as u64 + // locale
+        self.value.len() as u64
+    }
+}
+
+/// `mean` and `name` of a freeform item.
+pub struct ItemStringBox {
+    full_box: FullBox,
+    pub value: String,
+}
+
+impl ItemStringBox {
+    pub fn new(name: BoxName, value: String) -> Self {
+        ItemStringBox {
+            full_box: FullBox::new(name, 0, 0),
+            value,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered, name: BoxName) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, name)?;
+
+        let value = read_bytes(reader, full_box.remaining_size())?;
+
+        Ok(ItemStringBox {
+            full_box,
+            value: String::from_utf8(value)?,
+        })
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.full_box.write(writer, self.total_size())?;
+        writer.write_all(self.value.as_bytes())?;
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.full_box.size(self.value.len() as u64)
+    }
+}
+
+/// A metadata item, named by its box. Freeform items are named `----` and
+/// carry their own `mean` and `name`.
+pub struct MetadataItemBox {
+    boks: Boks,
+    pub mean: Option<ItemStringBox>,
+    pub name: Option<ItemStringBox>,
+    pub data: Vec<DataBox>,
+}
+
+impl MetadataItemBox {
+    pub fn new(name: BoxName, data: Vec<DataBox>) -> Self {
+        MetadataItemBox {
+            boks: Boks::new(name),
+            mean: None,
+            name: None,
+            data,
+        }
+    }
+
+    pub fn freeform(mean: String, name: String, data: Vec<DataBox>) -> Self {
+        MetadataItemBox {
+            boks: Boks::new(*b"----"),
+            mean: Some(ItemStringBox::new(*b"mean", mean)),
+            name: Some(ItemStringBox::new(*b"name", name)),
+            data,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let boks = Boks::read(reader)?;
+
+        let mut mean = None;
+        let mut name = None;
+        let mut data = Vec::new();
+
+        let iter = BoksIterator::new(reader, boks.remaining_size());
+        while let Some((pos, boks)) = iter.next(reader)? {
+            debug!("{}: {:?}", pos, boks);
+
+            match &boks.name {
+                b"mean" => mean = Some(ItemStringBox::read(reader, *b"mean").context(pos, &boks)?),
+                b"name" => name = Some(ItemStringBox::read(reader, *b"name").context(pos, &boks)?),
+                b"data" => data.push(DataBox::read(reader).nth_context(pos, &boks, data.len())?),
+                _ => {
+                    diagnostic!(at pos, "skipping metadata item box {:?}", boks);
+                    skip(reader, boks.size)?;
+                }
+            }
+        }
+
+        Ok(MetadataItemBox {
+            boks,
+            mean,
+            name,
+            data,
+        })
+    }
+
+    /// Name of the item's box.
+    pub fn key(&self) -> BoxName {
+        self.boks.name
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.boks.write(writer, self.total_size())?;
+
+        if let Some(mean) = self.mean {
+            mean.write(writer)?;
+        }
+        if let Some(name) = self.name {
+            name.write(writer)?;
+        }
+        for data in self.data {
+            data.write(writer)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.boks.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        self.mean.as_ref().map(|b| b.total_size()).unwrap_or(0)
+            + self.name.as_ref().map(|b| b.total_size()).unwrap_or(0)
+            + self.data.iter().map(|b| b.total_size()).sum::<u64>()
+    }
+}
+
+pub struct ItemListBox {
+    boks: Boks,
+    pub items: Vec<MetadataItemBox>,
+}
+
+impl ItemListBox {
+    pub fn new(items: Vec<MetadataItemBox>) -> Self {
+        ItemListBox {
+            boks: Boks::new(*b"ilst"),
+            items,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let boks = Boks::read_named(reader, *b"ilst")?;
+
+        let mut items = Vec::new();
+
+        let iter = BoksIterator::new(reader, boks.remaining_size());
+        while let Some((pos, boks)) = iter.next(reader)? {
+            debug!("{}: {:?}", pos, boks);
+
+            match MetadataItemBox::read(reader).context(pos, &boks) {
+                Ok(item) => items.push(item),
+                Err(e) if diagnostics::is_strict() => return Err(e),
+                Err(e) => {
+                    diagnostic!(at pos, "skipping metadata item: {}", e);
+                    goto(reader, pos + boks.size)?;
+                }
+            }
+        }
+
+        Ok(ItemListBox { boks, items })
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.boks.write(writer, self.total_size())?;
+
+        for item in self.items {
+            item.write(writer)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.boks.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        self.items.iter().map(|b| b.total_size()).sum()
+    }
+}
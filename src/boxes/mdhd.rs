@@ -12,6 +12,22 @@ pub struct MediaHeaderBox {
     pub modification_time: u64,
     pub timescale: u32,
     pub duration: u64,
+    // ISO 639-2/T code
+    pub language: [u8; 3],
+}
+
+fn pack_language(language: [u8; 3]) -> u16 {
+    language.iter().fold(0, |packed, c| {
+        (packed << 5) | (c.wrapping_sub(0x60) & 0x1f) as u16
+    })
+}
+
+fn unpack_language(packed: u16) -> [u8; 3] {
+    [
+        ((packed >> 10) & 0x1f) as u8 + 0x60,
+        ((packed >> 5) & 0x1f) as u8 + 0x60,
+        (packed & 0x1f) as u8 + 0x60,
+    ]
 }
 
 impl MediaHeaderBox {
@@ -22,6 +38,7 @@ impl MediaHeaderBox {
             modification_time: 0,
             timescale,
             duration,
+            language: *b"und",
         }
     }
 
@@ -35,7 +52,7 @@ impl MediaHeaderBox {
         BigEndian::write_u32(&mut contents[16..], self.timescale);
         BigEndian::write_u64(&mut contents[20..], self.duration);
 
-        BigEndian::write_u16(&mut contents[28..], 0); // language
+        BigEndian::write_u16(&mut contents[28..], pack_language(self.language));
 
         writer.write_all(&contents)?;
 
@@ -45,21 +62,29 @@ impl MediaHeaderBox {
     pub fn read(buf: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
         let full_box = FullBox::read(buf)?;
 
-        match full_box.version {
-            0 => Self::read_v0(buf, full_box),
-            1 => Self::read_v1(buf, full_box),
-            _ => todo!(),
+        let mdhd = match full_box.version {
+            0 => Self::read_v0(buf, full_box)?,
+            1 => Self::read_v1(buf, full_box)?,
+            version => return Err(Mp4BoxError::UnsupportedVersion(BoxPrint(*b"mdhd"), version)),
+        };
+
+        // times cannot be converted without a timescale
+        if mdhd.timescale == 0 {
+            deviation!("media timescale of 0")?;
         }
+
+        Ok(mdhd)
     }
 
     pub fn read_v0(buf: &mut dyn Buffered, full_box: FullBox) -> Result<Self, Mp4BoxError> {
         let mut contents = [0u8; 20];
-        buf.read_exact(&mut contents).unwrap();
+        buf.read_exact(&mut contents)?;
 
         let creation_time = BigEndian::read_u32(&contents[0..]) as u64;
         let modification_time = BigEndian::read_u32(&contents[4..]) as u64;
         let timescale = BigEndian::read_u32(&contents[8..]);
         let duration = BigEndian::read_u32(&contents[12..]) as u64;
+        let language = unpack_language(BigEndian::read_u16(&contents[16..]));
 
         Ok(MediaHeaderBox {
             full_box,
@@ -67,17 +92,19 @@ impl MediaHeaderBox {
             modification_time,
             timescale,
             duration,
+            language,
         })
     }
 
     pub fn read_v1(buf: &mut dyn Buffered, full_box: FullBox) -> Result<Self, Mp4BoxError> {
         let mut contents = [0u8; 32];
-        buf.read_exact(&mut contents).unwrap();
+        buf.read_exact(&mut contents)?;
 
         let creation_time = BigEndian::read_u64(&contents[0..]);
         let modification_time = BigEndian::read_u64(&contents[8..]);
         let timescale = BigEndian::read_u32(&contents[16..]);
         let duration = BigEndian::read_u64(&contents[20..]);
+        let language = unpack_language(BigEndian::read_u16(&contents[28..]));
 
         Ok(MediaHeaderBox {
             full_box,
@@ -85,6 +112,7 @@ impl MediaHeaderBox {
             modification_time,
             timescale,
             duration,
+            language,
         })
     }
 
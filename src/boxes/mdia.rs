@@ -29,15 +29,15 @@ impl MediaBox {
         let mut minf = None;
 
         let iter = BoksIterator::new(reader, boks.remaining_size());
-        while let Some((pos, boks)) = iter.next(reader) {
+        while let Some((pos, boks)) = iter.next(reader)? {
             debug!("{}: {:?}", pos, boks);
 
             match &boks.name {
-                b"mdhd" => mdhd = Some(MediaHeaderBox::read(reader)?),
-                b"hdlr" => hdlr = Some(HandlerBox::read(reader)?),
-                b"minf" => minf = Some(MediaInformationBox::read(reader)?),
+                b"mdhd" => mdhd = Some(MediaHeaderBox::read(reader).context(pos, &boks)?),
+                b"hdlr" => hdlr = Some(HandlerBox::read(reader).context(pos, &boks)?),
+                b"minf" => minf = Some(MediaInformationBox::read(reader).context(pos, &boks)?),
                 _ => {
-                    warn!("skipping mdia box {:?}", boks);
+                    diagnostic!(at pos, "skipping mdia box {:?}", boks);
                     skip(reader, boks.size)?;
                 }
             }
@@ -1,4 +1,4 @@
-use byteorder::{BigEndian, WriteBytesExt};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
 use crate::*;
 
@@ -7,7 +7,7 @@ use std::mem::size_of;
 
 pub struct MovieExtendsHeaderBox {
     full_box: FullBox,
-    fragment_duration: u64,
+    pub fragment_duration: u64,
 }
 
 impl MovieExtendsHeaderBox {
@@ -18,10 +18,27 @@ impl MovieExtendsHeaderBox {
         }
     }
 
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"mehd")?;
+
+        let fragment_duration = match full_box.version {
+            1 => reader.read_u64::<BigEndian>()?,
+            _ => reader.read_u32::<BigEndian>()? as u64,
+        };
+
+        Ok(MovieExtendsHeaderBox {
+            full_box,
+            fragment_duration,
+        })
+    }
+
     pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
         self.full_box.write(writer, self.total_size())?;
 
-        writer.write_u64::<BigEndian>(self.fragment_duration)?;
+        match self.full_box.version {
+            1 => writer.write_u64::<BigEndian>(self.fragment_duration)?,
+            _ => writer.write_u32::<BigEndian>(self.fragment_duration as u32)?,
+        }
 
         Ok(())
     }
@@ -31,6 +48,9 @@ impl MovieExtendsHeaderBox {
     }
 
     fn size(&self) -> u64 {
-        size_of::<u64>() as u64 // fragment_duration
+        match self.full_box.version {
+            1 => size_of::<u64>() as u64, // fragment_duration
+            _ => size_of::<u32>() as u64, // fragment_duration
+        }
     }
 }
@@ -0,0 +1,85 @@
+use crate::*;
+
+use super::{hdlr::HandlerBox, ilst::ItemListBox};
+
+use std::io::Write;
+
+pub struct MetaBox {
+    boks: Boks,
+    // QuickTime writes meta as a plain box without version and flags
+    full_box: bool,
+    pub hdlr: HandlerBox,
+    pub ilst: Option<ItemListBox>,
+}
+
+impl MetaBox {
+    pub fn new(hdlr: HandlerBox, ilst: Option<ItemListBox>) -> Self {
+        MetaBox {
+            boks: Boks::new(*b"meta"),
+            full_box: true,
+            hdlr,
+            ilst,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let boks = Boks::read_named(reader, *b"meta")?;
+
+        let full_box = peek(reader, 8)?[4..] != *b"hdlr";
+        let mut size = boks.remaining_size();
+
+        if full_box {
+            skip(reader, 4)?;
+            size = size.saturating_sub(4);
+        }
+
+        let mut hdlr = None;
+        let mut ilst = None;
+
+        let iter = BoksIterator::new(reader, size);
+        while let Some((pos, boks)) = iter.next(reader)? {
+            debug!("{}: {:?}", pos, boks);
+
+            match &boks.name {
+                b"hdlr" => hdlr = Some(HandlerBox::read(reader).context(pos, &boks)?),
+                b"ilst" => ilst = Some(ItemListBox::read(reader).context(pos, &boks)?),
+                _ => {
+                    diagnostic!(at pos, "skipping meta box {:?}", boks);
+                    skip(reader, boks.size)?;
+                }
+            }
+        }
+
+        Ok(MetaBox {
+            boks,
+            full_box,
+            hdlr: require_box(hdlr, *b"hdlr")?,
+            ilst,
+        })
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.boks.write(writer, self.total_size())?;
+
+        if self.full_box {
+            writer.write_all(&[0u8; 4])?;
+        }
+
+        self.hdlr.write(writer)?;
+        if let Some(ilst) = self.ilst {
+            ilst.write(writer)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.boks.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        (if self.full_box { 4 } else { 0 })
+            + self.hdlr.total_size()
+            + self.ilst.as_ref().map(|b| b.total_size()).unwrap_or(0)
+    }
+}
@@ -1,32 +1,47 @@
-use byteorder::{BigEndian, ByteOrder};
-use four_cc::FourCC;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
-use bytes::{BufMut, BytesMut};
+use crate::*;
 
-use crate::Mp4BoxError;
-use crate::{FullBoxHeader, Mp4Box};
+use std::io::Write;
+use std::mem::size_of;
 
 pub struct MovieFragmentHeaderBox {
+    full_box: FullBox,
     pub sequence_number: u32,
 }
 
-impl Mp4Box for MovieFragmentHeaderBox {
-    const NAME: FourCC = FourCC(*b"mfhd");
-
-    fn get_full_box_header(&self) -> Option<FullBoxHeader> {
-        Some(FullBoxHeader::new(0, 0))
+impl MovieFragmentHeaderBox {
+    pub fn new(sequence_number: u32) -> Self {
+        MovieFragmentHeaderBox {
+            full_box: FullBox::new(*b"mfhd", 0, 0),
+            sequence_number,
+        }
     }
 
-    fn content_size(&self) -> u64 {
-        4
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"mfhd")?;
+
+        let sequence_number = reader.read_u32::<BigEndian>()?;
+
+        Ok(MovieFragmentHeaderBox {
+            full_box,
+            sequence_number,
+        })
     }
 
-    fn write_box_contents(&self, writer: &mut BytesMut) -> Result<(), Mp4BoxError> {
-        let mut contents = [0u8; 4];
-        BigEndian::write_u32(&mut contents, self.sequence_number);
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.full_box.write(writer, self.total_size())?;
 
-        writer.put_slice(&contents);
+        writer.write_u32::<BigEndian>(self.sequence_number)?;
 
         Ok(())
     }
+
+    pub fn total_size(&self) -> u64 {
+        self.full_box.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        size_of::<u32>() as u64 // sequence_number
+    }
 }
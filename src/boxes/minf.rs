@@ -1,8 +1,9 @@
 use crate::*;
 
 use super::{
-    dinf::DataInformationBox, smhd::SoundMediaHeaderBox, stbl::SampleTableBox,
-    vmhd::VideoMediaHeaderBox,
+    dinf::DataInformationBox, gmhd::BaseMediaHeaderBox, hmhd::HintMediaHeaderBox,
+    nmhd::NullMediaHeaderBox, smhd::SoundMediaHeaderBox, stbl::SampleTableBox,
+    sthd::SubtitleMediaHeaderBox, vmhd::VideoMediaHeaderBox,
 };
 
 use std::io::Write;
@@ -10,6 +11,34 @@ use std::io::Write;
 pub enum MediaHeader {
     Video(VideoMediaHeaderBox),
     Sound(SoundMediaHeaderBox),
+    Subtitle(SubtitleMediaHeaderBox),
+    Hint(HintMediaHeaderBox),
+    Null(NullMediaHeaderBox),
+    Base(BaseMediaHeaderBox),
+}
+
+impl MediaHeader {
+    fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        match self {
+            MediaHeader::Video(vmhd) => vmhd.write(writer),
+            MediaHeader::Sound(smhd) => smhd.write(writer),
+            MediaHeader::Subtitle(sthd) => sthd.write(writer),
+            MediaHeader::Hint(hmhd) => hmhd.write(writer),
+            MediaHeader::Null(nmhd) => nmhd.write(writer),
+            MediaHeader::Base(gmhd) => gmhd.write(writer),
+        }
+    }
+
+    fn total_size(&self) -> u64 {
+        match self {
+            MediaHeader::Video(vmhd) => vmhd.total_size(),
+            MediaHeader::Sound(smhd) => smhd.total_size(),
+            MediaHeader::Subtitle(sthd) => sthd.total_size(),
+            MediaHeader::Hint(hmhd) => hmhd.total_size(),
+            MediaHeader::Null(nmhd) => nmhd.total_size(),
+            MediaHeader::Base(gmhd) => gmhd.total_size(),
+        }
+    }
 }
 
 pub struct MediaInformationBox {
@@ -32,28 +61,66 @@ impl MediaInformationBox {
     pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
         let boks = Boks::read_named(reader, *b"minf")?;
 
+        let mut media_header = None;
+        let mut dinf = None;
         let mut stbl = None;
 
         let iter = BoksIterator::new(reader, boks.remaining_size());
-        while let Some((pos, boks)) = iter.next(reader) {
+        while let Some((pos, boks)) = iter.next(reader)? {
             debug!("{}: {:?}", pos, boks);
 
             match &boks.name {
-                // b"vmhd" => media_header = Some(MediaHeader::Video(VideoMediaHeaderBox::read(reader)?)),
-                // b"smhd" => media_header = Some(MediaHeader::Sound(SoundMediaHeaderBox::read(reader)?)),
-                // b"dinf" => dinf = Some(DataInformationBox::read(reader)?),
-                b"stbl" => stbl = Some(SampleTableBox::read(reader)?),
+                b"vmhd" => {
+                    media_header = Some(MediaHeader::Video(
+                        VideoMediaHeaderBox::read(reader).context(pos, &boks)?,
+                    ))
+                }
+                b"smhd" => {
+                    media_header = Some(MediaHeader::Sound(
+                        SoundMediaHeaderBox::read(reader).context(pos, &boks)?,
+                    ))
+                }
+                b"sthd" => {
+                    media_header = Some(MediaHeader::Subtitle(
+                        SubtitleMediaHeaderBox::read(reader).context(pos, &boks)?,
+                    ))
+                }
+                b"hmhd" => {
+                    media_header = Some(MediaHeader::Hint(
+                        HintMediaHeaderBox::read(reader).context(pos, &boks)?,
+                    ))
+                }
+                b"nmhd" => {
+                    media_header = Some(MediaHeader::Null(
+                        NullMediaHeaderBox::read(reader).context(pos, &boks)?,
+                    ))
+                }
+                b"gmhd" => {
+                    media_header = Some(MediaHeader::Base(
+                        BaseMediaHeaderBox::read(reader).context(pos, &boks)?,
+                    ))
+                }
+                b"dinf" => dinf = Some(DataInformationBox::read(reader).context(pos, &boks)?),
+                b"stbl" => stbl = Some(SampleTableBox::read(reader).context(pos, &boks)?),
                 _ => {
-                    warn!("skipping minf box {:?}", boks);
+                    diagnostic!(at pos, "skipping minf box {:?}", boks);
                     skip(reader, boks.size)?;
                 }
             }
         }
 
+        // optional here, but required by the specification
+        if media_header.is_none() {
+            deviation!("missing media header box")?;
+        }
+        if dinf.is_none() {
+            diagnostics::tolerate(None, Mp4BoxError::RequiredBoxNotFound(BoxPrint(*b"dinf")))?;
+        }
+
         Ok(MediaInformationBox {
             boks,
-            media_header: None,
-            dinf: None,
+            media_header,
+            dinf,
             stbl: require_box(stbl, *b"stbl")?,
         })
     }
@@ -61,9 +128,8 @@ impl MediaInformationBox {
     pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
         self.boks.write(writer, self.total_size())?;
 
-        match require_box(self.media_header, *b"vmhd")? {
-            MediaHeader::Video(vmhd) => vmhd.write(writer)?,
-            MediaHeader::Sound(smhd) => smhd.write(writer)?,
+        if let Some(media_header) = self.media_header {
+            media_header.write(writer)?;
         }
 
         require_box(self.dinf, *b"dinf")?.write(writer)?;
@@ -77,15 +143,11 @@ impl MediaInformationBox {
     }
 
     fn size(&self) -> u64 {
-        let mut size =
-            self.dinf.as_ref().map(|d| d.total_size()).unwrap_or(0) + self.stbl.total_size();
-
-        match &self.media_header {
-            Some(MediaHeader::Video(vmhd)) => size += vmhd.total_size(),
-            Some(MediaHeader::Sound(smhd)) => size += smhd.total_size(),
-            _ => {}
-        }
-
-        size
+        self.media_header
+            .as_ref()
+            .map(|h| h.total_size())
+            .unwrap_or(0)
+            + self.dinf.as_ref().map(|d| d.total_size()).unwrap_or(0)
+            + self.stbl.total_size()
     }
 }
@@ -1,27 +1,71 @@
-use four_cc::FourCC;
+use crate::*;
 
-use bytes::BytesMut;
+use super::{mfhd::MovieFragmentHeaderBox, traf::TrackFragmentBox};
 
-use crate::{Mp4Box, Mp4BoxError};
-
-use super::{MovieFragmentHeaderBox, TrackFragmentBox};
+use std::io::Write;
 
 pub struct MovieFragmentBox {
+    boks: Boks,
     pub mfhd: MovieFragmentHeaderBox,
-    pub traf: TrackFragmentBox,
+    pub trafs: Vec<TrackFragmentBox>,
 }
 
-impl Mp4Box for MovieFragmentBox {
-    const NAME: FourCC = FourCC(*b"moof");
+impl MovieFragmentBox {
+    pub fn new(mfhd: MovieFragmentHeaderBox, trafs: Vec<TrackFragmentBox>) -> Self {
+        MovieFragmentBox {
+            boks: Boks::new(*b"moof"),
+            mfhd,
+            trafs,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let boks = Boks::read_named(reader, *b"moof")?;
+
+        let mut mfhd = None;
+        let mut trafs = Vec::new();
+
+        let iter = BoksIterator::new(reader, boks.remaining_size());
+        while let Some((pos, boks)) = iter.next(reader)? {
+            debug!("{}: {:?}", pos, boks);
+
+            match &boks.name {
+                b"mfhd" => mfhd = Some(MovieFragmentHeaderBox::read(reader).context(pos, &boks)?),
+                b"traf" => {
+                    let index = trafs.len();
+                    trafs.push(TrackFragmentBox::read(reader).nth_context(pos, &boks, index)?);
+                }
+                _ => {
+                    diagnostic!(at pos, "skipping moof box {:?}", boks);
+                    skip(reader, boks.size)?;
+                }
+            }
+        }
 
-    fn content_size(&self) -> u64 {
-        self.mfhd.size() + self.traf.size()
+        Ok(MovieFragmentBox {
+            boks,
+            mfhd: require_box_or(mfhd, *b"mfhd", || MovieFragmentHeaderBox::new(0))?,
+            trafs,
+        })
     }
 
-    fn write_box_contents(&self, writer: &mut BytesMut) -> Result<(), Mp4BoxError> {
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.boks.write(writer, self.total_size())?;
+
         self.mfhd.write(writer)?;
-        self.traf.write(writer)?;
+
+        for traf in self.trafs {
+            traf.write(writer)?;
+        }
 
         Ok(())
     }
+
+    pub fn total_size(&self) -> u64 {
+        self.boks.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        self.mfhd.total_size() + self.trafs.iter().map(|t| t.total_size()).sum::<u64>()
+    }
 }
@@ -1,6 +1,6 @@
 use crate::*;
 
-use super::{mvex::MovieExtendsBox, mvhd::MovieHeaderBox, trak::TrackBox};
+use super::{mvex::MovieExtendsBox, mvhd::MovieHeaderBox, trak::TrackBox, udta::UserDataBox};
 
 use std::io::Write;
 
@@ -9,15 +9,22 @@ pub struct MovieBox {
     pub mvhd: MovieHeaderBox,
     pub mvex: Option<MovieExtendsBox>,
     pub tracks: Vec<TrackBox>,
+    pub udta: Option<UserDataBox>,
 }
 
 impl MovieBox {
-    pub fn new(mvhd: MovieHeaderBox, mvex: Option<MovieExtendsBox>, tracks: Vec<TrackBox>) -> Self {
+    pub fn new(
+        mvhd: MovieHeaderBox,
+        mvex: Option<MovieExtendsBox>,
+        tracks: Vec<TrackBox>,
+        udta: Option<UserDataBox>,
+    ) -> Self {
         MovieBox {
             boks: Boks::new(*b"moov"),
             mvhd,
             mvex,
             tracks,
+            udta,
         }
     }
 
@@ -33,32 +40,54 @@ impl MovieBox {
             track.write(writer)?;
         }
 
+        if let Some(udta) = self.udta {
+            udta.write(writer)?;
+        }
+
         Ok(())
     }
 
     pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let start = pos(reader)?;
         let boks = Boks::read_named(reader, *b"moov")?;
 
         let mut mvhd = None;
         let mut mvex = None;
         let mut tracks = Vec::new();
+        let mut udta = None;
+        let mut track_count = 0;
 
         let iter = BoksIterator::new(reader, boks.remaining_size());
-        while let Some((pos, boks)) = iter.next(reader) {
+        while let Some((pos, boks)) = iter.next(reader)? {
             debug!("{}: {:?}", pos, boks);
 
             match &boks.name {
-                b"mvhd" => mvhd = Some(MovieHeaderBox::read(reader)?),
-                b"mvex" => mvex = Some(MovieExtendsBox::read(reader)?),
-                b"trak" => match TrackBox::read(reader) {
-                    Ok(track) => tracks.push(track),
+                b"mvhd" => mvhd = Some(MovieHeaderBox::read(reader).context(pos, &boks)?),
+                b"mvex" => mvex = Some(MovieExtendsBox::read(reader).context(pos, &boks)?),
+                b"trak" => {
+                    match TrackBox::read(reader).nth_context(pos, &boks, track_count) {
+                        Ok(track) => tracks.push(track),
+                        Err(e) if diagnostics::is_strict() => return Err(e),
+                        Err(e) => {
+                            let e = e.within(*b"moov", None, start);
+                            diagnostic!(at pos, "dropping track: {}", e);
+                            goto(reader, pos + boks.size)?;
+                        }
+                    }
+
+                    track_count += 1;
+                }
+                b"udta" => match UserDataBox::read(reader).context(pos, &boks) {
+                    Ok(boks) => udta = Some(boks),
+                    Err(e) if diagnostics::is_strict() => return Err(e),
                     Err(e) => {
-                        warn!("Failed to parse trak: {}", e);
+                        let e = e.within(*b"moov", None, start);
+                        diagnostic!(at pos, "skipping user data: {}", e);
                         goto(reader, pos + boks.size)?;
                     }
                 },
                 _ => {
-                    warn!("skipping moov box {:?}", boks);
+                    diagnostic!(at pos, "skipping moov box {:?}", boks);
                     skip(reader, boks.size)?;
                 }
             }
@@ -71,6 +100,7 @@ impl MovieBox {
             mvhd: require_box(mvhd, *b"mvhd")?,
             mvex,
             tracks: non_empty(tracks, *b"trak")?,
+            udta,
         })
     }
 
@@ -89,6 +119,10 @@ impl MovieBox {
             size += track.total_size();
         }
 
+        if let Some(udta) = &self.udta {
+            size += udta.total_size();
+        }
+
         size
     }
 }
@@ -6,12 +6,12 @@ use std::io::Write;
 
 pub struct MovieExtendsBox {
     boks: Boks,
-    mehd: MovieExtendsHeaderBox,
-    trex: TrackExtendsBox,
+    pub mehd: Option<MovieExtendsHeaderBox>,
+    pub trex: Vec<TrackExtendsBox>,
 }
 
 impl MovieExtendsBox {
-    pub fn new(mehd: MovieExtendsHeaderBox, trex: TrackExtendsBox) -> Self {
+    pub fn new(mehd: Option<MovieExtendsHeaderBox>, trex: Vec<TrackExtendsBox>) -> Self {
         MovieExtendsBox {
             boks: Boks::new(*b"mvex"),
             mehd,
@@ -20,19 +20,45 @@ impl MovieExtendsBox {
     }
 
     pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
-        let _boks = Boks::read_named(reader, *b"moov")?;
+        let boks = Boks::read_named(reader, *b"mvex")?;
 
-        todo!()
-        /*Ok(MovieExtendsBox {
-            boks,
-        })*/
+        let mut mehd = None;
+        let mut trex = Vec::new();
+
+        let iter = BoksIterator::new(reader, boks.remaining_size());
+        while let Some((pos, boks)) = iter.next(reader)? {
+            debug!("{}: {:?}", pos, boks);
+
+            match &boks.name {
+                b"mehd" => mehd = Some(MovieExtendsHeaderBox::read(reader).context(pos, &boks)?),
+                b"trex" => {
+                    trex.push(TrackExtendsBox::read(reader).nth_context(pos, &boks, trex.len())?)
+                }
+                _ => {
+                    diagnostic!(at pos, "skipping mvex box {:?}", boks);
+                    skip(reader, boks.size)?;
+                }
+            }
+        }
+
+        // tracks without trex take zero defaults
+        if trex.is_empty() {
+            diagnostics::tolerate(None, Mp4BoxError::NotEnoughBoxes(BoxPrint(*b"trex"), 1, 0))?;
+        }
+
+        Ok(MovieExtendsBox { boks, mehd, trex })
     }
 
     pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
         self.boks.write(writer, self.total_size())?;
 
-        self.mehd.write(writer)?;
-        self.trex.write(writer)?;
+        if let Some(mehd) = self.mehd {
+            mehd.write(writer)?;
+        }
+
+        for trex in self.trex {
+            trex.write(writer)?;
+        }
 
         Ok(())
     }
@@ -42,6 +68,7 @@ impl MovieExtendsBox {
     }
 
     fn size(&self) -> u64 {
-        self.mehd.total_size() + self.trex.total_size()
+        self.mehd.as_ref().map(|b| b.total_size()).unwrap_or(0)
+            + self.trex.iter().map(|b| b.total_size()).sum::<u64>()
     }
 }
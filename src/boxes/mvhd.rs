@@ -9,8 +9,9 @@ pub struct MovieHeaderBox {
     full_box: FullBox,
     creation_time: u64,
     modification_time: u64,
-    timescale: u32,
-    duration: u64,
+    pub timescale: u32,
+    pub duration: u64,
+    pub next_track_id: u32,
 }
 
 impl MovieHeaderBox {
@@ -21,17 +22,25 @@ impl MovieHeaderBox {
             modification_time: 0,
             timescale,
             duration,
+            next_track_id: 1,
         }
     }
 
     pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
         let full_box = FullBox::read_named(reader, *b"mvhd")?;
 
-        match full_box.version {
-            0 => Self::read_v0(reader, full_box),
-            1 => Self::read_v1(reader, full_box),
-            _ => todo!(),
+        let mvhd = match full_box.version {
+            0 => Self::read_v0(reader, full_box)?,
+            1 => Self::read_v1(reader, full_box)?,
+            version => return Err(Mp4BoxError::UnsupportedVersion(BoxPrint(*b"mvhd"), version)),
+        };
+
+        // times cannot be converted without a timescale
+        if mvhd.timescale == 0 {
+            deviation!("movie timescale of 0")?;
         }
+
+        Ok(mvhd)
     }
 
     pub fn read_v0(reader: &mut dyn Buffered, full_box: FullBox) -> Result<Self, Mp4BoxError> {
@@ -40,6 +49,7 @@ impl MovieHeaderBox {
 
         let timescale = BigEndian::read_u32(&contents[8..]);
         let duration = BigEndian::read_u32(&contents[12..]) as u64;
+        let next_track_id = BigEndian::read_u32(&contents[92..]);
 
         Ok(MovieHeaderBox {
             full_box,
@@ -47,6 +57,7 @@ impl MovieHeaderBox {
             modification_time: 0,
             timescale,
             duration,
+            next_track_id,
         })
     }
 
@@ -56,6 +67,7 @@ impl MovieHeaderBox {
 
         let timescale = BigEndian::read_u32(&contents[16..]);
         let duration = BigEndian::read_u64(&contents[20..]);
+        let next_track_id = BigEndian::read_u32(&contents[104..]);
 
         Ok(MovieHeaderBox {
             full_box,
@@ -63,6 +75,7 @@ impl MovieHeaderBox {
             modification_time: 0,
             timescale,
             duration,
+            next_track_id,
         })
     }
 
@@ -86,7 +99,7 @@ impl MovieHeaderBox {
         BigEndian::write_i32(&mut contents[60..], 0x00010000);
         BigEndian::write_i32(&mut contents[76..], 0x40000000);
 
-        BigEndian::write_u32(&mut contents[104..], 1);
+        BigEndian::write_u32(&mut contents[104..], self.next_track_id);
 
         writer.write_all(&contents)?;
 
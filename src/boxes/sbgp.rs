@@ -0,0 +1,96 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::*;
+
+use std::io::Write;
+use std::mem::size_of;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SampleToGroupEntry {
+    pub sample_count: u32,
+    /// One-based index into the matching `sgpd`, 0 if the samples are not in
+    /// a group of this type.
+    pub group_description_index: u32,
+}
+
+pub struct SampleToGroupBox {
+    full_box: FullBox,
+    pub grouping_type: BoxName,
+    pub grouping_type_parameter: Option<u32>,
+    pub entries: Vec<SampleToGroupEntry>,
+}
+
+impl SampleToGroupBox {
+    pub fn new(grouping_type: BoxName, entries: Vec<SampleToGroupEntry>) -> Self {
+        SampleToGroupBox {
+            full_box: FullBox::new(*b"sbgp", 0, 0),
+            grouping_type,
+            grouping_type_parameter: None,
+            entries,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"sbgp")?;
+
+        let grouping_type = reader.read_u32::<BigEndian>()?.to_be_bytes();
+        let grouping_type_parameter = if full_box.version == 1 {
+            Some(reader.read_u32::<BigEndian>()?)
+        } else {
+            None
+        };
+
+        let read = grouping_type_parameter.map_or(8, |_| 12);
+        let count = entry_count(
+            reader.read_u32::<BigEndian>()?,
+            8,
+            full_box.remaining_size().saturating_sub(read),
+            *b"sbgp",
+        )?;
+        let mut entries = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            entries.push(SampleToGroupEntry {
+                sample_count: reader.read_u32::<BigEndian>()?,
+                group_description_index: reader.read_u32::<BigEndian>()?,
+            });
+        }
+
+        Ok(SampleToGroupBox {
+            full_box,
+            grouping_type,
+            grouping_type_parameter,
+            entries,
+        })
+    }
+
+    pub fn write(mut self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.full_box.version = self.grouping_type_parameter.is_some() as u8;
+        self.full_box.write(writer, self.total_size())?;
+
+        writer.write_all(&self.grouping_type)?;
+        if let Some(parameter) = self.grouping_type_parameter {
+            writer.write_u32::<BigEndian>(parameter)?;
+        }
+
+        writer.write_u32::<BigEndian>(self.entries.len() as u32)?;
+
+        for entry in self.entries {
+            writer.write_u32::<BigEndian>(entry.sample_count)?;
+            writer.write_u32::<BigEndian>(entry.group_description_index)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.full_box.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        size_of::<u32>() as u64 + // grouping_type
+        self.grouping_type_parameter.map_or(0, |_| size_of::<u32>() as u64) +
+        size_of::<u32>() as u64 + // entry_count
+        self.entries.len() as u64 * size_of::<u32>() as u64 * 2
+    }
+}
@@ -0,0 +1,96 @@
+use crate::*;
+
+use super::trun::SampleFlags;
+
+use std::io::Write;
+
+/// Dependencies of a sample on others, each field is 0 if unknown.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SampleDependency {
+    /// 1: leading sample depending on the preceding random access point,
+    /// 2: not a leading sample, 3: leading sample without that dependency.
+    pub is_leading: u8,
+    /// 1: depends on other samples, 2: independent such as an I picture.
+    pub depends_on: u8,
+    /// 1: other samples depend on it, 2: disposable.
+    pub is_depended_on: u8,
+    /// 1: contains redundant coding, 2: no redundant coding.
+    pub has_redundancy: u8,
+}
+
+impl SampleDependency {
+    pub fn from_byte(byte: u8) -> Self {
+        SampleDependency {
+            is_leading: (byte >> 6) & 0b11,
+            depends_on: (byte >> 4) & 0b11,
+            is_depended_on: (byte >> 2) & 0b11,
+            has_redundancy: byte & 0b11,
+        }
+    }
+
+    pub fn to_byte(&self) -> u8 {
+        (self.is_leading & 0b11) << 6
+            | (self.depends_on & 0b11) << 4
+            | (self.is_depended_on & 0b11) << 2
+            | (self.has_redundancy & 0b11)
+    }
+
+    /// No other sample depends on this one, so it can be dropped.
+    pub fn is_disposable(&self) -> bool {
+        self.is_depended_on == 2
+    }
+
+    pub fn is_independent(&self) -> bool {
+        self.depends_on == 2
+    }
+}
+
+impl From<SampleFlags> for SampleDependency {
+    fn from(flags: SampleFlags) -> Self {
+        SampleDependency {
+            is_leading: flags.is_leading(),
+            depends_on: flags.depends_on(),
+            is_depended_on: flags.is_depended_on(),
+            has_redundancy: flags.has_redundancy(),
+        }
+    }
+}
+
+pub struct SampleDependencyTypeBox {
+    full_box: FullBox,
+    pub entries: Vec<SampleDependency>,
+}
+
+impl SampleDependencyTypeBox {
+    pub fn new(entries: Vec<SampleDependency>) -> Self {
+        SampleDependencyTypeBox {
+            full_box: FullBox::new(*b"sdtp", 0, 0),
+            entries,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"sdtp")?;
+
+        // one byte per sample, the count comes from the sample size box
+        let bytes = read_bytes(reader, full_box.remaining_size())?;
+
+        Ok(SampleDependencyTypeBox {
+            full_box,
+            entries: bytes.into_iter().map(SampleDependency::from_byte).collect(),
+        })
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.full_box.write(writer, self.total_size())?;
+
+        let bytes = self.entries.iter().map(|e| e.to_byte()).collect::<Vec<_>>();
+        writer.write_all(&bytes)?;
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.full_box.size(self.entries.len() as u64)
+    }
+}
@@ -0,0 +1,375 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::*;
+
+use std::io::Write;
+use std::mem::size_of;
+
+/// Description of a group of samples, the variant follows from the grouping
+/// type.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SampleGroupEntry {
+    /// `roll`: the output is correct after decoding `distance` samples from
+    /// a group member, or from `-distance` samples before it.
+    Roll(i16),
+    /// `prol`: pre-roll needed before a group member, e.g. for AAC.
+    PreRoll(i16),
+    /// `rap `: random access points which are not sync samples, such as
+    /// open-GOP pictures.
+    RandomAccess {
+        num_leading_samples_known: bool,
+        num_leading_samples: u8,
+    },
+    /// `sync`: sync samples of the given NAL unit type.
+    Sync {
+        nal_unit_type: u8,
+    },
+    /// `tele`: samples of a temporal level.
+    TemporalLevel {
+        level_independently_decodable: bool,
+    },
+    /// `seig`: encryption parameters of the group members.
+    Encryption {
+        crypt_byte_block: u8,
+        skip_byte_block: u8,
+        is_protected: bool,
+        per_sample_iv_size: u8,
+        kid: [u8; 16],
+        constant_iv: Option<Vec<u8>>,
+    },
+    Unknown(BoxName, Vec<u8>),
+}
+
+impl SampleGroupEntry {
+    pub fn grouping_type(&self) -> BoxName {
+        match self {
+            SampleGroupEntry::Roll(_) => *b"roll",
+            SampleGroupEntry::PreRoll(_) => *b"prol",
+            SampleGroupEntry::RandomAccess { .. } => *b"rap ",
+            SampleGroupEntry::Sync { .. } => *b"sync",
+            SampleGroupEntry::TemporalLevel { .. } => *b"tele",
+            SampleGroupEntry::Encryption { .. } => *b"seig",
+            SampleGroupEntry::Unknown(grouping_type, _) => *grouping_type,
+        }
+    }
+
+    // entry size of grouping types with fixed size entries
+    fn fixed_size(grouping_type: BoxName) -> Option<u32> {
+        match &grouping_type {
+            b"roll" | b"prol" => Some(2),
+            b"rap " | b"sync" | b"tele" => Some(1),
+            _ => None,
+        }
+    }
+
+    fn parse(grouping_type: BoxName, data: &[u8]) -> Self {
+        let entry = match (&grouping_type, data) {
+            (b"roll", [a, b, ..]) => Some(SampleGroupEntry::Roll(i16::from_be_bytes([*a, *b]))),
+            (b"prol", [a, b, ..]) => Some(SampleGroupEntry::PreRoll(i16::from_be_bytes([*a, *b]))),
+            (b"rap ", [a, ..]) => Some(SampleGroupEntry::RandomAccess {
+                num_leading_samples_known: a & 0x80 != 0,
+                num_leading_samples: a & 0x7f,
+            }),
+            (b"sync", [a, ..]) => Some(SampleGroupEntry::Sync {
+                nal_unit_type: a & 0x3f,
+            }),
+            (b"tele", [a, ..]) => Some(SampleGroupEntry::TemporalLevel {
+                level_independently_decodable: a & 0x80 != 0,
+            }),
+            (b"seig", data) if data.len() >= 20 => {
+                let is_protected = data[2] == 1;
+                let per_sample_iv_size = data[3];
+
+                let mut kid = [0u8; 16];
+                kid.copy_from_slice(&data[4..20]);
+
+                let constant_iv = if is_protected && per_sample_iv_size == 0 {
+                    let size = *data.get(20).unwrap_or(&0) as usize;
+                    data.get(21..21 + size).map(|iv| iv.to_vec())
+                } else {
+                    None
+                };
+
+                Some(SampleGroupEntry::Encryption {
+                    crypt_byte_block: data[1] >> 4,
+                    skip_byte_block: data[1] & 0x0f,
+                    is_protected,
+                    per_sample_iv_size,
+                    kid,
+                    constant_iv,
+                })
+            }
+            _ => None,
+        };
+
+        entry.unwrap_or_else(|| SampleGroupEntry::Unknown(grouping_type, data.to_vec()))
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            SampleGroupEntry::Roll(distance) | SampleGroupEntry::PreRoll(distance) => {
+                distance.to_be_bytes().to_vec()
+            }
+            SampleGroupEntry::RandomAccess {
+                num_leading_samples_known,
+                num_leading_samples,
+            } => vec![(*num_leading_samples_known as u8) << 7 | *num_leading_samples & 0x7f],
+            SampleGroupEntry::Sync { nal_unit_type } => vec![*nal_unit_type & 0x3f],
+            SampleGroupEntry::TemporalLevel {
+                level_independently_decodable,
+            } => vec![(*level_independently_decodable as u8) << 7],
+            SampleGroupEntry::Encryption {
+                crypt_byte_block,
+                skip_byte_block,
+                is_protected,
+                per_sample_iv_size,
+                kid,
+                constant_iv,
+            } => {
+                let mut data = vec![
+                    0,
+                    *crypt_byte_block << 4 | *skip_byte_block & 0x0f,
+                    *is_protected as u8,
+                    *per_sample_iv_size,
+                ];
+                data.extend_from_slice(kid);
+
+                if let Some(iv) = constant_iv {
+                    data.push(iv.len() as u8);
+                    data.extend_from_slice(iv);
+                }
+
+                data
+            }
+            SampleGroupEntry::Unknown(_, data) => data.clone(),
+        }
+    }
+}
+
+pub struct SampleGroupDescriptionBox {
+    full_box: FullBox,
+    pub grouping_type: BoxName,
+    /// One-based index of the description of samples not mapped by `sbgp`.
+    pub default_sample_description_index: Option<u32>,
+    pub entries: Vec<SampleGroupEntry>,
+}
+
+impl SampleGroupDescriptionBox {
+    pub fn new(grouping_type: BoxName, entries: Vec<SampleGroupEntry>) -> Self {
+        SampleGroupDescriptionBox {
+            full_box: FullBox::new(*b"sgpd", 1, 0),
+            grouping_type,
+            default_sample_description_index: None,
+            entries,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"sgpd")?;
+        let version = full_box.version;
+
+        let grouping_type = reader.read_u32::<BigEndian>()?.to_be_bytes();
+
+        let mut read = size_of::<u32>() as u64 * 2;
+
+        let default_length = if version >= 1 {
+            read += size_of::<u32>() as u64;
+            reader.read_u32::<BigEndian>()?
+        } else {
+            0
+        };
+
+        let default_sample_description_index = if version >= 2 {
+            read += size_of::<u32>() as u64;
+            Some(reader.read_u32::<BigEndian>()?)
+        } else {
+            None
+        };
+
+        let mut remaining = full_box.remaining_size().saturating_sub(read);
+        // every entry takes at least a byte or its length field
+        let count = entry_count(reader.read_u32::<BigEndian>()?, 1, remaining, *b"sgpd")?;
+        let mut entries = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            let length = match (version, default_length) {
+                // version 0 has no lengths, which only works for known types
+                (0, _) => SampleGroupEntry::fixed_size(grouping_type)
+                    .map(|size| size as u64)
+                    .unwrap_or(remaining),
+                (_, 0) => {
+                    remaining = remaining.saturating_sub(size_of::<u32>() as u64);
+                    reader.read_u32::<BigEndian>()? as u64
+                }
+                (_, length) => length as u64,
+            };
+
+            if length > remaining {
+                return Err(Mp4BoxError::UnexpectedEos);
+            }
+
+            let data = read_bytes(reader, length)?;
+            remaining -= length;
+
+            entries.push(SampleGroupEntry::parse(grouping_type, &data));
+        }
+
+        skip(reader, remaining)?;
+
+        Ok(SampleGroupDescriptionBox {
+            full_box,
+            grouping_type,
+            default_sample_description_index,
+            entries,
+        })
+    }
+
+    pub fn write(mut self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.full_box.version = if self.default_sample_description_index.is_some() {
+            2
+        } else {
+            1
+        };
+        self.full_box.write(writer, self.total_size())?;
+
+        let entries = self
+            .entries
+            .iter()
+            .map(|e| e.to_bytes())
+            .collect::<Vec<_>>();
+        let default_length = Self::default_length(&entries);
+
+        writer.write_all(&self.grouping_type)?;
+        writer.write_u32::<BigEndian>(default_length)?;
+        if let Some(index) = self.default_sample_description_index {
+            writer.write_u32::<BigEndian>(index)?;
+        }
+
+        writer.write_u32::<BigEndian>(entries.len() as u32)?;
+
+        for entry in entries {
+            if default_length == 0 {
+                writer.write_u32::<BigEndian>(entry.len() as u32)?;
+            }
+            writer.write_all(&entry)?;
+        }
+
+        Ok(())
+    }
+
+    // the common entry length, or 0 if the lengths differ
+    fn default_length(entries: &[Vec<u8>]) -> u32 {
+        match entries.first() {
+            Some(first) if entries.iter().all(|e| e.len() == first.len()) => first.len() as u32,
+            _ => 0,
+        }
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.full_box.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        let entries = self
+            .entries
+            .iter()
+            .map(|e| e.to_bytes())
+            .collect::<Vec<_>>();
+        let default_length = Self::default_length(&entries);
+
+        let mut size = size_of::<u32>() as u64 * 3; // grouping_type, default_length, entry_count
+
+        if self.default_sample_description_index.is_some() {
+            size += size_of::<u32>() as u64;
+        }
+
+        if default_length == 0 {
+            size += entries.len() as u64 * size_of::<u32>() as u64;
+        }
+
+        size + entries.iter().map(|e| e.len() as u64).sum::<u64>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{read_test_box, round_trip, test_box};
+
+    #[test]
+    fn fixed_length_entries() {
+        let entries = vec![SampleGroupEntry::Roll(-1), SampleGroupEntry::Roll(2)];
+        let (bytes, sgpd) = round_trip(SampleGroupDescriptionBox::new(*b"roll", entries.clone()));
+
+        // version 1 with a default length
+        assert_eq!(bytes[8], 1);
+        assert_eq!(bytes[16..20], [0, 0, 0, 2]);
+        assert_eq!(sgpd.entries, entries);
+    }
+
+    #[test]
+    fn variable_length_entries() {
+        let entries = vec![
+            SampleGroupEntry::Unknown(*b"abcd", vec![1]),
+            SampleGroupEntry::Unknown(*b"abcd", vec![2, 3]),
+        ];
+        let (bytes, sgpd) = round_trip(SampleGroupDescriptionBox::new(*b"abcd", entries.clone()));
+
+        assert_eq!(bytes[16..20], [0, 0, 0, 0]);
+        assert_eq!(sgpd.entries, entries);
+    }
+
+    #[test]
+    fn version_0() {
+        let bytes = test_box(
+            b"sgpd",
+            &[
+                0, 0, 0, 0, b'r', b'o', b'l', b'l', 0, 0, 0, 2, 0, 1, 0xff, 0xfe,
+            ],
+        );
+        let sgpd = read_test_box::<SampleGroupDescriptionBox>(&bytes).unwrap();
+
+        assert_eq!(sgpd.default_sample_description_index, None);
+        assert_eq!(
+            sgpd.entries,
+            vec![SampleGroupEntry::Roll(1), SampleGroupEntry::Roll(-2)]
+        );
+    }
+
+    #[test]
+    fn version_2() {
+        let bytes = test_box(
+            b"sgpd",
+            &[
+                2, 0, 0, 0, b'r', b'a', b'p', b' ', 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 1, 0x85,
+            ],
+        );
+        let sgpd = read_test_box::<SampleGroupDescriptionBox>(&bytes).unwrap();
+
+        assert_eq!(sgpd.default_sample_description_index, Some(1));
+        assert_eq!(
+            sgpd.entries,
+            vec![SampleGroupEntry::RandomAccess {
+                num_leading_samples_known: true,
+                num_leading_samples: 5,
+            }]
+        );
+
+        let mut written = Vec::new();
+        sgpd.write(&mut written).unwrap();
+        assert_eq!(written, bytes);
+    }
+
+    #[test]
+    fn entry_past_end() {
+        let bytes = test_box(
+            b"sgpd",
+            &[
+                1, 0, 0, 0, b'a', b'b', b'c', b'd', 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 100, 1, 2,
+            ],
+        );
+
+        assert!(read_test_box::<SampleGroupDescriptionBox>(&bytes).is_err());
+    }
+}
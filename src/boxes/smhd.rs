@@ -1,3 +1,5 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
 use crate::*;
 
 use std::io::Write;
@@ -5,21 +7,38 @@ use std::mem::size_of;
 
 pub struct SoundMediaHeaderBox {
     full_box: FullBox,
+    // 8.8 fixed point, 0 is centered
+    pub balance: i16,
+}
+
+impl Default for SoundMediaHeaderBox {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl SoundMediaHeaderBox {
-    pub fn new(_timescale: Vec<u32>) -> Self {
+    pub fn new() -> Self {
         SoundMediaHeaderBox {
             full_box: FullBox::new(*b"smhd", 0, 0),
+            balance: 0,
         }
     }
 
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"smhd")?;
+
+        let balance = reader.read_i16::<BigEndian>()?;
+        let _reserved = reader.read_u16::<BigEndian>()?;
+
+        Ok(SoundMediaHeaderBox { full_box, balance })
+    }
+
     pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
         self.full_box.write(writer, self.total_size())?;
 
-        let contents = [0u8; 4];
-
-        writer.write_all(&contents)?;
+        writer.write_i16::<BigEndian>(self.balance)?;
+        writer.write_u16::<BigEndian>(0)?;
 
         Ok(())
     }
@@ -1,8 +1,11 @@
 use crate::*;
 
 use super::{
-    co64::ChunkLargeOffsetBox, stco::ChunkOffsetBox, stsc::SampleToChunkBox,
-    codec::stsd::SampleDescriptionBox, stss::SyncSampleBox, stsz::SampleSizeBox, stts::TimeToSampleBox,
+    co64::ChunkLargeOffsetBox, codec::stsd::SampleDescriptionBox, cslg::CompositionToDecodeBox,
+    ctts::CompositionOffsetBox, sbgp::SampleToGroupBox, sdtp::SampleDependencyTypeBox,
+    sgpd::SampleGroupDescriptionBox, stco::ChunkOffsetBox, stsc::SampleToChunkBox,
+    stss::SyncSampleBox, stsz::SampleSizeBox, stsz::SampleSizes, stts::TimeToSampleBox,
+    subs::SubSampleInformationBox,
 };
 
 use std::io::Write;
@@ -20,6 +23,17 @@ impl ChunkOffsets {
         }
     }
 
+    pub fn len(&self) -> usize {
+        match self {
+            ChunkOffsets::Stco(offsets) => offsets.chunk_offsets.len(),
+            ChunkOffsets::Co64(offsets) => offsets.chunk_offsets.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     fn size(&self) -> u64 {
         match self {
             ChunkOffsets::Co64(co64) => co64.total_size(),
@@ -32,16 +46,24 @@ pub struct SampleTableBox {
     boks: Boks,
     pub stsd: SampleDescriptionBox,
     pub stts: TimeToSampleBox,
+    pub ctts: Option<CompositionOffsetBox>,
+    pub cslg: Option<CompositionToDecodeBox>,
     pub stsc: SampleToChunkBox,
     pub stsz: SampleSizeBox,
     pub chunk_offsets: ChunkOffsets,
     pub stss: Option<SyncSampleBox>,
+    pub sdtp: Option<SampleDependencyTypeBox>,
+    pub sbgp: Vec<SampleToGroupBox>,
+    pub sgpd: Vec<SampleGroupDescriptionBox>,
+    pub subs: Vec<SubSampleInformationBox>,
 }
 
 impl SampleTableBox {
     pub fn new(
         stsd: SampleDescriptionBox,
         stts: TimeToSampleBox,
+        ctts: Option<CompositionOffsetBox>,
+        cslg: Option<CompositionToDecodeBox>,
         stsc: SampleToChunkBox,
         stsz: SampleSizeBox,
         chunk_offsets: ChunkOffsets,
@@ -51,10 +73,16 @@ impl SampleTableBox {
             boks: Boks::new(*b"stbl"),
             stsd,
             stts,
+            ctts,
+            cslg,
             stsc,
             stsz,
             chunk_offsets,
             stss,
+            sdtp: None,
+            sbgp: Vec::new(),
+            sgpd: Vec::new(),
+            subs: Vec::new(),
         }
     }
 
@@ -63,41 +91,190 @@ impl SampleTableBox {
 
         let mut stsd = None;
         let mut stts = None;
+        let mut ctts = None;
+        let mut cslg = None;
         let mut stsc = None;
         let mut stsz = None;
         let mut chunk_offsets = None;
         let mut stss = None;
+        let mut sdtp = None;
+        let mut sbgp = Vec::new();
+        let mut sgpd = Vec::new();
+        let mut subs = Vec::new();
 
         let iter = BoksIterator::new(reader, boks.remaining_size());
-        while let Some((pos, boks)) = iter.next(reader) {
+        while let Some((pos, boks)) = iter.next(reader)? {
             debug!("{}: {:?}", pos, boks);
 
             match &boks.name {
-                b"stsd" => stsd = Some(SampleDescriptionBox::read(reader)?),
-                b"stts" => stts = Some(TimeToSampleBox::read(reader)?),
-                b"stsc" => stsc = Some(SampleToChunkBox::read(reader)?),
-                b"stsz" => stsz = Some(SampleSizeBox::read(reader)?),
+                b"stsd" => stsd = Some(SampleDescriptionBox::read(reader).context(pos, &boks)?),
+                b"stts" => stts = Some(TimeToSampleBox::read(reader).context(pos, &boks)?),
+                b"ctts" => ctts = Some(CompositionOffsetBox::read(reader).context(pos, &boks)?),
+                b"cslg" => cslg = Some(CompositionToDecodeBox::read(reader).context(pos, &boks)?),
+                b"stsc" => stsc = Some(SampleToChunkBox::read(reader).context(pos, &boks)?),
+                b"stsz" | b"stz2" => stsz = Some(SampleSizeBox::read(reader).context(pos, &boks)?),
                 b"co64" => {
-                    chunk_offsets = Some(ChunkOffsets::Co64(ChunkLargeOffsetBox::read(reader)?))
+                    chunk_offsets = Some(ChunkOffsets::Co64(
+                        ChunkLargeOffsetBox::read(reader).context(pos, &boks)?,
+                    ))
+                }
+                b"stco" => {
+                    chunk_offsets = Some(ChunkOffsets::Stco(
+                        ChunkOffsetBox::read(reader).context(pos, &boks)?,
+                    ))
+                }
+                b"stss" => stss = Some(SyncSampleBox::read(reader).context(pos, &boks)?),
+                b"sdtp" => sdtp = Some(SampleDependencyTypeBox::read(reader).context(pos, &boks)?),
+                b"sbgp" => {
+                    sbgp.push(SampleToGroupBox::read(reader).nth_context(pos, &boks, sbgp.len())?)
+                }
+                b"sgpd" => {
+                    let index = sgpd.len();
+                    sgpd.push(
+                        SampleGroupDescriptionBox::read(reader).nth_context(pos, &boks, index)?,
+                    );
+                }
+                b"subs" => {
+                    let index = subs.len();
+                    subs.push(
+                        SubSampleInformationBox::read(reader).nth_context(pos, &boks, index)?,
+                    );
                 }
-                b"stco" => chunk_offsets = Some(ChunkOffsets::Stco(ChunkOffsetBox::read(reader)?)),
-                b"stss" => stss = Some(SyncSampleBox::read(reader)?),
                 _ => {
-                    warn!("skipping stbl box {:?}", boks);
+                    diagnostic!(at pos, "skipping stbl box {:?}", boks);
                     skip(reader, boks.size)?;
                 }
             }
         }
 
-        Ok(SampleTableBox {
+        // without tables the track has no samples
+        let stbl = SampleTableBox {
             boks,
             stsd: require_box(stsd, *b"stsd")?,
-            stts: require_box(stts, *b"stts")?,
-            stsc: require_box(stsc, *b"stsc")?,
-            stsz: require_box(stsz, *b"stsz")?,
-            chunk_offsets: require_either_box(chunk_offsets, *b"co64", *b"stco")?,
+            stts: require_box_or(stts, *b"stts", || TimeToSampleBox::new(Vec::new()))?,
+            ctts,
+            cslg,
+            stsc: require_box_or(stsc, *b"stsc", || SampleToChunkBox::new(Vec::new()))?,
+            stsz: require_either_box_or(stsz, *b"stsz", *b"stz2", || {
+                SampleSizeBox::new(SampleSizes::Variable(Vec::new()))
+            })?,
+            chunk_offsets: require_either_box_or(chunk_offsets, *b"co64", *b"stco", || {
+                ChunkOffsets::Stco(ChunkOffsetBox::new(Vec::new()))
+            })?,
             stss,
-        })
+            sdtp,
+            sbgp,
+            sgpd,
+            subs,
+        };
+
+        stbl.check_counts()?;
+
+        Ok(stbl)
+    }
+
+    // the tables have to agree on the samples and chunks they describe
+    fn check_counts(&self) -> Result<(), Mp4BoxError> {
+        let timed_samples: u64 = self.stts.entries.iter().map(|e| e.count as u64).sum();
+
+        // a constant sample size leaves the sample count to the time table
+        let sample_count = match &self.stsz.sample_sizes {
+            SampleSizes::Constant(_) => timed_samples,
+            SampleSizes::Variable(sizes) => sizes.len() as u64,
+            SampleSizes::Compact { sizes, .. } => sizes.len() as u64,
+        };
+
+        if timed_samples != sample_count {
+            deviation!(
+                "{} samples have durations, but {} have sizes",
+                timed_samples,
+                sample_count
+            )?;
+        }
+
+        if let Some(ctts) = &self.ctts {
+            let offset_samples: u64 = ctts.entries.iter().map(|e| e.count as u64).sum();
+
+            if offset_samples != sample_count {
+                deviation!(
+                    "{} of {} samples have composition offsets",
+                    offset_samples,
+                    sample_count
+                )?;
+            }
+        }
+
+        let chunk_count = self.chunk_offsets.len() as u64;
+        let description_count = self.stsd.entries.len() as u64;
+        let mut chunked_samples = 0u64;
+
+        for (idx, entry) in self.stsc.entries.iter().enumerate() {
+            let first_chunk = entry.first_chunk as u64;
+            let end_chunk = self
+                .stsc
+                .entries
+                .get(idx + 1)
+                .map_or(chunk_count + 1, |next| next.first_chunk as u64);
+
+            let in_order = match idx {
+                0 => first_chunk == 1,
+                _ => first_chunk > self.stsc.entries[idx - 1].first_chunk as u64,
+            };
+
+            if !in_order || first_chunk > chunk_count {
+                deviation!(
+                    "sample-to-chunk entry {} starts at chunk {}",
+                    idx,
+                    first_chunk
+                )?;
+            }
+
+            let description = entry.sample_description_index as u64;
+
+            if description == 0 || description > description_count {
+                deviation!(
+                    "sample-to-chunk entry {} refers to sample entry {}",
+                    idx,
+                    description
+                )?;
+            }
+
+            let samples = end_chunk
+                .saturating_sub(first_chunk)
+                .saturating_mul(entry.samples_per_chunk as u64);
+
+            chunked_samples = chunked_samples.saturating_add(samples);
+        }
+
+        if chunked_samples != sample_count {
+            deviation!(
+                "{} samples are in chunks, but {} have sizes",
+                chunked_samples,
+                sample_count
+            )?;
+        }
+
+        if let Some(stss) = &self.stss {
+            if let Some(&sample) = stss
+                .sync_samples
+                .iter()
+                .find(|&&s| s == 0 || s as u64 > sample_count)
+            {
+                deviation!("sync sample {} of {} is out of range", sample, sample_count)?;
+            }
+        }
+
+        if let Some(sdtp) = &self.sdtp {
+            if sdtp.entries.len() as u64 != sample_count {
+                deviation!(
+                    "{} of {} samples have dependencies",
+                    sdtp.entries.len(),
+                    sample_count
+                )?;
+            }
+        }
+
+        Ok(())
     }
 
     pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
@@ -105,6 +282,12 @@ impl SampleTableBox {
 
         self.stsd.write(writer)?;
         self.stts.write(writer)?;
+        if let Some(ctts) = self.ctts {
+            ctts.write(writer)?;
+        }
+        if let Some(cslg) = self.cslg {
+            cslg.write(writer)?;
+        }
         self.stsc.write(writer)?;
         self.stsz.write(writer)?;
         match self.chunk_offsets {
@@ -114,6 +297,18 @@ impl SampleTableBox {
         if let Some(stss) = self.stss {
             stss.write(writer)?;
         }
+        if let Some(sdtp) = self.sdtp {
+            sdtp.write(writer)?;
+        }
+        for sgpd in self.sgpd {
+            sgpd.write(writer)?;
+        }
+        for sbgp in self.sbgp {
+            sbgp.write(writer)?;
+        }
+        for subs in self.subs {
+            subs.write(writer)?;
+        }
 
         Ok(())
     }
@@ -125,9 +320,15 @@ impl SampleTableBox {
     fn size(&self) -> u64 {
         self.stsd.total_size()
             + self.stts.total_size()
+            + self.ctts.as_ref().map(|b| b.total_size()).unwrap_or(0)
+            + self.cslg.as_ref().map(|b| b.total_size()).unwrap_or(0)
             + self.stsc.total_size()
             + self.stsz.total_size()
             + self.chunk_offsets.size()
             + self.stss.as_ref().map(|b| b.total_size()).unwrap_or(0)
+            + self.sdtp.as_ref().map(|b| b.total_size()).unwrap_or(0)
+            + self.sgpd.iter().map(|b| b.total_size()).sum::<u64>()
+            + self.sbgp.iter().map(|b| b.total_size()).sum::<u64>()
+            + self.subs.iter().map(|b| b.total_size()).sum::<u64>()
     }
 }
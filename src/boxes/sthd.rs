@@ -0,0 +1,37 @@
+use crate::*;
+
+use std::io::Write;
+
+pub struct SubtitleMediaHeaderBox {
+    full_box: FullBox,
+}
+
+impl Default for SubtitleMediaHeaderBox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SubtitleMediaHeaderBox {
+    pub fn new() -> Self {
+        SubtitleMediaHeaderBox {
+            full_box: FullBox::new(*b"sthd", 0, 0),
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"sthd")?;
+
+        Ok(SubtitleMediaHeaderBox { full_box })
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.full_box.write(writer, self.total_size())?;
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.full_box.size(0)
+    }
+}
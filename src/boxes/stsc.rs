@@ -41,9 +41,14 @@ impl SampleToChunkBox {
     pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
         let full_box = FullBox::read_named(reader, *b"stsc")?;
 
-        let count = reader.read_u32::<BigEndian>()?;
-
-        let mut entries = Vec::new();
+        let count = entry_count(
+            reader.read_u32::<BigEndian>()?,
+            12,
+            full_box.remaining_size().saturating_sub(4),
+            *b"stsc",
+        )?;
+
+        let mut entries = Vec::with_capacity(count);
 
         for _ in 0..count {
             let first_chunk = reader.read_u32::<BigEndian>()?;
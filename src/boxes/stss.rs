@@ -21,9 +21,14 @@ impl SyncSampleBox {
     pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
         let full_box = FullBox::read_named(reader, *b"stss")?;
 
-        let count = reader.read_u32::<BigEndian>()?;
-
-        let mut sync_samples = Vec::new();
+        let count = entry_count(
+            reader.read_u32::<BigEndian>()?,
+            4,
+            full_box.remaining_size().saturating_sub(4),
+            *b"stss",
+        )?;
+
+        let mut sync_samples = Vec::with_capacity(count);
 
         for _ in 0..count {
             let sample = reader.read_u32::<BigEndian>()?;
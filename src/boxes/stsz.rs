@@ -8,13 +8,54 @@ use std::mem::size_of;
 pub enum SampleSizes {
     Constant(u32),
     Variable(Vec<u32>),
+    /// Sizes stored in `field_size` bits each, written as `stz2`.
+    Compact {
+        field_size: u8,
+        sizes: Vec<u16>,
+    },
 }
 
 impl SampleSizes {
+    /// Picks `stz2` with the smallest field size when all sizes fit into
+    /// 16 bits.
+    pub fn compact(sizes: Vec<u32>) -> Self {
+        let field_size = match sizes.iter().max() {
+            Some(&max) if max < 1 << 4 => 4,
+            Some(&max) if max < 1 << 8 => 8,
+            Some(&max) if max < 1 << 16 => 16,
+            _ => return SampleSizes::Variable(sizes),
+        };
+
+        SampleSizes::Compact {
+            field_size,
+            sizes: sizes.into_iter().map(|s| s as u16).collect(),
+        }
+    }
+
+    /// Size of the sample at the zero-based `index`, the constant size does
+    /// not know the sample count.
+    pub fn get(&self, index: usize) -> Option<u32> {
+        match self {
+            SampleSizes::Constant(size) => Some(*size),
+            SampleSizes::Variable(sizes) => sizes.get(index).copied(),
+            SampleSizes::Compact { sizes, .. } => sizes.get(index).map(|&s| s as u32),
+        }
+    }
+
+    fn box_name(&self) -> BoxName {
+        match self {
+            SampleSizes::Compact { .. } => *b"stz2",
+            _ => *b"stsz",
+        }
+    }
+
     fn size(&self) -> u64 {
         match self {
             SampleSizes::Constant(_) => size_of::<u32>() as u64 * 2,
             SampleSizes::Variable(sizes) => size_of::<u32>() as u64 * (2 + sizes.len() as u64),
+            SampleSizes::Compact { field_size, sizes } => {
+                size_of::<u32>() as u64 * 2 + (sizes.len() as u64 * *field_size as u64 + 7) / 8
+            }
         }
     }
 }
@@ -27,38 +68,101 @@ pub struct SampleSizeBox {
 impl SampleSizeBox {
     pub fn new(sample_sizes: SampleSizes) -> Self {
         SampleSizeBox {
-            full_box: FullBox::new(*b"stsz", 0, 0),
+            full_box: FullBox::new(sample_sizes.box_name(), 0, 0),
             sample_sizes,
         }
     }
 
     pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
-        let full_box = FullBox::read_named(reader, *b"stsz")?;
+        let full_box = FullBox::read(reader)?;
+
+        let sample_sizes = match &full_box.boks.name {
+            b"stsz" => Self::read_sizes(reader, full_box.remaining_size())?,
+            b"stz2" => Self::read_compact_sizes(reader, full_box.remaining_size())?,
+            name => {
+                return Err(Mp4BoxError::UnexpectedName(
+                    BoxPrint(*b"stsz"),
+                    BoxPrint(*name),
+                ))
+            }
+        };
+
+        Ok(SampleSizeBox {
+            full_box,
+            sample_sizes,
+        })
+    }
 
+    fn read_sizes(reader: &mut dyn Buffered, remaining: u64) -> Result<SampleSizes, Mp4BoxError> {
         let constant_size = reader.read_u32::<BigEndian>()?;
         let count = reader.read_u32::<BigEndian>()?;
 
-        let sample_sizes = if count == 0 {
-            SampleSizes::Constant(constant_size)
-        } else {
-            let mut sample_sizes = Vec::new();
+        // with a constant size, only the sample count follows
+        if constant_size != 0 || count == 0 {
+            return Ok(SampleSizes::Constant(constant_size));
+        }
 
-            for _ in 0..count {
-                let size = reader.read_u32::<BigEndian>()?;
+        let count = entry_count(count, 4, remaining.saturating_sub(8), *b"stsz")?;
+        let mut sample_sizes = Vec::with_capacity(count);
 
-                sample_sizes.push(size);
-            }
+        for _ in 0..count {
+            let size = reader.read_u32::<BigEndian>()?;
+
+            sample_sizes.push(size);
+        }
+
+        Ok(SampleSizes::Variable(sample_sizes))
+    }
+
+    fn read_compact_sizes(
+        reader: &mut dyn Buffered,
+        remaining: u64,
+    ) -> Result<SampleSizes, Mp4BoxError> {
+        // 24 reserved bits precede the field size
+        let field_size = reader.read_u32::<BigEndian>()? as u8;
+        let count = reader.read_u32::<BigEndian>()?;
 
-            SampleSizes::Variable(sample_sizes)
+        // each size takes field_size bits
+        let fitting = remaining.saturating_sub(8).saturating_mul(8) / (field_size as u64).max(1);
+
+        let count = if count as u64 <= fitting {
+            count as usize
+        } else {
+            diagnostics::tolerate(None, Mp4BoxError::TooManyEntries(BoxPrint(*b"stz2"), count))?;
+            fitting as usize
         };
+        let mut sizes = Vec::with_capacity(count);
 
-        Ok(SampleSizeBox {
-            full_box,
-            sample_sizes,
-        })
+        match field_size {
+            4 => {
+                // two sizes per byte, the first one in the upper nibble
+                for i in 0..(count + 1) / 2 {
+                    let byte = reader.read_u8()?;
+
+                    sizes.push((byte >> 4) as u16);
+                    if 2 * i + 1 < count {
+                        sizes.push((byte & 0x0f) as u16);
+                    }
+                }
+            }
+            8 => {
+                for _ in 0..count {
+                    sizes.push(reader.read_u8()? as u16);
+                }
+            }
+            16 => {
+                for _ in 0..count {
+                    sizes.push(reader.read_u16::<BigEndian>()?);
+                }
+            }
+            _ => return Err(Mp4BoxError::InvalidFieldSize(field_size)),
+        }
+
+        Ok(SampleSizes::Compact { field_size, sizes })
     }
 
-    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+    pub fn write(mut self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.full_box.boks.name = self.sample_sizes.box_name();
         self.full_box.write(writer, self.total_size())?;
 
         match self.sample_sizes {
@@ -77,6 +181,30 @@ impl SampleSizeBox {
 
                 writer.write_all(bytemuck::cast_slice(&sizes))?;
             }
+            SampleSizes::Compact { field_size, sizes } => {
+                writer.write_u32::<BigEndian>(field_size as u32)?;
+                writer.write_u32::<BigEndian>(sizes.len() as u32)?;
+
+                match field_size {
+                    4 => {
+                        for pair in sizes.chunks(2) {
+                            let low = pair.get(1).copied().unwrap_or(0);
+
+                            writer.write_u8((pair[0] as u8) << 4 | (low as u8 & 0x0f))?;
+                        }
+                    }
+                    8 => {
+                        for size in sizes {
+                            writer.write_u8(size as u8)?;
+                        }
+                    }
+                    _ => {
+                        for size in sizes {
+                            writer.write_u16::<BigEndian>(size)?;
+                        }
+                    }
+                }
+            }
         }
 
         Ok(())
@@ -90,3 +218,72 @@ impl SampleSizeBox {
         self.sample_sizes.size()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::diagnostics::{self, ParseMode};
+    use crate::{read_test_box, round_trip, test_box};
+
+    #[test]
+    fn variable_sizes() {
+        let (_, stsz) = round_trip(SampleSizeBox::new(SampleSizes::Variable(vec![1, 70000, 3])));
+
+        assert!(
+            matches!(stsz.sample_sizes, SampleSizes::Variable(sizes) if sizes == [1, 70000, 3])
+        );
+    }
+
+    #[test]
+    fn constant_size() {
+        let (_, stsz) = round_trip(SampleSizeBox::new(SampleSizes::Constant(5)));
+
+        assert!(matches!(stsz.sample_sizes, SampleSizes::Constant(5)));
+    }
+
+    #[test]
+    fn compact_sizes() {
+        for sizes in vec![vec![1, 15, 3], vec![1, 255], vec![1, 65535]] {
+            let (bytes, stsz) = round_trip(SampleSizeBox::new(SampleSizes::compact(sizes.clone())));
+
+            assert_eq!(bytes[4..8], *b"stz2");
+            assert_eq!(
+                (0..sizes.len())
+                    .map(|i| stsz.sample_sizes.get(i).unwrap())
+                    .collect::<Vec<_>>(),
+                sizes
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_field_size() {
+        let bytes = test_box(b"stz2", &[0, 0, 0, 0, 0, 0, 0, 7, 0, 0, 0, 1, 0]);
+
+        assert!(matches!(
+            read_test_box::<SampleSizeBox>(&bytes),
+            Err(Mp4BoxError::InvalidFieldSize(7))
+        ));
+    }
+
+    #[test]
+    fn too_many_entries() {
+        let bytes = test_box(
+            b"stsz",
+            &[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3, 232, 0, 0, 0, 1, 0, 0, 0, 2],
+        );
+
+        let (res, _) =
+            diagnostics::collect(ParseMode::Strict, || read_test_box::<SampleSizeBox>(&bytes));
+        assert!(matches!(res, Err(Mp4BoxError::TooManyEntries(_, 1000))));
+
+        let (res, diagnostics) = diagnostics::collect(ParseMode::Lenient, || {
+            read_test_box::<SampleSizeBox>(&bytes)
+        });
+        assert!(
+            matches!(res.unwrap().sample_sizes, SampleSizes::Variable(sizes) if sizes == [1, 2])
+        );
+        assert_eq!(diagnostics.len(), 1);
+    }
+}
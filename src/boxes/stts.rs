@@ -39,9 +39,14 @@ impl TimeToSampleBox {
     pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
         let full_box = FullBox::read_named(reader, *b"stts")?;
 
-        let count = reader.read_u32::<BigEndian>()?;
-
-        let mut entries = Vec::new();
+        let count = entry_count(
+            reader.read_u32::<BigEndian>()?,
+            8,
+            full_box.remaining_size().saturating_sub(4),
+            *b"stts",
+        )?;
+
+        let mut entries = Vec::with_capacity(count);
 
         for _ in 0..count {
             let count = reader.read_u32::<BigEndian>()?;
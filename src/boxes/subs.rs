@@ -0,0 +1,163 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::*;
+
+use std::io::Write;
+use std::mem::size_of;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SubSample {
+    pub size: u32,
+    pub priority: u8,
+    /// The sample can still be decoded without this sub-sample.
+    pub discardable: bool,
+    pub codec_specific_parameters: u32,
+}
+
+/// Sub-samples of a single sample as described by one `subs` box.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubSampleLayout {
+    /// Codec-specific kind of the sub-samples, e.g. NAL units or tiles.
+    pub flags: u32,
+    pub subsamples: Vec<SubSample>,
+}
+
+pub struct SubSampleEntry {
+    /// Difference to the sample number of the previous entry.
+    pub sample_delta: u32,
+    pub subsamples: Vec<SubSample>,
+}
+
+pub struct SubSampleInformationBox {
+    full_box: FullBox,
+    pub entries: Vec<SubSampleEntry>,
+}
+
+impl SubSampleInformationBox {
+    pub fn new(flags: u32, entries: Vec<SubSampleEntry>) -> Self {
+        SubSampleInformationBox {
+            full_box: FullBox::new(*b"subs", 0, flags),
+            entries,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"subs")?;
+
+        let count = entry_count(
+            reader.read_u32::<BigEndian>()?,
+            6,
+            full_box.remaining_size().saturating_sub(4),
+            *b"subs",
+        )?;
+        let mut entries = Vec::with_capacity(count);
+
+        for _ in 0..count {
+            let sample_delta = reader.read_u32::<BigEndian>()?;
+            let subsample_count = reader.read_u16::<BigEndian>()?;
+            let mut subsamples = Vec::with_capacity(subsample_count as usize);
+
+            for _ in 0..subsample_count {
+                let size = if full_box.version == 1 {
+                    reader.read_u32::<BigEndian>()?
+                } else {
+                    reader.read_u16::<BigEndian>()? as u32
+                };
+
+                subsamples.push(SubSample {
+                    size,
+                    priority: reader.read_u8()?,
+                    discardable: reader.read_u8()? != 0,
+                    codec_specific_parameters: reader.read_u32::<BigEndian>()?,
+                });
+            }
+
+            entries.push(SubSampleEntry {
+                sample_delta,
+                subsamples,
+            });
+        }
+
+        Ok(SubSampleInformationBox { full_box, entries })
+    }
+
+    pub fn flags(&self) -> u32 {
+        self.full_box.flags
+    }
+
+    /// The layouts by one-based sample number, counted from the first sample
+    /// of the track fragment in `traf`.
+    pub fn layouts(&self) -> impl Iterator<Item = (u32, SubSampleLayout)> + '_ {
+        let flags = self.flags();
+
+        self.entries.iter().scan(0u32, move |number, entry| {
+            *number = number.saturating_add(entry.sample_delta);
+
+            Some((
+                *number,
+                SubSampleLayout {
+                    flags,
+                    subsamples: entry.subsamples.clone(),
+                },
+            ))
+        })
+    }
+
+    pub fn write(mut self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.full_box.version = self.large_sizes() as u8;
+        self.full_box.write(writer, self.total_size())?;
+
+        writer.write_u32::<BigEndian>(self.entries.len() as u32)?;
+
+        for entry in &self.entries {
+            writer.write_u32::<BigEndian>(entry.sample_delta)?;
+            writer.write_u16::<BigEndian>(entry.subsamples.len() as u16)?;
+
+            for subsample in &entry.subsamples {
+                if self.full_box.version == 1 {
+                    writer.write_u32::<BigEndian>(subsample.size)?;
+                } else {
+                    writer.write_u16::<BigEndian>(subsample.size as u16)?;
+                }
+                writer.write_u8(subsample.priority)?;
+                writer.write_u8(subsample.discardable as u8)?;
+                writer.write_u32::<BigEndian>(subsample.codec_specific_parameters)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // version 1 is only needed for sub-samples of 64 KiB or more
+    fn large_sizes(&self) -> bool {
+        self.entries
+            .iter()
+            .flat_map(|e| &e.subsamples)
+            .any(|s| s.size > u16::MAX as u32)
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.full_box.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        let size_field = if self.large_sizes() {
+            size_of::<u32>()
+        } else {
+            size_of::<u16>()
+        } as u64;
+
+        // size, priority, discardable, codec_specific_parameters
+        let subsample_size = size_field + 2 + size_of::<u32>() as u64;
+
+        size_of::<u32>() as u64
+            + self
+                .entries
+                .iter()
+                .map(|e| {
+                    (size_of::<u32>() + size_of::<u16>()) as u64
+                        + e.subsamples.len() as u64 * subsample_size
+                })
+                .sum::<u64>()
+    }
+}
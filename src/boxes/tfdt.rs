@@ -1,33 +1,56 @@
-use byteorder::{BigEndian, ByteOrder};
-use four_cc::FourCC;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
-use bytes::{BufMut, BytesMut};
-
-use crate::{FullBoxHeader, Mp4Box, Mp4BoxError};
+use crate::*;
 
+use std::io::Write;
 use std::mem::size_of;
 
 pub struct TrackFragmentBaseMediaDecodeTimeBox {
+    full_box: FullBox,
     pub base_media_decode_time: u64,
 }
 
-impl Mp4Box for TrackFragmentBaseMediaDecodeTimeBox {
-    const NAME: FourCC = FourCC(*b"tfdt");
-
-    fn get_full_box_header(&self) -> Option<FullBoxHeader> {
-        Some(FullBoxHeader::new(1, 0))
+impl TrackFragmentBaseMediaDecodeTimeBox {
+    pub fn new(base_media_decode_time: u64) -> Self {
+        TrackFragmentBaseMediaDecodeTimeBox {
+            full_box: FullBox::new(*b"tfdt", 1, 0),
+            base_media_decode_time,
+        }
     }
 
-    fn content_size(&self) -> u64 {
-        size_of::<u64>() as u64 // base_media_decode_time
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"tfdt")?;
+
+        let base_media_decode_time = match full_box.version {
+            1 => reader.read_u64::<BigEndian>()?,
+            _ => reader.read_u32::<BigEndian>()? as u64,
+        };
+
+        Ok(TrackFragmentBaseMediaDecodeTimeBox {
+            full_box,
+            base_media_decode_time,
+        })
     }
 
-    fn write_box_contents(&self, writer: &mut BytesMut) -> Result<(), Mp4BoxError> {
-        let mut content = [0u8; 8];
-        BigEndian::write_u64(&mut content[..], self.base_media_decode_time);
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.full_box.write(writer, self.total_size())?;
 
-        writer.put_slice(&content);
+        match self.full_box.version {
+            1 => writer.write_u64::<BigEndian>(self.base_media_decode_time)?,
+            _ => writer.write_u32::<BigEndian>(self.base_media_decode_time as u32)?,
+        }
 
         Ok(())
     }
+
+    pub fn total_size(&self) -> u64 {
+        self.full_box.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        match self.full_box.version {
+            1 => size_of::<u64>() as u64, // base_media_decode_time
+            _ => size_of::<u32>() as u64, // base_media_decode_time
+        }
+    }
 }
@@ -1,10 +1,8 @@
-use byteorder::{BigEndian, WriteBytesExt};
-use four_cc::FourCC;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
-use bytes::{BufMut, BytesMut};
-
-use crate::{FullBoxHeader, Mp4Box, Mp4BoxError};
+use crate::*;
 
+use std::io::Write;
 use std::mem::size_of;
 
 bitflags::bitflags! {
@@ -20,6 +18,7 @@ bitflags::bitflags! {
 }
 
 pub struct TrackFragmentHeaderBox {
+    full_box: FullBox,
     pub track_id: u32,
     pub base_data_offset: Option<u64>,
     pub sample_description_index: Option<u32>,
@@ -31,6 +30,20 @@ pub struct TrackFragmentHeaderBox {
 }
 
 impl TrackFragmentHeaderBox {
+    pub fn new(track_id: u32) -> Self {
+        TrackFragmentHeaderBox {
+            full_box: FullBox::new(*b"tfhd", 0, 0),
+            track_id,
+            base_data_offset: None,
+            sample_description_index: None,
+            default_sample_duration: None,
+            default_sample_size: None,
+            default_sample_flags: None,
+            duration_is_empty: false,
+            default_base_is_moof: false,
+        }
+    }
+
     fn flags_from_fields(&self) -> TrackFragmentHeaderFlags {
         let mut flags = TrackFragmentHeaderFlags::empty();
 
@@ -64,68 +77,106 @@ impl TrackFragmentHeaderBox {
 
         flags
     }
-}
-
-impl Mp4Box for TrackFragmentHeaderBox {
-    const NAME: FourCC = FourCC(*b"tfhd");
 
-    fn get_full_box_header(&self) -> Option<FullBoxHeader> {
-        Some(FullBoxHeader::new(0, self.flags_from_fields().bits()))
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"tfhd")?;
+        let flags = TrackFragmentHeaderFlags::from_bits_truncate(full_box.flags);
+
+        let track_id = reader.read_u32::<BigEndian>()?;
+
+        let base_data_offset = if flags.contains(TrackFragmentHeaderFlags::BASE_DATA_OFFSET_PRESENT)
+        {
+            Some(reader.read_u64::<BigEndian>()?)
+        } else {
+            None
+        };
+
+        let mut read_optional = |flag| -> Result<Option<u32>, Mp4BoxError> {
+            if flags.contains(flag) {
+                Ok(Some(reader.read_u32::<BigEndian>()?))
+            } else {
+                Ok(None)
+            }
+        };
+
+        let sample_description_index =
+            read_optional(TrackFragmentHeaderFlags::SAMPLE_DESCRIPTION_INDEX_PRESENT)?;
+        let default_sample_duration =
+            read_optional(TrackFragmentHeaderFlags::DEFAULT_SAMPLE_DURATION_PRESENT)?;
+        let default_sample_size =
+            read_optional(TrackFragmentHeaderFlags::DEFAULT_SAMPLE_SIZE_PRESENT)?;
+        let default_sample_flags =
+            read_optional(TrackFragmentHeaderFlags::DEFAULT_SAMPLE_FLAGS_PRESENT)?;
+
+        Ok(TrackFragmentHeaderBox {
+            full_box,
+            track_id,
+            base_data_offset,
+            sample_description_index,
+            default_sample_duration,
+            default_sample_size,
+            default_sample_flags,
+            duration_is_empty: flags.contains(TrackFragmentHeaderFlags::DURATION_IS_EMPTY),
+            default_base_is_moof: flags.contains(TrackFragmentHeaderFlags::DEFAULT_BASE_IS_MOOF),
+        })
     }
 
-    fn content_size(&self) -> u64 {
-        let mut size = size_of::<u32>() as u64; // track_ID
+    pub fn write(mut self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.full_box.flags = self.flags_from_fields().bits();
+        self.full_box.write(writer, self.total_size())?;
 
-        if self.base_data_offset.is_some() {
-            size += size_of::<u64>() as u64;
+        writer.write_u32::<BigEndian>(self.track_id)?;
+
+        if let Some(base_data_offset) = self.base_data_offset {
+            writer.write_u64::<BigEndian>(base_data_offset)?;
         }
 
-        if self.sample_description_index.is_some() {
-            size += size_of::<u32>() as u64;
+        if let Some(sample_description_index) = self.sample_description_index {
+            writer.write_u32::<BigEndian>(sample_description_index)?;
         }
 
-        if self.default_sample_duration.is_some() {
-            size += size_of::<u32>() as u64;
+        if let Some(default_sample_duration) = self.default_sample_duration {
+            writer.write_u32::<BigEndian>(default_sample_duration)?;
         }
 
-        if self.default_sample_size.is_some() {
-            size += size_of::<u32>() as u64;
+        if let Some(default_sample_size) = self.default_sample_size {
+            writer.write_u32::<BigEndian>(default_sample_size)?;
         }
 
-        if self.default_sample_flags.is_some() {
-            size += size_of::<u32>() as u64;
+        if let Some(default_sample_flags) = self.default_sample_flags {
+            writer.write_u32::<BigEndian>(default_sample_flags)?;
         }
 
-        size
+        Ok(())
     }
 
-    fn write_box_contents(&self, writer: &mut BytesMut) -> Result<(), Mp4BoxError> {
-        let mut v = Vec::new();
+    pub fn total_size(&self) -> u64 {
+        self.full_box.size(self.size())
+    }
 
-        v.write_u32::<BigEndian>(self.track_id)?;
+    fn size(&self) -> u64 {
+        let mut size = size_of::<u32>() as u64; // track_ID
 
-        if let Some(base_data_offset) = self.base_data_offset {
-            v.write_u64::<BigEndian>(base_data_offset)?;
+        if self.base_data_offset.is_some() {
+            size += size_of::<u64>() as u64;
         }
 
-        if let Some(sample_description_index) = self.sample_description_index {
-            v.write_u32::<BigEndian>(sample_description_index)?;
+        if self.sample_description_index.is_some() {
+            size += size_of::<u32>() as u64;
         }
 
-        if let Some(default_sample_duration) = self.default_sample_duration {
-            v.write_u32::<BigEndian>(default_sample_duration)?;
+        if self.default_sample_duration.is_some() {
+            size += size_of::<u32>() as u64;
         }
 
-        if let Some(default_sample_size) = self.default_sample_size {
-            v.write_u32::<BigEndian>(default_sample_size)?;
+        if self.default_sample_size.is_some() {
+            size += size_of::<u32>() as u64;
         }
 
-        if let Some(default_sample_flags) = self.default_sample_flags {
-            v.write_u32::<BigEndian>(default_sample_flags)?;
+        if self.default_sample_flags.is_some() {
+            size += size_of::<u32>() as u64;
         }
 
-        writer.put_slice(&v);
-
-        Ok(())
+        size
     }
 }
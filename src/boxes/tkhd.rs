@@ -21,6 +21,11 @@ pub struct TrackHeaderBox {
     pub modification_time: u64,
     pub track_id: u32,
     pub duration: u64,
+    pub layer: i16,
+    // tracks sharing a non-zero group are alternatives to each other
+    pub alternate_group: i16,
+    // 8.8 fixed point
+    pub volume: i16,
     pub width: I16F16,
     pub height: I16F16,
 }
@@ -39,11 +44,18 @@ impl TrackHeaderBox {
             modification_time: 0,
             track_id,
             duration,
+            layer: 0,
+            alternate_group: 0,
+            volume: 0,
             width,
             height,
         }
     }
 
+    pub fn flags(&self) -> TrackHeaderFlags {
+        TrackHeaderFlags::from_bits_truncate(self.full_box.flags)
+    }
+
     pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
         self.full_box.write(writer, self.total_size())?;
 
@@ -54,7 +66,9 @@ impl TrackHeaderBox {
         BigEndian::write_u32(&mut contents[16..], self.track_id);
         BigEndian::write_u64(&mut contents[24..], self.duration);
 
-        BigEndian::write_i32(&mut contents[44..], 0); // volume
+        BigEndian::write_i16(&mut contents[40..], self.layer);
+        BigEndian::write_i16(&mut contents[42..], self.alternate_group);
+        BigEndian::write_i16(&mut contents[44..], self.volume);
 
         BigEndian::write_i32(&mut contents[48..], 0x00010000);
         BigEndian::write_i32(&mut contents[64..], 0x00010000);
@@ -74,21 +88,23 @@ impl TrackHeaderBox {
         match full_box.version {
             0 => Self::read_v0(buf, full_box),
             1 => Self::read_v1(buf, full_box),
-            _ => todo!(),
+            version => Err(Mp4BoxError::UnsupportedVersion(BoxPrint(*b"tkhd"), version)),
         }
     }
 
     fn read_v0(buf: &mut dyn Buffered, full_box: FullBox) -> Result<Self, Mp4BoxError> {
-        let _flags = TrackHeaderFlags::from_bits(full_box.flags).unwrap();
-
         let mut contents = [0u8; 80];
-        buf.read_exact(&mut contents).unwrap();
+        buf.read_exact(&mut contents)?;
 
         let track_id = BigEndian::read_u32(&contents[8..]);
         let duration = BigEndian::read_u32(&contents[16..]) as u64;
 
-        let width = BigEndian::read_u32(&contents[64..]).into();
-        let height = BigEndian::read_u32(&contents[68..]).into();
+        let layer = BigEndian::read_i16(&contents[28..]);
+        let alternate_group = BigEndian::read_i16(&contents[30..]);
+        let volume = BigEndian::read_i16(&contents[32..]);
+
+        let width = I16F16(BigEndian::read_u32(&contents[72..]));
+        let height = I16F16(BigEndian::read_u32(&contents[76..]));
 
         Ok(TrackHeaderBox {
             full_box,
@@ -96,22 +112,27 @@ impl TrackHeaderBox {
             modification_time: 0,
             track_id,
             duration,
+            layer,
+            alternate_group,
+            volume,
             width,
             height,
         })
     }
 
     fn read_v1(buf: &mut dyn Buffered, full_box: FullBox) -> Result<Self, Mp4BoxError> {
-        let _flags = TrackHeaderFlags::from_bits(full_box.flags).unwrap();
-
         let mut contents = [0u8; 92];
-        buf.read_exact(&mut contents).unwrap();
+        buf.read_exact(&mut contents)?;
 
         let track_id = BigEndian::read_u32(&contents[16..]);
         let duration = BigEndian::read_u64(&contents[24..]);
 
-        let width = BigEndian::read_u32(&contents[76..]).into();
-        let height = BigEndian::read_u32(&contents[80..]).into();
+        let layer = BigEndian::read_i16(&contents[40..]);
+        let alternate_group = BigEndian::read_i16(&contents[42..]);
+        let volume = BigEndian::read_i16(&contents[44..]);
+
+        let width = I16F16(BigEndian::read_u32(&contents[84..]));
+        let height = I16F16(BigEndian::read_u32(&contents[88..]));
 
         Ok(TrackHeaderBox {
             full_box,
@@ -119,6 +140,9 @@ impl TrackHeaderBox {
             modification_time: 0,
             track_id,
             duration,
+            layer,
+            alternate_group,
+            volume,
             width,
             height,
         })
@@ -1,45 +1,120 @@
-use four_cc::FourCC;
+use crate::*;
 
-use bytes::BytesMut;
+use super::{
+    subs::SubSampleInformationBox, tfdt::TrackFragmentBaseMediaDecodeTimeBox,
+    tfhd::TrackFragmentHeaderBox, trun::TrackFragmentRunBox,
+};
 
-use crate::{Mp4Box, Mp4BoxError};
-
-use super::{TrackFragmentBaseMediaDecodeTimeBox, TrackFragmentHeaderBox, TrackFragmentRunBox};
+use std::io::Write;
 
 pub struct TrackFragmentBox {
+    boks: Boks,
     pub tfhd: TrackFragmentHeaderBox,
     pub track_runs: Vec<TrackFragmentRunBox>,
     pub base_media_decode_time: Option<TrackFragmentBaseMediaDecodeTimeBox>,
+    pub subs: Vec<SubSampleInformationBox>,
 }
 
-impl Mp4Box for TrackFragmentBox {
-    const NAME: FourCC = FourCC(*b"traf");
+impl TrackFragmentBox {
+    pub fn new(
+        tfhd: TrackFragmentHeaderBox,
+        track_runs: Vec<TrackFragmentRunBox>,
+        base_media_decode_time: Option<TrackFragmentBaseMediaDecodeTimeBox>,
+    ) -> Self {
+        TrackFragmentBox {
+            boks: Boks::new(*b"traf"),
+            tfhd,
+            track_runs,
+            base_media_decode_time,
+            subs: Vec::new(),
+        }
+    }
 
-    fn content_size(&self) -> u64 {
-        let mut size = self.tfhd.size();
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let boks = Boks::read_named(reader, *b"traf")?;
 
-        for trun in &self.track_runs {
-            size += trun.size();
-        }
+        let mut tfhd = None;
+        let mut track_runs = Vec::new();
+        let mut base_media_decode_time = None;
+        let mut subs = Vec::new();
 
-        if let Some(base_media_decode_time) = &self.base_media_decode_time {
-            size += base_media_decode_time.size();
+        let iter = BoksIterator::new(reader, boks.remaining_size());
+        while let Some((pos, boks)) = iter.next(reader)? {
+            debug!("{}: {:?}", pos, boks);
+
+            match &boks.name {
+                b"tfhd" => tfhd = Some(TrackFragmentHeaderBox::read(reader).context(pos, &boks)?),
+                b"trun" => {
+                    let index = track_runs.len();
+                    track_runs
+                        .push(TrackFragmentRunBox::read(reader).nth_context(pos, &boks, index)?);
+                }
+                b"tfdt" => {
+                    base_media_decode_time = Some(
+                        TrackFragmentBaseMediaDecodeTimeBox::read(reader).context(pos, &boks)?,
+                    )
+                }
+                b"subs" => {
+                    let index = subs.len();
+                    subs.push(
+                        SubSampleInformationBox::read(reader).nth_context(pos, &boks, index)?,
+                    );
+                }
+                _ => {
+                    diagnostic!(at pos, "skipping traf box {:?}", boks);
+                    skip(reader, boks.size)?;
+                }
+            }
         }
 
-        size
+        Ok(TrackFragmentBox {
+            boks,
+            tfhd: require_box(tfhd, *b"tfhd")?,
+            track_runs,
+            base_media_decode_time,
+            subs,
+        })
     }
 
-    fn write_box_contents(&self, writer: &mut BytesMut) -> Result<(), Mp4BoxError> {
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.boks.write(writer, self.total_size())?;
+
         self.tfhd.write(writer)?;
 
-        if let Some(base_media_decode_time) = &self.base_media_decode_time {
+        if let Some(base_media_decode_time) = self.base_media_decode_time {
             base_media_decode_time.write(writer)?;
         }
 
-        for run in &self.track_runs {
+        for run in self.track_runs {
             run.write(writer)?;
         }
 
+        for subs in self.subs {
+            subs.write(writer)?;
+        }
+
         Ok(())
     }
+
+    pub fn total_size(&self) -> u64 {
+        self.boks.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        let mut size = self.tfhd.total_size();
+
+        for trun in &self.track_runs {
+            size += trun.total_size();
+        }
+
+        if let Some(base_media_decode_time) = &self.base_media_decode_time {
+            size += base_media_decode_time.total_size();
+        }
+
+        for subs in &self.subs {
+            size += subs.total_size();
+        }
+
+        size
+    }
 }
@@ -1,20 +1,29 @@
 use crate::*;
 
-use super::{mdia::MediaBox, tkhd::TrackHeaderBox};
+use super::{edts::EditBox, mdia::MediaBox, tkhd::TrackHeaderBox, tref::TrackReferenceBox};
 
 use std::io::Write;
 
 pub struct TrackBox {
     boks: Boks,
     pub tkhd: TrackHeaderBox,
+    pub tref: Option<TrackReferenceBox>,
+    pub edts: Option<EditBox>,
     pub mdia: MediaBox,
 }
 
 impl TrackBox {
-    pub fn new(tkhd: TrackHeaderBox, mdia: MediaBox) -> Self {
+    pub fn new(
+        tkhd: TrackHeaderBox,
+        tref: Option<TrackReferenceBox>,
+        edts: Option<EditBox>,
+        mdia: MediaBox,
+    ) -> Self {
         TrackBox {
             boks: Boks::new(*b"trak"),
             tkhd,
+            tref,
+            edts,
             mdia,
         }
     }
@@ -23,17 +32,21 @@ impl TrackBox {
         let boks = Boks::read_named(reader, *b"trak")?;
 
         let mut tkhd = None;
+        let mut tref = None;
+        let mut edts = None;
         let mut mdia = None;
 
         let iter = BoksIterator::new(reader, boks.remaining_size());
-        while let Some((pos, boks)) = iter.next(reader) {
+        while let Some((pos, boks)) = iter.next(reader)? {
             debug!("{}: {:?}", pos, boks);
 
             match &boks.name {
-                b"tkhd" => tkhd = Some(TrackHeaderBox::read(reader)?),
-                b"mdia" => mdia = Some(MediaBox::read(reader)?),
+                b"tkhd" => tkhd = Some(TrackHeaderBox::read(reader).context(pos, &boks)?),
+                b"tref" => tref = Some(TrackReferenceBox::read(reader).context(pos, &boks)?),
+                b"edts" => edts = Some(EditBox::read(reader).context(pos, &boks)?),
+                b"mdia" => mdia = Some(MediaBox::read(reader).context(pos, &boks)?),
                 _ => {
-                    warn!("skipping trak box {:?}", boks);
+                    diagnostic!(at pos, "skipping trak box {:?}", boks);
                     skip(reader, boks.size)?;
                 }
             }
@@ -42,6 +55,8 @@ impl TrackBox {
         Ok(TrackBox {
             boks,
             tkhd: require_box(tkhd, *b"tkhd")?,
+            tref,
+            edts,
             mdia: require_box(mdia, *b"mdia")?,
         })
     }
@@ -50,6 +65,12 @@ impl TrackBox {
         self.boks.write(writer, self.total_size())?;
 
         self.tkhd.write(writer)?;
+        if let Some(tref) = self.tref {
+            tref.write(writer)?;
+        }
+        if let Some(edts) = self.edts {
+            edts.write(writer)?;
+        }
         self.mdia.write(writer)?;
 
         Ok(())
@@ -60,6 +81,9 @@ impl TrackBox {
     }
 
     fn size(&self) -> u64 {
-        self.tkhd.total_size() + self.mdia.total_size()
+        self.tkhd.total_size()
+            + self.tref.as_ref().map(|b| b.total_size()).unwrap_or(0)
+            + self.edts.as_ref().map(|b| b.total_size()).unwrap_or(0)
+            + self.mdia.total_size()
     }
 }
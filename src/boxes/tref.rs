@@ -0,0 +1,188 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::*;
+
+use std::io::Write;
+use std::mem::size_of;
+
+/// Relationship of a track to the tracks it references.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReferenceType {
+    /// Text track holding the chapters of the referencing track.
+    Chapter,
+    /// Hint track for the referenced media.
+    Hint,
+    /// Metadata track describing the referenced track.
+    Describes,
+    /// Subtitle track for the referenced track.
+    Subtitle,
+    /// Auxiliary depth video for the referenced video.
+    Depth,
+    /// Font track used by the referencing text track.
+    Font,
+    /// Track to be synchronised with the referencing track.
+    Sync,
+    /// Decoding depends on the referenced track.
+    Dependency,
+    /// Timecode track of the referencing video.
+    Timecode,
+    Other(BoxName),
+}
+
+impl ReferenceType {
+    pub fn fourcc(&self) -> BoxName {
+        match self {
+            ReferenceType::Chapter => *b"chap",
+            ReferenceType::Hint => *b"hint",
+            ReferenceType::Describes => *b"cdsc",
+            ReferenceType::Subtitle => *b"subt",
+            ReferenceType::Depth => *b"vdep",
+            ReferenceType::Font => *b"font",
+            ReferenceType::Sync => *b"sync",
+            ReferenceType::Dependency => *b"dpnd",
+            ReferenceType::Timecode => *b"tmcd",
+            ReferenceType::Other(name) => *name,
+        }
+    }
+}
+
+impl From<BoxName> for ReferenceType {
+    fn from(name: BoxName) -> Self {
+        match &name {
+            b"chap" => ReferenceType::Chapter,
+            b"hint" => ReferenceType::Hint,
+            b"cdsc" => ReferenceType::Describes,
+            b"subt" => ReferenceType::Subtitle,
+            b"vdep" => ReferenceType::Depth,
+            b"font" => ReferenceType::Font,
+            b"sync" => ReferenceType::Sync,
+            b"dpnd" => ReferenceType::Dependency,
+            b"tmcd" => ReferenceType::Timecode,
+            _ => ReferenceType::Other(name),
+        }
+    }
+}
+
+/// IDs of the tracks referenced by a track, the box name is the type of the
+/// reference.
+pub struct TrackReferenceTypeBox {
+    boks: Boks,
+    pub track_ids: Vec<u32>,
+}
+
+impl TrackReferenceTypeBox {
+    pub fn new(reference_type: ReferenceType, track_ids: Vec<u32>) -> Self {
+        TrackReferenceTypeBox {
+            boks: Boks::new(reference_type.fourcc()),
+            track_ids,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let boks = Boks::read(reader)?;
+
+        let count = boks.remaining_size() / size_of::<u32>() as u64;
+        let mut track_ids = Vec::with_capacity(count as usize);
+
+        for _ in 0..count {
+            track_ids.push(reader.read_u32::<BigEndian>()?);
+        }
+
+        // ignore trailing bytes that do not form a whole ID
+        skip(reader, boks.remaining_size() % size_of::<u32>() as u64)?;
+
+        Ok(TrackReferenceTypeBox { boks, track_ids })
+    }
+
+    pub fn reference_type(&self) -> ReferenceType {
+        self.boks.name.into()
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.boks.write(writer, self.total_size())?;
+
+        for track_id in self.track_ids {
+            writer.write_u32::<BigEndian>(track_id)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.boks.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        self.track_ids.len() as u64 * size_of::<u32>() as u64
+    }
+}
+
+pub struct TrackReferenceBox {
+    boks: Boks,
+    pub references: Vec<TrackReferenceTypeBox>,
+}
+
+impl TrackReferenceBox {
+    pub fn new(references: Vec<TrackReferenceTypeBox>) -> Self {
+        TrackReferenceBox {
+            boks: Boks::new(*b"tref"),
+            references,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let boks = Boks::read_named(reader, *b"tref")?;
+
+        let mut references = Vec::new();
+
+        let iter = BoksIterator::new(reader, boks.remaining_size());
+        while let Some((pos, boks)) = iter.next(reader)? {
+            debug!("{}: {:?}", pos, boks);
+
+            let index = references.len();
+            references.push(TrackReferenceTypeBox::read(reader).nth_context(pos, &boks, index)?);
+        }
+
+        Ok(TrackReferenceBox { boks, references })
+    }
+
+    /// Adds a reference, next to existing ones of the same type.
+    pub fn add(&mut self, reference_type: ReferenceType, track_id: u32) {
+        match self
+            .references
+            .iter()
+            .position(|r| r.reference_type() == reference_type)
+        {
+            Some(idx) => self.references[idx].track_ids.push(track_id),
+            None => self
+                .references
+                .push(TrackReferenceTypeBox::new(reference_type, vec![track_id])),
+        }
+    }
+
+    /// IDs of the tracks referenced with the given type.
+    pub fn track_ids(&self, reference_type: ReferenceType) -> impl Iterator<Item = u32> + '_ {
+        self.references
+            .iter()
+            .filter(move |r| r.reference_type() == reference_type)
+            .flat_map(|r| r.track_ids.iter().copied())
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.boks.write(writer, self.total_size())?;
+
+        for reference in self.references {
+            reference.write(writer)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.boks.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        self.references.iter().map(|b| b.total_size()).sum()
+    }
+}
@@ -7,11 +7,11 @@ use std::mem::size_of;
 
 pub struct TrackExtendsBox {
     full_box: FullBox,
-    track_id: u32,
-    default_sample_description_index: u32,
-    default_sample_duration: u32,
-    default_sample_size: u32,
-    default_sample_flags: u32,
+    pub track_id: u32,
+    pub default_sample_description_index: u32,
+    pub default_sample_duration: u32,
+    pub default_sample_size: u32,
+    pub default_sample_flags: u32,
 }
 
 impl TrackExtendsBox {
@@ -32,6 +32,22 @@ impl TrackExtendsBox {
         }
     }
 
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"trex")?;
+
+        let mut contents = [0u8; 20];
+        reader.read_exact(&mut contents)?;
+
+        Ok(TrackExtendsBox {
+            full_box,
+            track_id: BigEndian::read_u32(&contents[..]),
+            default_sample_description_index: BigEndian::read_u32(&contents[4..]),
+            default_sample_duration: BigEndian::read_u32(&contents[8..]),
+            default_sample_size: BigEndian::read_u32(&contents[12..]),
+            default_sample_flags: BigEndian::read_u32(&contents[16..]),
+        })
+    }
+
     pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
         self.full_box.write(writer, self.total_size())?;
 
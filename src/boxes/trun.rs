@@ -1,12 +1,10 @@
-use byteorder::{BigEndian, WriteBytesExt};
-use four_cc::FourCC;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
 
-use bytes::{BufMut, BytesMut};
+use crate::*;
 
+use std::io::Write;
 use std::mem::size_of;
 
-use crate::{FullBoxHeader, Mp4Box, Mp4BoxError};
-
 bitflags::bitflags! {
     pub struct TrackFragmentRunFlags: u32 {
         const DATA_OFFSET_PRESENT = 0x00000001;
@@ -18,6 +16,40 @@ bitflags::bitflags! {
     }
 }
 
+/// Sample flags as used by `trex`, `tfhd` and `trun`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SampleFlags(pub u32);
+
+impl SampleFlags {
+    pub fn is_leading(&self) -> u8 {
+        ((self.0 >> 26) & 0b11) as u8
+    }
+
+    pub fn depends_on(&self) -> u8 {
+        ((self.0 >> 24) & 0b11) as u8
+    }
+
+    pub fn is_depended_on(&self) -> u8 {
+        ((self.0 >> 22) & 0b11) as u8
+    }
+
+    pub fn has_redundancy(&self) -> u8 {
+        ((self.0 >> 20) & 0b11) as u8
+    }
+
+    pub fn padding_value(&self) -> u8 {
+        ((self.0 >> 17) & 0b111) as u8
+    }
+
+    pub fn is_non_sync_sample(&self) -> bool {
+        self.0 & 0x0001_0000 != 0
+    }
+
+    pub fn degradation_priority(&self) -> u16 {
+        (self.0 & 0xffff) as u16
+    }
+}
+
 pub struct TrackFragmentSample {
     pub duration: Option<u32>,
     pub size: Option<u32>,
@@ -26,13 +58,37 @@ pub struct TrackFragmentSample {
 }
 
 pub struct TrackFragmentRunBox {
+    full_box: FullBox,
     pub data_offset: Option<i32>,
     pub first_sample_flags: Option<u32>,
     pub samples: Vec<TrackFragmentSample>,
 }
 
 impl TrackFragmentRunBox {
-    fn sample_size(&self, flags: TrackFragmentRunFlags) -> u64 {
+    pub fn new(
+        data_offset: Option<i32>,
+        first_sample_flags: Option<u32>,
+        samples: Vec<TrackFragmentSample>,
+    ) -> Self {
+        // version 1 is only required when offsets go negative
+        let version = if samples
+            .iter()
+            .any(|s| s.composition_time_offset.unwrap_or(0) < 0)
+        {
+            1
+        } else {
+            0
+        };
+
+        TrackFragmentRunBox {
+            full_box: FullBox::new(*b"trun", version, 0),
+            data_offset,
+            first_sample_flags,
+            samples,
+        }
+    }
+
+    fn sample_size(flags: TrackFragmentRunFlags) -> u64 {
         let mut sample_size = 0;
 
         if flags.contains(TrackFragmentRunFlags::SAMPLE_DURATION_PRESENT) {
@@ -85,74 +141,133 @@ impl TrackFragmentRunBox {
 
         flags
     }
-}
 
-impl Mp4Box for TrackFragmentRunBox {
-    const NAME: FourCC = FourCC(*b"trun");
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"trun")?;
+        let flags = TrackFragmentRunFlags::from_bits_truncate(full_box.flags);
+
+        let sample_count = reader.read_u32::<BigEndian>()?;
+        let mut read = size_of::<u32>() as u64;
+
+        let data_offset = if flags.contains(TrackFragmentRunFlags::DATA_OFFSET_PRESENT) {
+            read += size_of::<i32>() as u64;
+            Some(reader.read_i32::<BigEndian>()?)
+        } else {
+            None
+        };
+
+        let first_sample_flags =
+            if flags.contains(TrackFragmentRunFlags::FIRST_SAMPLE_FLAGS_PRESENT) {
+                read += size_of::<u32>() as u64;
+                Some(reader.read_u32::<BigEndian>()?)
+            } else {
+                None
+            };
+
+        let sample_count = entry_count(
+            sample_count,
+            Self::sample_size(flags),
+            full_box.remaining_size().saturating_sub(read),
+            *b"trun",
+        )?;
+        let mut samples = Vec::with_capacity(sample_count);
+
+        for _ in 0..sample_count {
+            let mut read_optional = |flag| -> Result<Option<u32>, Mp4BoxError> {
+                if flags.contains(flag) {
+                    Ok(Some(reader.read_u32::<BigEndian>()?))
+                } else {
+                    Ok(None)
+                }
+            };
+
+            let duration = read_optional(TrackFragmentRunFlags::SAMPLE_DURATION_PRESENT)?;
+            let size = read_optional(TrackFragmentRunFlags::SAMPLE_SIZE_PRESENT)?;
+            let sample_flags = read_optional(TrackFragmentRunFlags::SAMPLE_FLAGS_PRESENT)?;
+
+            // version 0 offsets are unsigned, but like in ctts they are read
+            // as signed in both versions
+            let composition_time_offset =
+                read_optional(TrackFragmentRunFlags::SAMPLE_COMPOSITION_TIME_OFFSET_PRESENT)?
+                    .map(|offset| offset as i32);
+
+            samples.push(TrackFragmentSample {
+                duration,
+                size,
+                flags: sample_flags,
+                composition_time_offset,
+            });
+        }
 
-    fn get_full_box_header(&self) -> Option<FullBoxHeader> {
-        Some(FullBoxHeader::new(0, self.flags_from_fields().bits()))
+        Ok(TrackFragmentRunBox {
+            full_box,
+            data_offset,
+            first_sample_flags,
+            samples,
+        })
     }
 
-    fn content_size(&self) -> u64 {
+    pub fn write(mut self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
         let flags = self.flags_from_fields();
 
-        let mut size = 0;
-
-        size += size_of::<u32>() as u64; // sample_count
-
-        if flags.contains(TrackFragmentRunFlags::DATA_OFFSET_PRESENT) {
-            size += size_of::<i32>() as u64; // data_offset
-        }
-
-        if flags.contains(TrackFragmentRunFlags::FIRST_SAMPLE_FLAGS_PRESENT) {
-            size += size_of::<u32>() as u64; // first_sample_flags
-        }
-
-        size += self.sample_size(flags) * self.samples.len() as u64;
-
-        size
-    }
+        self.full_box.flags = flags.bits();
+        self.full_box.write(writer, self.total_size())?;
 
-    fn write_box_contents(&self, writer: &mut BytesMut) -> Result<(), Mp4BoxError> {
-        let mut v = Vec::new();
-
-        v.write_u32::<BigEndian>(self.samples.len() as u32)?; // TODO: check for truncation
+        writer.write_u32::<BigEndian>(self.samples.len() as u32)?; // TODO: check for truncation
 
         if let Some(data_offset) = self.data_offset {
-            v.write_i32::<BigEndian>(data_offset)?;
+            writer.write_i32::<BigEndian>(data_offset)?;
         }
 
         if let Some(first_sample_flags) = self.first_sample_flags {
-            v.write_u32::<BigEndian>(first_sample_flags)?;
+            writer.write_u32::<BigEndian>(first_sample_flags)?;
         }
 
-        let flags = self.flags_from_fields();
         for sample in &self.samples {
-            ensure_sample_fields_present(&sample, flags);
+            ensure_sample_fields_present(sample, flags);
 
             if let Some(duration) = sample.duration {
-                v.write_u32::<BigEndian>(duration)?;
+                writer.write_u32::<BigEndian>(duration)?;
             }
 
             if let Some(size) = sample.size {
-                v.write_u32::<BigEndian>(size)?;
+                writer.write_u32::<BigEndian>(size)?;
             }
 
             if let Some(flags) = sample.flags {
-                v.write_u32::<BigEndian>(flags)?;
+                writer.write_u32::<BigEndian>(flags)?;
             }
 
             if let Some(composition_time_offset) = sample.composition_time_offset {
-                v.write_i32::<BigEndian>(composition_time_offset)?;
+                writer.write_i32::<BigEndian>(composition_time_offset)?;
             }
         }
 
-        assert_eq!(v.len() as u64, self.content_size());
+        Ok(())
+    }
 
-        writer.put_slice(&v);
+    pub fn total_size(&self) -> u64 {
+        self.full_box.size(self.size())
+    }
 
-        Ok(())
+    fn size(&self) -> u64 {
+        let flags = self.flags_from_fields();
+
+        let mut size = 0;
+
+        size += size_of::<u32>() as u64; // sample_count
+
+        if flags.contains(TrackFragmentRunFlags::DATA_OFFSET_PRESENT) {
+            size += size_of::<i32>() as u64; // data_offset
+        }
+
+        if flags.contains(TrackFragmentRunFlags::FIRST_SAMPLE_FLAGS_PRESENT) {
+            size += size_of::<u32>() as u64; // first_sample_flags
+        }
+
+        size += Self::sample_size(flags) * self.samples.len() as u64;
+
+        size
     }
 }
 
@@ -177,3 +292,59 @@ fn ensure_sample_fields_present(sample: &TrackFragmentSample, flags: TrackFragme
         composition_time_offset_is_present
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{read_test_box, round_trip, test_box};
+
+    fn sample(size: u32, composition_time_offset: i32) -> TrackFragmentSample {
+        TrackFragmentSample {
+            duration: None,
+            size: Some(size),
+            flags: None,
+            composition_time_offset: Some(composition_time_offset),
+        }
+    }
+
+    #[test]
+    fn optional_fields() {
+        let trun = TrackFragmentRunBox::new(
+            Some(-8),
+            Some(0x0200_0000),
+            vec![sample(10, -2), sample(20, 4)],
+        );
+        let (bytes, trun) = round_trip(trun);
+
+        // negative composition offsets need version 1
+        assert_eq!(bytes[8], 1);
+        assert_eq!(trun.data_offset, Some(-8));
+        assert_eq!(trun.first_sample_flags, Some(0x0200_0000));
+        assert_eq!(
+            trun.samples
+                .iter()
+                .map(|s| (s.duration, s.size, s.composition_time_offset))
+                .collect::<Vec<_>>(),
+            [(None, Some(10), Some(-2)), (None, Some(20), Some(4))]
+        );
+    }
+
+    #[test]
+    fn samples_past_end() {
+        // two sizes are announced, but only one follows
+        let bytes = test_box(b"trun", &[0, 0, 2, 0, 0, 0, 0, 2, 0, 0, 0, 10]);
+        let trun = read_test_box::<TrackFragmentRunBox>(&bytes).unwrap();
+
+        assert_eq!(trun.samples.len(), 1);
+    }
+
+    #[test]
+    fn empty_samples() {
+        // samples without fields take no bytes, so their count is capped
+        let bytes = test_box(b"trun", &[0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]);
+        let trun = read_test_box::<TrackFragmentRunBox>(&bytes).unwrap();
+
+        assert_eq!(trun.samples.len(), MAX_EMPTY_ENTRIES as usize);
+    }
+}
@@ -0,0 +1,66 @@
+use crate::*;
+
+use super::{chpl::ChapterListBox, meta::MetaBox};
+
+use std::io::Write;
+
+pub struct UserDataBox {
+    boks: Boks,
+    pub meta: Option<MetaBox>,
+    pub chpl: Option<ChapterListBox>,
+}
+
+impl UserDataBox {
+    pub fn new(meta: Option<MetaBox>, chpl: Option<ChapterListBox>) -> Self {
+        UserDataBox {
+            boks: Boks::new(*b"udta"),
+            meta,
+            chpl,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let boks = Boks::read_named(reader, *b"udta")?;
+
+        let mut meta = None;
+        let mut chpl = None;
+
+        let iter = BoksIterator::new(reader, boks.remaining_size());
+        while let Some((pos, boks)) = iter.next(reader)? {
+            debug!("{}: {:?}", pos, boks);
+
+            match &boks.name {
+                b"meta" => meta = Some(MetaBox::read(reader).context(pos, &boks)?),
+                b"chpl" => chpl = Some(ChapterListBox::read(reader).context(pos, &boks)?),
+                _ => {
+                    diagnostic!(at pos, "skipping udta box {:?}", boks);
+                    skip(reader, boks.size)?;
+                }
+            }
+        }
+
+        Ok(UserDataBox { boks, meta, chpl })
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.boks.write(writer, self.total_size())?;
+
+        if let Some(meta) = self.meta {
+            meta.write(writer)?;
+        }
+        if let Some(chpl) = self.chpl {
+            chpl.write(writer)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.boks.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        self.meta.as_ref().map(|b| b.total_size()).unwrap_or(0)
+            + self.chpl.as_ref().map(|b| b.total_size()).unwrap_or(0)
+    }
+}
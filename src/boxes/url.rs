@@ -4,19 +4,51 @@ use crate::*;
 
 use std::io::Write;
 
+/// The media data is in the same file as the movie.
+pub const SELF_CONTAINED: u32 = 0x000001;
+
+// strings are null-terminated, some writers omit the terminator
+pub(crate) fn read_string(reader: &mut dyn Buffered, size: u64) -> Result<String, Mp4BoxError> {
+    let mut bytes = read_bytes(reader, size)?;
+
+    if let Some(end) = bytes.iter().position(|b| *b == 0) {
+        bytes.truncate(end);
+    }
+
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
 pub struct DataEntryUrlBox {
     full_box: FullBox,
     pub location: String,
 }
 
 impl DataEntryUrlBox {
+    /// An empty location refers to the file containing the movie.
     pub fn new(location: String) -> Self {
+        let flags = if location.is_empty() {
+            SELF_CONTAINED
+        } else {
+            0
+        };
+
         DataEntryUrlBox {
-            full_box: FullBox::new(*b"url ", 0, 0x000001),
+            full_box: FullBox::new(*b"url ", 0, flags),
             location,
         }
     }
 
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"url ")?;
+        let location = read_string(reader, full_box.remaining_size())?;
+
+        Ok(DataEntryUrlBox { full_box, location })
+    }
+
+    pub fn is_self_contained(&self) -> bool {
+        self.full_box.flags & SELF_CONTAINED != 0
+    }
+
     pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
         self.full_box.write(writer, self.total_size())?;
 
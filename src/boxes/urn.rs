@@ -0,0 +1,65 @@
+use byteorder::WriteBytesExt;
+
+use crate::*;
+
+use super::url::SELF_CONTAINED;
+
+use std::io::Write;
+
+pub struct DataEntryUrnBox {
+    full_box: FullBox,
+    pub name: String,
+    pub location: String,
+}
+
+impl DataEntryUrnBox {
+    pub fn new(name: String, location: String) -> Self {
+        DataEntryUrnBox {
+            full_box: FullBox::new(*b"urn ", 0, 0),
+            name,
+            location,
+        }
+    }
+
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"urn ")?;
+
+        let bytes = read_bytes(reader, full_box.remaining_size())?;
+
+        // name and the optional location are both null-terminated
+        let mut strings = bytes
+            .split(|b| *b == 0)
+            .map(|s| String::from_utf8_lossy(s).into_owned());
+        let name = strings.next().unwrap_or_default();
+        let location = strings.next().unwrap_or_default();
+
+        Ok(DataEntryUrnBox {
+            full_box,
+            name,
+            location,
+        })
+    }
+
+    pub fn is_self_contained(&self) -> bool {
+        self.full_box.flags & SELF_CONTAINED != 0
+    }
+
+    pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        self.full_box.write(writer, self.total_size())?;
+
+        writer.write_all(self.name.as_bytes())?;
+        writer.write_u8(0)?;
+        writer.write_all(self.location.as_bytes())?;
+        writer.write_u8(0)?;
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.full_box.size(self.size())
+    }
+
+    fn size(&self) -> u64 {
+        self.name.len() as u64 + 1 + self.location.len() as u64 + 1
+    }
+}
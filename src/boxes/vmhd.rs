@@ -1,3 +1,5 @@
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
 use crate::*;
 
 use std::io::Write;
@@ -5,22 +7,43 @@ use std::mem::size_of;
 
 pub struct VideoMediaHeaderBox {
     full_box: FullBox,
+    pub graphics_mode: u16,
+    pub opcolor: [u16; 3],
 }
 
 impl VideoMediaHeaderBox {
     pub fn new() -> Self {
         VideoMediaHeaderBox {
-            full_box: FullBox::new(*b"vmhd", 0, 0),
+            full_box: FullBox::new(*b"vmhd", 0, 1),
+            graphics_mode: 0,
+            opcolor: [0; 3],
         }
     }
 
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let full_box = FullBox::read_named(reader, *b"vmhd")?;
+
+        let graphics_mode = reader.read_u16::<BigEndian>()?;
+
+        let mut opcolor = [0u16; 3];
+        reader.read_u16_into::<BigEndian>(&mut opcolor)?;
+
+        Ok(VideoMediaHeaderBox {
+            full_box,
+            graphics_mode,
+            opcolor,
+        })
+    }
+
     pub fn write(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
         debug!("vmhd: {}", self.total_size());
         self.full_box.write(writer, self.total_size())?;
 
-        let contents = [0u8; 8];
+        writer.write_u16::<BigEndian>(self.graphics_mode)?;
 
-        writer.write_all(&contents)?;
+        for color in &self.opcolor {
+            writer.write_u16::<BigEndian>(*color)?;
+        }
 
         Ok(())
     }
@@ -1,13 +1,18 @@
-use std::io::SeekFrom;
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, Cursor, ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
 
 use av_data::{
     packet::Packet,
-    params::{CodecParams, MediaKind, VideoInfo},
+    params::{AudioInfo, CodecParams, MediaKind, VideoInfo},
     pixel::{ColorPrimaries, Formaton, FromPrimitive, MatrixCoefficients, TransferCharacteristic},
     timeinfo::TimeInfo,
 };
 use av_format::{
-    buffer::Buffered,
+    buffer::{AccReader, Buffered},
     common::GlobalInfo,
     demuxer::{Demuxer, Descr, Descriptor, Event},
     rational::Rational64,
@@ -20,12 +25,18 @@ use log::*;
 
 use crate::boxes::*;
 use crate::boxes::codec::*;
-use crate::{skip, Boks, Mp4BoxError};
-
-use stbl::ChunkOffsets;
-use stsz::SampleSizes;
+use crate::diagnostics::{self, Diagnostic, ParseMode};
+use crate::index::SampleIndex;
+use crate::metadata::{decode_text_sample, Chapter, Metadata};
+use crate::timecode::Timecode;
+use crate::{
+    goto, read_bytes, skip, Boks, BoxContext, BoxName, BoxPrint, Mp4BoxError, PacketInfo,
+    SharedData,
+};
 
-use std::sync::Arc;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 struct VpxCodecData {
     format: Formaton,
@@ -40,24 +51,86 @@ fn get_mp4v_codec_id(boks: &mp4v::Mpeg4VideoSampleEntryBox) -> Result<&'static s
     }
 }
 
-fn read_sample(buf: &mut dyn Buffered, sample: SampleRef) -> Result<Sample, Mp4BoxError> {
+fn input_size(input: &mut dyn Buffered) -> Result<u64, Mp4BoxError> {
+    let current = input.seek(SeekFrom::Current(0))?;
+    let size = input.seek(SeekFrom::End(0))?;
+    goto(input, current)?;
+
+    Ok(size)
+}
+
+// reads the sample into the start of `data`, which has room for its padding
+fn read_sample(
+    buf: &mut dyn Buffered,
+    sample: SampleRef,
+    mut data: Vec<u8>,
+) -> Result<Sample, Mp4BoxError> {
     buf.seek(SeekFrom::Start(sample.data_offset))?;
+    buf.read_exact(&mut data[..sample.data_length as usize])?;
 
-    let len = sample.data_length as usize + 0;
-    let padded_len = ((len + 8 + 7) / 8) * 8;
-    println!("offset={} len={}, padded={}", sample.data_offset, sample.data_length, padded_len);
-    let mut data = vec![0u8; padded_len];
-    buf.read_exact(&mut data[..len])?;
-
-    Ok(Sample {
-        time: sample.time,
-        duration: sample.duration,
-        data,
-        keyframe: sample.keyframe,
-    })
+    Ok(sample.with_data(data))
+}
+
+/// Source of packet buffers, so that allocations can be reused once the
+/// caller is done with a packet.
+pub trait BufferPool: Send + Sync {
+    /// Returns an empty buffer with room for at least `capacity` bytes.
+    fn get(&self, capacity: usize) -> Vec<u8>;
+
+    /// Takes back the buffer of a packet that is no longer needed.
+    fn put(&self, _buffer: Vec<u8>) {}
+}
+
+/// Allocates a new buffer for every packet.
+#[derive(Default)]
+pub struct HeapPool;
+
+impl BufferPool for HeapPool {
+    fn get(&self, capacity: usize) -> Vec<u8> {
+        Vec::with_capacity(capacity)
+    }
+}
+
+/// Keeps up to `max_buffers` returned buffers around for reuse.
+pub struct RecyclingPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    max_buffers: usize,
+}
+
+impl RecyclingPool {
+    pub fn new(max_buffers: usize) -> Self {
+        RecyclingPool {
+            buffers: Mutex::new(Vec::with_capacity(max_buffers)),
+            max_buffers,
+        }
+    }
+}
+
+impl BufferPool for RecyclingPool {
+    fn get(&self, capacity: usize) -> Vec<u8> {
+        let mut buffers = self.buffers.lock().unwrap_or_else(|e| e.into_inner());
+
+        // prefer a buffer that does not need to grow
+        let mut buffer = match buffers.iter().position(|b| b.capacity() >= capacity) {
+            Some(idx) => buffers.swap_remove(idx),
+            None => buffers.pop().unwrap_or_default(),
+        };
+
+        buffer.clear();
+        buffer.reserve(capacity);
+        buffer
+    }
+
+    fn put(&self, buffer: Vec<u8>) {
+        let mut buffers = self.buffers.lock().unwrap_or_else(|e| e.into_inner());
+
+        if buffers.len() < self.max_buffers {
+            buffers.push(buffer);
+        }
+    }
 }
 
-fn get_vpx_codec_data(vpcc: &vpcc::VpCodecConfigurationBox) -> VpxCodecData {
+fn get_vpx_codec_data(vpcc: &vpcc::VpCodecConfigurationBox) -> Result<VpxCodecData, Mp4BoxError> {
     use av_data::pixel::formats;
 
     let profile = vpcc.config.profile;
@@ -83,31 +156,64 @@ fn get_vpx_codec_data(vpcc: &vpcc::VpCodecConfigurationBox) -> VpxCodecData {
         (1, 8, 2) => *formats::YUV444,
         (3, 10, 2) => *formats::YUV444_10,
 
-        _ => panic!(
-            "unknown chroma subsampling: {} {} {}",
-            profile, bit_depth, chroma_subsampling
-        ), // TODO: error
+        _ => {
+            warn!(
+                "unknown chroma subsampling: {} {} {}",
+                profile, bit_depth, chroma_subsampling
+            );
+            return Err(Mp4BoxError::UnsupportedSampleEntry(BoxPrint(*b"vp09")));
+        }
     };
 
-    // TODO: error
-    base.primaries = ColorPrimaries::from_u8(vpcc.config.colour_primaries).unwrap();
-    base.xfer = TransferCharacteristic::from_u8(vpcc.config.transfer_characteristics).unwrap();
-    base.matrix = MatrixCoefficients::from_u8(vpcc.config.matrix_coefficients).unwrap();
+    // reserved values keep the defaults of the format
+    if let Some(primaries) = ColorPrimaries::from_u8(vpcc.config.colour_primaries) {
+        base.primaries = primaries;
+    }
+    if let Some(xfer) = TransferCharacteristic::from_u8(vpcc.config.transfer_characteristics) {
+        base.xfer = xfer;
+    }
+    if let Some(matrix) = MatrixCoefficients::from_u8(vpcc.config.matrix_coefficients) {
+        base.matrix = matrix;
+    }
 
-    VpxCodecData {
+    Ok(VpxCodecData {
         format: base,
         extradata: data.to_vec(),
+    })
+}
+
+fn get_mp4a_codec_id(boks: &mp4a::Mpeg4AudioSampleEntryBox) -> Option<&'static str> {
+    let ty = boks
+        .esds()?
+        .descriptor
+        .decoder_description
+        .object_type_indication;
+
+    match ty {
+        0x40 | 0x66 | 0x67 | 0x68 => Some("aac"),
+        0x69 | 0x6b => Some("mp3"),
+        0xa5 => Some("ac3"),
+        0xa6 => Some("eac3"),
+        0xa9 => Some("dts"),
+        0xad => Some("opus"),
+        0xdd => Some("vorbis"),
+        _ => None,
     }
 }
 
 impl stsd::SampleEntry {
-    fn as_codec_params(&self) -> Result<CodecParams, Mp4BoxError> {
+    // the handler type decides the kind of entries without dedicated support
+    pub(crate) fn as_codec_params(
+        &self,
+        handler: hdlr::HandlerType,
+        timescale: u32,
+    ) -> Result<CodecParams, Mp4BoxError> {
         match self {
             stsd::SampleEntry::Vp9(entry) => {
                 let width = entry.visual_sample_entry.width as usize;
                 let height = entry.visual_sample_entry.height as usize;
 
-                let codec_data = get_vpx_codec_data(&entry.vpcc);
+                let codec_data = get_vpx_codec_data(&entry.vpcc)?;
 
                 Ok(CodecParams {
                     kind: Some(MediaKind::Video(VideoInfo {
@@ -126,8 +232,11 @@ impl stsd::SampleEntry {
                 let width = entry.visual_sample_entry.width as usize;
                 let height = entry.visual_sample_entry.height as usize;
 
-                let mut parameter_sets = vec![0, 0, 1];
-                parameter_sets.extend(&entry.avcc.config.sequence_parameter_sets[0].0);
+                let mut parameter_sets = Vec::new();
+                if let Some(sps) = entry.avcc.config.sequence_parameter_sets.first() {
+                    parameter_sets.extend(&[0, 0, 1][..]);
+                    parameter_sets.extend(&sps.0);
+                }
                 if let Some(pps) = entry.avcc.config.picture_parameter_sets.get(0) {
                     parameter_sets.extend(&[0, 0, 1][..]);
                     parameter_sets.extend(&pps.0);
@@ -156,113 +265,338 @@ impl stsd::SampleEntry {
                     delay: 0,
                 })
             }
+            stsd::SampleEntry::Mpeg4Audio(mp4a) => {
+                let entry = &mp4a.audio_sample_entry;
+
+                let extradata = mp4a
+                    .esds()
+                    .map(|esds| esds.descriptor.decoder_description.decoder_specific.clone())
+                    .filter(|data| !data.is_empty());
+
+                let bit_rate = mp4a
+                    .esds()
+                    .map(|esds| esds.descriptor.decoder_description.avg_bitrate as usize)
+                    .unwrap_or(0);
+
+                Ok(CodecParams {
+                    kind: Some(MediaKind::Audio(AudioInfo {
+                        rate: entry.sample_rate_hz() as usize,
+                        map: None,
+                        format: None,
+                    })),
+                    codec_id: get_mp4a_codec_id(mp4a).map(Into::into),
+                    extradata,
+                    bit_rate,
+                    convergence_window: 0,
+                    delay: 0,
+                })
+            }
+            stsd::SampleEntry::Timecode(_) => Ok(CodecParams {
+                kind: None,
+                codec_id: Some("tmcd".into()),
+                extradata: None,
+                bit_rate: 0,
+                convergence_window: 0,
+                delay: 0,
+            }),
+            stsd::SampleEntry::Unknown(entry) => {
+                let kind = match handler {
+                    hdlr::HandlerType::Video => Some(MediaKind::Video(VideoInfo {
+                        width: 0,
+                        height: 0,
+                        format: None,
+                    })),
+                    hdlr::HandlerType::Sound => Some(MediaKind::Audio(AudioInfo {
+                        rate: timescale as usize,
+                        map: None,
+                        format: None,
+                    })),
+                    _ => None,
+                };
+
+                // the name of the entry identifies the format, e.g. tx3g or wvtt
+                let name = entry.sample_entry.name();
+                let codec_id = String::from_utf8_lossy(&name).trim_end().to_owned();
+
+                Ok(CodecParams {
+                    kind,
+                    codec_id: Some(codec_id),
+                    extradata: None,
+                    bit_rate: 0,
+                    convergence_window: 0,
+                    delay: 0,
+                })
+            }
         }
     }
 }
 
+#[derive(Clone, Copy)]
 struct SampleRef {
+    // number in the sample table, None for samples from fragments
+    number: Option<u32>,
     time: u64,
+    composition_offset: i32,
     duration: u32,
     data_offset: u64,
     data_length: u32,
     keyframe: bool,
+    dependency: Option<sdtp::SampleDependency>,
+}
+
+impl SampleRef {
+    fn with_data(self, data: Vec<u8>) -> Sample {
+        Sample {
+            number: self.number,
+            time: self.time,
+            composition_offset: self.composition_offset,
+            duration: self.duration,
+            data,
+            shared_data: None,
+            keyframe: self.keyframe,
+            dependency: self.dependency,
+        }
+    }
 }
 
 struct Sample {
+    number: Option<u32>,
     time: u64,
+    composition_offset: i32,
     duration: u32,
     data: Vec<u8>,
+    shared_data: Option<SharedData>,
     keyframe: bool,
+    dependency: Option<sdtp::SampleDependency>,
+}
+
+#[derive(Clone, Copy, Default)]
+struct FragmentDefaults {
+    sample_description_index: u32,
+    duration: u32,
+    size: u32,
+    flags: u32,
+}
+
+impl From<&trex::TrackExtendsBox> for FragmentDefaults {
+    fn from(trex: &trex::TrackExtendsBox) -> Self {
+        FragmentDefaults {
+            sample_description_index: trex.default_sample_description_index,
+            duration: trex.default_sample_duration,
+            size: trex.default_sample_size,
+            flags: trex.default_sample_flags,
+        }
+    }
+}
+
+// shift applied to decode times, so that no sample is presented before it is
+// decoded
+fn get_dts_shift(
+    ctts: Option<&ctts::CompositionOffsetBox>,
+    cslg: Option<&cslg::CompositionToDecodeBox>,
+) -> i64 {
+    if let Some(cslg) = cslg {
+        return cslg.composition_to_dts_shift.max(0);
+    }
+
+    ctts.iter()
+        .flat_map(|ctts| &ctts.entries)
+        .map(|o| -(o.offset as i64))
+        .max()
+        .unwrap_or(0)
+        .max(0)
+}
+
+fn rescale(value: u64, from: u32, to: u32) -> i64 {
+    (value as u128 * to as u128 / from.max(1) as u128) as i64
 }
 
-// range = base..(delta*count)
-struct SampleTimes {
-    base: u64,
-    delta: u32,
-    count: u32,
+// all times in media timescale
+struct EditSegment {
+    presentation_time: i64,
+    media_time: i64,
+    duration: Option<i64>,
+    rate: i32,
 }
 
-// range = sample_offset..sample_count
-struct Chunk {
-    sample_count: u32,
-    chunk_count: Option<u32>,
-    _sample_description_index: u32,
+impl EditSegment {
+    fn contains(&self, time: i64) -> bool {
+        if self.rate == 0 {
+            // dwell, a single media time is shown for the whole segment
+            return time == self.media_time;
+        }
+
+        let media_duration = self
+            .duration
+            .map(|d| d.saturating_mul(self.rate as i64) / 0x10000);
+
+        time >= self.media_time
+            && media_duration.map_or(true, |d| time < self.media_time.saturating_add(d))
+    }
+
+    fn map(&self, time: i64) -> i64 {
+        if self.rate == 0 {
+            return self.presentation_time;
+        }
+
+        let media_offset = time.saturating_sub(self.media_time);
+        let offset = media_offset.saturating_mul(0x10000) / self.rate as i64;
+
+        self.presentation_time.saturating_add(offset)
+    }
 }
 
-fn get_sample_times(stts: stts::TimeToSampleBox) -> Vec<SampleTimes> {
-    let mut base = 0;
+struct EditList {
+    segments: Vec<EditSegment>,
+}
 
-    let sample_count = stts.entries.len();
-    let mut times = Vec::with_capacity(sample_count);
+impl EditList {
+    fn new(edts: Option<&edts::EditBox>, movie_timescale: u32, media_timescale: u32) -> EditList {
+        let mut segments = Vec::new();
+        let mut presentation_time: i64 = 0;
+
+        let entries = edts
+            .and_then(|edts| edts.elst.as_ref())
+            .map(|elst| &elst.entries[..])
+            .unwrap_or(&[]);
+
+        for entry in entries {
+            let duration = rescale(entry.segment_duration, movie_timescale, media_timescale);
+
+            if !entry.is_empty() {
+                segments.push(EditSegment {
+                    presentation_time,
+                    media_time: entry.media_time,
+                    // zero duration means the edit extends to the end of the media
+                    duration: if entry.segment_duration == 0 {
+                        None
+                    } else {
+                        Some(duration)
+                    },
+                    rate: entry.media_rate(),
+                });
+            }
 
-    for entry in stts.entries {
-        let count = entry.count;
-        let delta = entry.delta;
+            presentation_time = presentation_time.saturating_add(duration);
+        }
 
-        times.push(SampleTimes { base, delta, count });
+        EditList { segments }
+    }
 
-        base += count as u64 * delta as u64;
+    fn start(&self) -> Option<u64> {
+        self.segments.first().map(|s| s.presentation_time as u64)
     }
 
-    times
+    // maps a media composition time to the presentation timeline, and tells
+    // whether the time is covered by any edit
+    fn map(&self, time: i64) -> (i64, bool) {
+        let first = match self.segments.first() {
+            Some(first) => first,
+            None => return (time, true),
+        };
+
+        match self.segments.iter().find(|s| s.contains(time)) {
+            Some(segment) => (segment.map(time), true),
+            None => (first.map(time), false),
+        }
+    }
 }
 
 struct Track {
     id: u32,
+    track_id: u32,
     index: usize,
     sample_entry: stsd::SampleEntry,
-    stsc: Vec<Chunk>,
-    chunk_offsets: ChunkOffsets,
-    times: Vec<SampleTimes>,
-    sizes: SampleSizes,
-    sync_samples: Option<Vec<u32>>,
+    samples: SampleIndex,
+    dts_shift: i64,
+    edit_list: EditList,
+    references: Vec<tref::TrackReferenceTypeBox>,
+
+    handler_type: BoxName,
+    handler_name: String,
+    language: [u8; 3],
+    enabled: bool,
+    alternate_group: i16,
+    // disabled tracks are never read
+    selected: bool,
+    trick_play: TrickPlay,
+    // URL of the media data when it is not in the movie file
+    data_location: Option<String>,
+    // index into the opened data sources, None for the movie file
+    data_source: Option<usize>,
+    // the external media data could not be opened
+    missing_data: bool,
+    // start of a timecode track, from its first sample
+    timecode: Option<Timecode>,
+
+    // samples from movie fragments, queued after the ones in moov
+    fragment_defaults: FragmentDefaults,
+    fragment_samples: VecDeque<SampleRef>,
+    // sub-sample layouts of the queued fragment samples
+    fragment_subsamples: VecDeque<Vec<subs::SubSampleLayout>>,
+    fragment_time: u64,
 
     stream: Stream,
     timebase: Rational64,
     duration: u64,
 
-    // TODO: put all "current" state into own struct?
-    current_sync_index: usize,
-    current_stsc: usize,
-    current_chunk_sample_offset: u64,
-    stsc_chunk_index: usize,
-    stsc_sample_index: usize,
-    current_times: usize,
-    time_index: usize,
-    current_sample: u64,
+    current_sample: u32,
+    // samples before it are decoded but not presented, after a seek
+    preroll_end: u32,
 }
 
 impl Track {
-    fn from_trak(id: u32, trak: trak::TrackBox) -> Result<Self, Mp4BoxError> {
+    fn from_trak(id: u32, trak: trak::TrackBox, movie_timescale: u32) -> Result<Self, Mp4BoxError> {
         let index = id as usize;
 
-        let sample_entry = trak.mdia.minf.stbl.stsd.entries.into_iter().next().unwrap();
-        let timebase = Rational64::new(1, trak.mdia.mdhd.timescale as i64);
+        let mut stbl = trak.mdia.minf.stbl;
+        let sample_entry = std::mem::take(&mut stbl.stsd.entries)
+            .into_iter()
+            .next()
+            .ok_or(Mp4BoxError::NoSampleEntries(trak.tkhd.track_id))?;
+        let data_entry = trak
+            .mdia
+            .minf
+            .dinf
+            .as_ref()
+            .and_then(|dinf| dinf.dref.entry(sample_entry.data_reference_index()));
+        let (data_location, missing_data) = match data_entry {
+            Some(entry) if !entry.is_self_contained() => match entry.location() {
+                Some(location) => (Some(location.to_string()), false),
+                None => {
+                    diagnostic!("track {}: unsupported data reference", trak.tkhd.track_id);
+                    (None, true)
+                }
+            },
+            _ => (None, false),
+        };
+        let timebase = Rational64::new(1, trak.mdia.mdhd.timescale.max(1) as i64);
         let duration = trak.tkhd.duration;
-        let sync_samples = trak.mdia.minf.stbl.stss.map(|s| s.sync_samples);
-
-        let mut chunks: Vec<Chunk> =
-            Vec::with_capacity(trak.mdia.minf.stbl.stsc.entries.len() as usize);
-
-        for (idx, entry) in trak.mdia.minf.stbl.stsc.entries.iter().enumerate() {
-            let first = entry.first_chunk;
-
-            if idx >= 1 {
-                chunks[idx - 1].chunk_count = Some(first);
-            }
-
-            chunks.push(Chunk {
-                sample_count: entry.samples_per_chunk,
-                chunk_count: None,
-                _sample_description_index: entry.sample_description_index,
-            });
-        }
+        let track_id = trak.tkhd.track_id;
+        let flags = trak.tkhd.flags();
+        let handler = trak.mdia.hdlr.handler();
+        let dts_shift = get_dts_shift(stbl.ctts.as_ref(), stbl.cslg.as_ref());
+        let edit_list = EditList::new(
+            trak.edts.as_ref(),
+            movie_timescale,
+            trak.mdia.mdhd.timescale,
+        );
+
+        let samples = SampleIndex::new(stbl);
+        let fragment_time = samples.duration();
+
+        debug!(
+            "track {}: {} samples, index uses {} bytes",
+            track_id,
+            samples.sample_count(),
+            samples.heap_size()
+        );
 
         let stream = Stream {
             id: id as isize,
             index: index,
-            params: sample_entry.as_codec_params()?,
-            start: None,
+            params: sample_entry.as_codec_params(handler, trak.mdia.mdhd.timescale)?,
+            start: edit_list.start(),
             duration: Some(duration),
             timebase: timebase,
             user_private: None,
@@ -270,258 +604,1973 @@ impl Track {
 
         Ok(Track {
             id,
+            track_id,
             index,
             sample_entry,
-            stsc: chunks,
-            chunk_offsets: trak.mdia.minf.stbl.chunk_offsets,
-            times: get_sample_times(trak.mdia.minf.stbl.stts),
-            sizes: trak.mdia.minf.stbl.stsz.sample_sizes,
-            sync_samples,
+            samples,
+            dts_shift,
+            edit_list,
+            references: trak.tref.map(|tref| tref.references).unwrap_or_default(),
+
+            handler_type: trak.mdia.hdlr.handler_type.to_be_bytes(),
+            handler_name: trak.mdia.hdlr.name,
+            language: trak.mdia.mdhd.language,
+            // preview-only tracks are not part of the presentation
+            enabled: flags.contains(tkhd::TrackHeaderFlags::ENABLED)
+                && (flags.contains(tkhd::TrackHeaderFlags::IN_MOVIE)
+                    || !flags.contains(tkhd::TrackHeaderFlags::IN_PREVIEW)),
+            alternate_group: trak.tkhd.alternate_group,
+            selected: true,
+            trick_play: TrickPlay::Off,
+            data_location,
+            data_source: None,
+            missing_data,
+            timecode: None,
+
+            fragment_defaults: FragmentDefaults::default(),
+            fragment_samples: VecDeque::new(),
+            fragment_subsamples: VecDeque::new(),
+            fragment_time,
 
             stream,
 
             timebase,
             duration,
 
-            current_sync_index: 0,
-            current_stsc: 0,
-            current_chunk_sample_offset: 0,
-            stsc_chunk_index: 0,
-            stsc_sample_index: 0,
-            current_times: 0,
-            time_index: 0,
             current_sample: 0,
+            preroll_end: 0,
         })
     }
 
-    pub fn current_sample(&self) -> Option<SampleRef> {
-        let keyframe = self
-            .sync_samples
-            .as_ref()
-            .map(|s| s[self.current_sync_index] as u64 == self.current_sample + 1)
-            .unwrap_or(true);
-        let _chunk = &self.stsc.get(self.current_stsc)?;
-        let data_offset =
-            self.current_chunk_sample_offset + self.chunk_offsets.get(self.current_stsc)?;
-
-        let times = &self.times.get(self.current_times)?;
-        let time = times.base + ((self.time_index as u32) * times.delta) as u64;
-        let duration = times.delta;
-
-        let data_length = match &self.sizes {
-            SampleSizes::Constant(size) => *size,
-            SampleSizes::Variable(ref sizes) => *sizes.get(self.current_sample as usize)?,
-        };
+    fn next_sample(&self) -> Option<SampleRef> {
+        if !self.selected || self.missing_data {
+            return None;
+        }
 
-        Some(SampleRef {
-            time,
-            duration,
-            data_offset,
-            data_length,
-            keyframe,
-        })
+        self.current_sample()
+            .or_else(|| self.fragment_samples.front().copied())
     }
 
-    pub fn advance_sample(&mut self) {
-        let chunk = &self.stsc[self.current_stsc];
-        let times = &self.times[self.current_times];
+    // whether the sample is returned in the current trick play mode
+    fn keeps(&self, sample: &SampleRef) -> bool {
+        match self.trick_play {
+            TrickPlay::Off => true,
+            TrickPlay::ReferenceFrames => !sample.dependency.map_or(false, |d| d.is_disposable()),
+            TrickPlay::Keyframes => sample.keyframe,
+        }
+    }
+
+    // moves past the samples dropped in trick play mode
+    fn skip_dropped(&mut self) {
+        while let Some(sample) = self.next_sample() {
+            if self.keeps(&sample) {
+                break;
+            }
+
+            self.advance();
+        }
+    }
 
-        self.current_chunk_sample_offset += match &self.sizes {
-            SampleSizes::Constant(size) => *size,
-            SampleSizes::Variable(ref sizes) => sizes[self.current_sample as usize],
-        } as u64;
+    fn advance(&mut self) {
+        if self.current_sample().is_some() {
+            self.advance_sample();
+        } else {
+            self.fragment_samples.pop_front();
+            self.fragment_subsamples.pop_front();
+        }
+    }
 
-        self.time_index += 1;
-        if self.time_index >= times.count as usize {
-            self.time_index = 0;
-            self.current_times += 1;
+    fn matches(&self, selector: &TrackSelector) -> bool {
+        match selector {
+            TrackSelector::Index(index) => self.index == *index,
+            TrackSelector::TrackId(track_id) => self.track_id == *track_id,
+            TrackSelector::Handler(handler_type) => self.handler_type == *handler_type,
+            TrackSelector::Language(language) => self.language == *language,
         }
+    }
 
-        self.stsc_sample_index += 1;
+    // queues the samples of a track fragment, returns the end of its data
+    fn add_track_fragment(&mut self, base_data_offset: u64, traf: traf::TrackFragmentBox) -> u64 {
+        let tfhd = &traf.tfhd;
+        let defaults = self.fragment_defaults;
 
-        // if we are past samples for a single chunk in a stsc entry, move on
-        // to next chunk
-        if self.stsc_sample_index >= chunk.sample_count as usize {
-            self.stsc_sample_index = 0;
-            self.stsc_chunk_index += 1;
+        let default_duration = tfhd.default_sample_duration.unwrap_or(defaults.duration);
+        let default_size = tfhd.default_sample_size.unwrap_or(defaults.size);
+        let default_flags = tfhd.default_sample_flags.unwrap_or(defaults.flags);
 
-            // if we are past chunks in the stsc entry, move to next entry
-            if let Some(chunk_count) = chunk.chunk_count {
-                if self.stsc_chunk_index >= chunk_count as usize {
-                    self.current_stsc += 1;
-                    self.current_chunk_sample_offset = 0;
-                }
-            }
+        if let Some(tfdt) = &traf.base_media_decode_time {
+            self.fragment_time = tfdt.base_media_decode_time;
         }
 
-        // always advance one sample
-        self.current_sample += 1;
+        let mut layouts: Vec<_> = traf.subs.iter().flat_map(|subs| subs.layouts()).collect();
+        layouts.sort_by_key(|(number, _)| *number);
+        let mut layouts = layouts.into_iter().peekable();
+        let mut number = 0u32;
 
-        if let Some(sync_samples) = self.sync_samples.as_ref() {
-            if self.current_sample > sync_samples[self.current_sync_index] as u64
-                && self.current_sync_index < sync_samples.len() - 1
-            {
-                self.current_sync_index += 1;
+        let mut data_offset = base_data_offset;
+
+        for trun in &traf.track_runs {
+            // without an explicit offset, runs follow each other contiguously
+            if let Some(offset) = trun.data_offset {
+                data_offset = (base_data_offset as i64).wrapping_add(offset as i64) as u64;
+            }
+
+            for (i, sample) in trun.samples.iter().enumerate() {
+                let duration = sample.duration.unwrap_or(default_duration);
+                let size = sample.size.unwrap_or(default_size);
+                let flags = match (i, trun.first_sample_flags) {
+                    (0, Some(flags)) => flags,
+                    _ => sample.flags.unwrap_or(default_flags),
+                };
+
+                // subs numbers the samples of the fragment from 1
+                number += 1;
+                let mut subsamples = Vec::new();
+                while let Some((_, layout)) = layouts.next_if(|(n, _)| *n <= number) {
+                    subsamples.push(layout);
+                }
+
+                if !self.selected {
+                    data_offset = data_offset.saturating_add(size as u64);
+                    continue;
+                }
+
+                self.fragment_samples.push_back(SampleRef {
+                    number: None,
+                    time: self.fragment_time,
+                    composition_offset: sample.composition_time_offset.unwrap_or(0),
+                    duration,
+                    data_offset,
+                    data_length: size,
+                    keyframe: !trun::SampleFlags(flags).is_non_sync_sample(),
+                    dependency: Some(trun::SampleFlags(flags).into()),
+                });
+                self.fragment_subsamples.push_back(subsamples);
+
+                self.fragment_time = self.fragment_time.saturating_add(duration as u64);
+                data_offset = data_offset.saturating_add(size as u64);
             }
         }
+
+        data_offset
     }
-}
 
-pub struct Mp4Demuxer {
-    tracks: Vec<Track>,
-}
+    // the sample `n` places after the next one, where samples from movie
+    // fragments follow the ones in moov
+    fn upcoming_sample(&self, n: usize) -> Option<SampleRef> {
+        let remaining = self
+            .samples
+            .sample_count()
+            .saturating_sub(self.current_sample) as usize;
 
-impl Default for Mp4Demuxer {
-    fn default() -> Self {
-        Self::new()
+        if n < remaining {
+            self.table_sample(self.current_sample + n as u32)
+        } else {
+            self.fragment_samples.get(n - remaining).copied()
+        }
     }
-}
 
-impl Mp4Demuxer {
-    pub fn new() -> Self {
-        Self { tracks: Vec::new() }
+    pub fn current_sample(&self) -> Option<SampleRef> {
+        self.table_sample(self.current_sample)
     }
 
-    fn pos(&mut self, buf: &mut dyn Buffered) -> Result<u64, Mp4BoxError> {
-        Ok(buf.seek(SeekFrom::Current(0))?)
+    fn table_sample(&self, number: u32) -> Option<SampleRef> {
+        let sample = self.samples.get(number)?;
+
+        Some(SampleRef {
+            number: Some(number),
+            time: sample.decode_time,
+            composition_offset: sample.composition_offset,
+            duration: sample.duration,
+            data_offset: sample.data_offset,
+            data_length: sample.data_length,
+            keyframe: sample.keyframe,
+            dependency: self.samples.dependency(number),
+        })
     }
 
-    fn parse_streams(&mut self, buf: &mut dyn Buffered) -> Result<(), Mp4BoxError> {
-        self.read_until_moov(buf)?;
+    pub fn advance_sample(&mut self) {
+        self.current_sample += 1;
+    }
+}
 
-        for t in &mut self.tracks {
-            if t.stream.params.kind.is_none() {
-                let sample = read_sample(buf, t.current_sample().unwrap())?;
+/// Matches tracks to be enabled or disabled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrackSelector {
+    /// Stream index.
+    Index(usize),
+    TrackId(u32),
+    /// Handler type of the media as stored in `hdlr`, e.g. `*b"soun"`.
+    Handler(BoxName),
+    /// ISO 639-2/T language code, e.g. `*b"eng"`.
+    Language([u8; 3]),
+}
 
-                mpeg1::fill_codec_params(&sample.data, &mut t.stream.params)?;
-            }
-        }
+/// Samples skipped without being read, e.g. for fast-forward previews.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TrickPlay {
+    #[default]
+    Off,
+    /// Drops disposable samples, on which no other sample depends according
+    /// to `sdtp` or the fragment sample flags, e.g. non-reference B frames.
+    ReferenceFrames,
+    /// Only returns sync samples and other random access points.
+    Keyframes,
+}
 
-        Ok(())
-    }
+/// Opens the media files that tracks refer to through `dref` entries, e.g.
+/// in QuickTime reference movies.
+pub trait DataResolver: Send + Sync {
+    /// `location` is the URL from the data entry, usually relative to the
+    /// movie.
+    fn open(&self, location: &str) -> io::Result<Box<dyn Buffered + Send + Sync>>;
+}
 
-    fn read_until_moov(&mut self, buf: &mut dyn Buffered) -> Result<(), Mp4BoxError> {
-        // go through boxes until we find moov
-        loop {
-            let pos = self.pos(buf)?;
-            let boks = Boks::peek(buf)?;
+/// Resolves relative `file:` URLs and paths against the directory of the
+/// movie. Locations outside of that directory are rejected, since they come
+/// from the file being read.
+pub struct FileResolver {
+    base: PathBuf,
+}
 
-            debug!("{}: {:?}", pos, boks);
+impl FileResolver {
+    pub fn new(movie_path: impl Into<PathBuf>) -> Self {
+        let mut base = movie_path.into();
+        base.pop();
 
-            match &boks.name {
-                // b"mdat" => self.mdat_offset = Some(self.offset),
-                b"moov" => {
-                    let pos = self.pos(buf)?;
-                    debug!("found moov box: {}", pos);
+        FileResolver { base }
+    }
+}
 
-                    let moov = moov::MovieBox::read(buf)?;
-                    let mut tracks = Vec::with_capacity(moov.tracks.len());
+impl DataResolver for FileResolver {
+    fn open(&self, location: &str) -> io::Result<Box<dyn Buffered + Send + Sync>> {
+        let invalid = |reason: &str| {
+            io::Error::new(ErrorKind::InvalidInput, format!("{}: {}", reason, location))
+        };
 
-                    for (i, trak) in moov.tracks.into_iter().enumerate() {
-                        match Track::from_trak(i as u32, trak) {
-                            Ok(track) => tracks.push(track),
-                            Err(e) => {
-                                warn!("Could not parse trak: {}", e);
-                            }
-                        }
-                    }
-                    self.tracks = tracks;
+        let path = match location.strip_prefix("file:") {
+            Some(url) => {
+                let path = match url.strip_prefix("//") {
+                    // only local files, without a host
+                    Some(url) => url.strip_prefix("localhost").unwrap_or(url),
+                    None => url,
+                };
 
-                    return Ok(());
-                }
-                _ => {
-                    warn!("skipping box {:?}", boks);
-                    skip(buf, boks.size)?;
-                }
+                percent_decode(path).ok_or_else(|| invalid("invalid file URL"))?
             }
+            None if location.contains("://") => return Err(invalid("not a file URL")),
+            None => location.to_string(),
+        };
+
+        let path = Path::new(&path);
+
+        if !path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(invalid("location outside of the movie directory"));
         }
-    }
 
+        // symbolic links must not lead out of the directory either
+        let base = self.base.canonicalize()?;
+        let path = base.join(path).canonicalize()?;
 
-    fn read_next_event(&mut self, buf: &mut dyn Buffered) -> AvResult<Event> {
-        let earliest_track_sample = self
-            .tracks
-            .iter()
-            .enumerate()
-            .filter_map(|(idx, t)| t.current_sample().map(|s| (idx, s)))
-            .min_by_key(|(_idx, s)| s.time);
-
-        if let Some((track, sample)) = earliest_track_sample {
-            let sample = read_sample(buf, sample)?;
-
-            let track = &mut self.tracks[track];
-            track.advance_sample();
-
-            let time = TimeInfo {
-                pts: Some(sample.time as i64),
-                dts: Some(sample.time as i64),
-                duration: Some(sample.duration as u64),
-                timebase: Some(track.timebase),
-                user_private: None,
-            };
+        if !path.starts_with(&base) {
+            return Err(invalid("location outside of the movie directory"));
+        }
 
-            let packet = Packet {
-                data: sample.data,
-                pos: None,
-                stream_index: track.index as isize,
-                t: time,
-                is_key: sample.keyframe,
-                is_corrupted: false,
-            };
+        let file = File::open(path)?;
+
+        Ok(Box::new(AccReader::new(file)))
+    }
+}
 
-            Ok(Event::NewPacket(packet))
+// decodes the `%XX` escapes of a URL path
+fn percent_decode(path: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = tail
+                .get(..2)
+                .filter(|h| h.iter().all(u8::is_ascii_hexdigit))?;
+            bytes.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            rest = &tail[2..];
         } else {
-            Ok(Event::Eof)
+            bytes.push(b);
+            rest = tail;
         }
     }
+
+    String::from_utf8(bytes).ok()
 }
 
-impl Demuxer for Mp4Demuxer {
-    fn read_headers(
-        &mut self,
-        buf: &mut dyn Buffered,
-        info: &mut GlobalInfo,
-    ) -> AvResult<SeekFrom> {
-        let res = self.parse_streams(buf);
+/// Order in which the packets of different tracks are returned.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReadStrategy {
+    /// Earliest decode time first, seeking back and forth in poorly
+    /// interleaved files.
+    #[default]
+    Interleaved,
+    /// Byte offset order, reading adjacent samples of a track at once. Up to
+    /// `reorder_window` packets are held back and returned in decode time order.
+    FileOrder { reorder_window: usize },
+}
 
-        if let Err(e) = res {
-            error!("{}", e);
+// upper bound for reading adjacent samples at once
+const MAX_COALESCED_READ: u64 = 1 << 20;
+
+// upper bound for boxes held in memory in streaming mode, e.g. the movie box
+const MAX_STREAMED_BOX_SIZE: u64 = 1 << 30;
+
+// compares decode times across timebases
+fn decode_order(a: &TimeInfo, b: &TimeInfo) -> Ordering {
+    let time = |t: &TimeInfo| {
+        let timebase = t.timebase.unwrap_or_else(|| Rational64::new(1, 1));
+
+        (
+            t.dts.unwrap_or(0) as i128 * *timebase.numer() as i128,
+            *timebase.denom() as i128,
+        )
+    };
+
+    let (a, a_denom) = time(a);
+    let (b, b_denom) = time(b);
+
+    (a * b_denom).cmp(&(b * a_denom))
+}
+
+// where the data of a traf ends, for tracks without defaults from trex, so
+// sizes missing from the traf count as 0
+fn track_fragment_end(base_data_offset: u64, traf: &traf::TrackFragmentBox) -> u64 {
+    let default_size = traf.tfhd.default_sample_size.unwrap_or(0);
+    let mut data_offset = base_data_offset;
+
+    for trun in &traf.track_runs {
+        if let Some(offset) = trun.data_offset {
+            data_offset = (base_data_offset as i64).wrapping_add(offset as i64) as u64;
         }
 
-        info.streams = self.tracks.iter().map(|t| t.stream.clone()).collect();
+        let size: u64 = trun
+            .samples
+            .iter()
+            .map(|s| s.size.unwrap_or(default_size) as u64)
+            .sum();
+        data_offset = data_offset.saturating_add(size);
+    }
 
-        Ok(SeekFrom::Current(0))
+    data_offset
+}
+
+// result of a forward-only read that may run out of input
+enum Streamed<T> {
+    Ready(T),
+    MoreDataNeeded(usize),
+    End,
+}
+
+// a top-level box read into memory in streaming mode, positioned at its
+// offset in the input so that errors and diagnostics report file offsets
+struct StreamedBox {
+    data: Cursor<Vec<u8>>,
+    offset: u64,
+}
+
+impl Read for StreamedBox {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.data.read(buf)
     }
+}
 
-    fn read_event(&mut self, buf: &mut dyn Buffered) -> AvResult<(SeekFrom, Event)> {
-        let event = self.read_next_event(buf)?;
+impl Seek for StreamedBox {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => match pos.checked_sub(self.offset) {
+                Some(pos) => SeekFrom::Start(pos),
+                None => {
+                    return Err(io::Error::new(
+                        ErrorKind::InvalidInput,
+                        "seek before the start of the box",
+                    ))
+                }
+            },
+            pos => pos,
+        };
 
-        Ok((SeekFrom::Current(0), event))
+        Ok(self.data.seek(pos)? + self.offset)
     }
 }
 
-struct Des {
-    d: Descr,
+pub struct Mp4Demuxer {
+    tracks: Vec<Track>,
+    fragmented: bool,
+    next_box_offset: u64,
+    streaming: bool,
+    // absolute offset of the next byte to be read in streaming mode
+    stream_pos: u64,
+    // input read ahead of `stream_pos` in streaming mode, kept when a box or
+    // sample is incomplete because the input cannot be rewound
+    stream_buffer: Vec<u8>,
+    file_type: Option<ftyp::FileTypeBox<'static>>,
+    metadata: Metadata,
+    chapters: Vec<Chapter>,
+    strategy: ReadStrategy,
+    trick_play: TrickPlay,
+    // packets already read from a coalesced read
+    queued: VecDeque<Packet>,
+    // packets held back to be returned in decode order
+    reorder: Vec<Packet>,
+    // zero bytes appended to every packet
+    padding: usize,
+    pool: Arc<dyn BufferPool>,
+    shared_source: Option<Arc<dyn AsRef<[u8]> + Send + Sync>>,
+    resolver: Option<Arc<dyn DataResolver>>,
+    // external media files by their location, with their last known size
+    data_sources: Vec<(String, Box<dyn Buffered + Send + Sync>, u64)>,
+    // last known size of the input, sample ranges are checked against it
+    input_size: u64,
+    // selections in the order they were made, applied to the tracks once they
+    // are known
+    track_selection: Vec<(TrackSelector, bool)>,
+    parse_mode: ParseMode,
+    diagnostics: Vec<Diagnostic>,
 }
 
-impl Descriptor for Des {
-    fn create(&self) -> Box<dyn Demuxer> {
-        Box::new(Mp4Demuxer::new())
+impl Default for Mp4Demuxer {
+    fn default() -> Self {
+        Self::new()
     }
-    fn describe(&self) -> &Descr {
-        &self.d
+}
+
+impl Mp4Demuxer {
+    pub fn new() -> Self {
+        Self {
+            tracks: Vec::new(),
+            fragmented: false,
+            next_box_offset: 0,
+            streaming: false,
+            stream_pos: 0,
+            stream_buffer: Vec::new(),
+            file_type: None,
+            metadata: Metadata::default(),
+            chapters: Vec::new(),
+            strategy: ReadStrategy::default(),
+            trick_play: TrickPlay::default(),
+            queued: VecDeque::new(),
+            reorder: Vec::new(),
+            padding: 8,
+            pool: Arc::new(HeapPool),
+            shared_source: None,
+            resolver: None,
+            data_sources: Vec::new(),
+            input_size: 0,
+            track_selection: Vec::new(),
+            parse_mode: ParseMode::default(),
+            diagnostics: Vec::new(),
+        }
     }
-    fn probe(&self, _data: &[u8]) -> u8 {
-        0
+
+    /// Enables the matching tracks, overriding the defaults taken from the
+    /// `tkhd` flags and alternate groups. Can be called before or after the
+    /// headers are read.
+    pub fn enable_tracks(&mut self, selector: TrackSelector) {
+        self.select_tracks(selector, true);
     }
-}
 
-pub const MP4_DESC: &dyn Descriptor = &Des {
-    d: Descr {
+    /// Disables the matching tracks, their samples are never read.
+    pub fn disable_tracks(&mut self, selector: TrackSelector) {
+        self.select_tracks(selector, false);
+    }
+
+    fn select_tracks(&mut self, selector: TrackSelector, selected: bool) {
+        for track in self.tracks.iter_mut().filter(|t| t.matches(&selector)) {
+            track.selected = selected;
+        }
+
+        self.track_selection.push((selector, selected));
+    }
+
+    pub fn handler_type(&self, stream_index: usize) -> Option<hdlr::HandlerType> {
+        self.track(stream_index)
+            .map(|t| u32::from_be_bytes(t.handler_type).into())
+    }
+
+    /// The human-readable name from the `hdlr` box of the stream.
+    pub fn handler_name(&self, stream_index: usize) -> Option<&str> {
+        self.track(stream_index).map(|t| t.handler_name.as_str())
+    }
+
+    fn track(&self, stream_index: usize) -> Option<&Track> {
+        self.tracks.iter().find(|t| t.index == stream_index)
+    }
+
+    pub fn is_track_enabled(&self, stream_index: usize) -> bool {
+        self.tracks
+            .iter()
+            .any(|t| t.index == stream_index && t.selected)
+    }
+
+    // by default, tracks disabled in tkhd are skipped, and only the first
+    // enabled track of every alternate group is read
+    fn apply_track_selection(&mut self) {
+        let mut groups = Vec::new();
+
+        // some muxers leave the flags of every track unset
+        let ignore_flags = self.tracks.iter().all(|t| !t.enabled);
+
+        for track in &mut self.tracks {
+            track.selected = track.enabled || ignore_flags;
+
+            if track.selected && track.alternate_group != 0 {
+                if groups.contains(&track.alternate_group) {
+                    track.selected = false;
+                } else {
+                    groups.push(track.alternate_group);
+                }
+            }
+        }
+
+        for (selector, selected) in &self.track_selection {
+            for track in self.tracks.iter_mut().filter(|t| t.matches(selector)) {
+                track.selected = *selected;
+            }
+        }
+    }
+
+    /// Number of zero bytes appended to the data of every packet, 8 by
+    /// default.
+    pub fn set_padding(&mut self, padding: usize) {
+        self.padding = padding;
+    }
+
+    pub fn set_buffer_pool(&mut self, pool: Arc<dyn BufferPool>) {
+        self.pool = pool;
+    }
+
+    /// The whole input as in-memory bytes, e.g. a memory map. Packets then
+    /// share these bytes through `PacketInfo::shared_data` instead of copying
+    /// them, and leave `Packet::data` empty. Not used in streaming mode.
+    pub fn set_shared_source(&mut self, source: Arc<dyn AsRef<[u8]> + Send + Sync>) {
+        self.shared_source = Some(source);
+    }
+
+    /// Lets tracks read media data from other files, which are otherwise
+    /// skipped. Not used in streaming mode.
+    pub fn set_data_resolver(&mut self, resolver: Arc<dyn DataResolver>) {
+        self.resolver = Some(resolver);
+    }
+
+    // opens the external media data of the tracks, which are skipped if that
+    // fails
+    fn open_data_sources(&mut self) {
+        for track in &mut self.tracks {
+            let location = match &track.data_location {
+                Some(location) => location,
+                None => continue,
+            };
+
+            if let Some(idx) = self.data_sources.iter().position(|(l, ..)| l == location) {
+                track.data_source = Some(idx);
+                continue;
+            }
+
+            let opened = match &self.resolver {
+                Some(_) if self.streaming => Err(io::Error::new(
+                    ErrorKind::Other,
+                    "external media data is not read in streaming mode",
+                )),
+                Some(resolver) => resolver.open(location),
+                None => Err(io::Error::new(ErrorKind::NotFound, "no data resolver set")),
+            };
+
+            match opened {
+                Ok(input) => {
+                    self.data_sources.push((location.clone(), input, 0));
+                    track.data_source = Some(self.data_sources.len() - 1);
+                }
+                Err(e) => {
+                    diagnostic!(
+                        "track {}: could not open {}: {}",
+                        track.track_id,
+                        location,
+                        e
+                    );
+                    track.missing_data = true;
+                }
+            }
+        }
+    }
+
+    // the input holding the sample data of the track
+    fn data_input<'a>(
+        &'a mut self,
+        buf: &'a mut dyn Buffered,
+        track: usize,
+    ) -> &'a mut dyn Buffered {
+        match self.tracks[track].data_source {
+            Some(idx) => &mut *self.data_sources[idx].1,
+            None => buf,
+        }
+    }
+
+    // the sizes of the boxes within are only checked against their parents, so
+    // top-level boxes that are parsed have to fit into the input
+    fn check_box_range(
+        &mut self,
+        buf: &mut dyn Buffered,
+        pos: u64,
+        boks: &Boks,
+    ) -> Result<(), Mp4BoxError> {
+        let end = pos.saturating_add(boks.size);
+
+        // the input may have grown since it was last measured
+        if end > self.input_size {
+            self.input_size = input_size(buf)?;
+        }
+
+        if end <= self.input_size {
+            Ok(())
+        } else {
+            Err(Mp4BoxError::InvalidBoxSize(BoxPrint(boks.name), boks.size))
+        }
+    }
+
+    // crafted sample tables can point far beyond the end of the input, which
+    // is caught before a buffer is allocated for the sample
+    fn check_sample_range(
+        &mut self,
+        buf: &mut dyn Buffered,
+        track: usize,
+        data_offset: u64,
+        length: u64,
+    ) -> Result<(), Mp4BoxError> {
+        let end = data_offset
+            .checked_add(length)
+            .ok_or(Mp4BoxError::UnexpectedEos)?;
+
+        let known_size = match self.tracks[track].data_source {
+            Some(idx) => self.data_sources[idx].2,
+            None => self.input_size,
+        };
+
+        if end <= known_size {
+            return Ok(());
+        }
+
+        // the input may have grown since it was last measured
+        let size = input_size(self.data_input(buf, track))?;
+
+        match self.tracks[track].data_source {
+            Some(idx) => self.data_sources[idx].2 = size,
+            None => self.input_size = size,
+        }
+
+        if end <= size {
+            Ok(())
+        } else {
+            Err(Mp4BoxError::UnexpectedEos)
+        }
+    }
+
+    fn sample_buffer(&self, len: usize) -> Vec<u8> {
+        let mut data = self.pool.get(len + self.padding);
+        data.clear();
+        data.resize(len + self.padding, 0);
+        data
+    }
+
+    fn shared_sample(&self, track: usize, sample: SampleRef) -> Option<Sample> {
+        if self.tracks[track].data_source.is_some() {
+            return None;
+        }
+
+        let source = self.shared_source.clone()?;
+        let start = usize::try_from(sample.data_offset).ok()?;
+        let end = start.checked_add(sample.data_length as usize)?;
+        let shared_data = SharedData::new(source, start..end)?;
+
+        Some(Sample {
+            shared_data: Some(shared_data),
+            ..sample.with_data(Vec::new())
+        })
+    }
+
+    /// Can be changed at any time, e.g. to return to normal playback.
+    pub fn set_trick_play(&mut self, trick_play: TrickPlay) {
+        self.trick_play = trick_play;
+
+        for track in &mut self.tracks {
+            track.trick_play = trick_play;
+        }
+    }
+
+    /// Streaming mode always reads in file order, the reorder window of
+    /// `ReadStrategy::FileOrder` applies there as well.
+    pub fn set_read_strategy(&mut self, strategy: ReadStrategy) {
+        self.strategy = strategy;
+    }
+
+    /// Reads the input strictly forward without seeking, for pipes and files
+    /// that are still being downloaded. Samples are returned in file order and
+    /// `Event::MoreDataNeeded` is returned when the input ends in the middle of
+    /// a box or sample. The `moov` box has to precede the media data; until it
+    /// is complete, reading the headers fails with `Error::MoreDataNeeded` and
+    /// can be retried. Incomplete boxes and samples are buffered internally.
+    pub fn set_streaming(&mut self, streaming: bool) {
+        self.streaming = streaming;
+    }
+
+    /// Selects whether deviations from the specification fail the read or
+    /// are worked around, lenient by default. Deviations that were worked
+    /// around show up in `diagnostics`.
+    pub fn set_parse_mode(&mut self, mode: ParseMode) {
+        self.parse_mode = mode;
+    }
+
+    /// Moves every track to `time`, in the timebase of the stream with
+    /// `stream_index`. Reading restarts at the random access point before
+    /// `time`, moved back by its `roll` or `prol` pre-roll, and the samples
+    /// up to `time` are marked with `PacketInfo::discard`. Only the samples in
+    /// `moov` can be seeked to.
+    pub fn seek(&mut self, stream_index: usize, time: u64) -> Result<(), Mp4BoxError> {
+        if self.streaming {
+            return Err(Mp4BoxError::SeekWhileStreaming);
+        }
+
+        let timebase = match self.tracks.iter().find(|t| t.index == stream_index) {
+            Some(track) => track.timebase,
+            None => return Ok(()),
+        };
+
+        for track in &mut self.tracks {
+            let time = (Rational64::from_integer(time.min(i64::MAX as u64) as i64) * timebase
+                / track.timebase)
+                .to_integer()
+                .max(0) as u64;
+
+            let count = track.samples.sample_count();
+            let target = track.samples.sample_at_time(time).unwrap_or(count);
+            let start = match target {
+                target if target < count => track.samples.seek_sample(target).unwrap_or(0),
+                _ => count,
+            };
+
+            track.current_sample = start;
+            track.preroll_end = target;
+        }
+
+        self.queued.clear();
+        self.reorder.clear();
+
+        Ok(())
+    }
+
+    /// The sample table of the stream with `stream_index`, without the samples
+    /// of movie fragments.
+    pub fn sample_index(&self, stream_index: usize) -> Option<&SampleIndex> {
+        self.tracks
+            .iter()
+            .find(|t| t.index == stream_index)
+            .map(|t| &t.samples)
+    }
+
+    /// ID of the track of a stream, as used in track references.
+    pub fn track_id(&self, stream_index: usize) -> Option<u32> {
+        self.tracks
+            .iter()
+            .find(|t| t.index == stream_index)
+            .map(|t| t.track_id)
+    }
+
+    /// Streams referenced by the track of a stream, e.g. the video described
+    /// by a metadata track. References to tracks that were not read as a
+    /// stream are left out.
+    pub fn track_references(&self, stream_index: usize) -> Vec<(tref::ReferenceType, usize)> {
+        let track = match self.tracks.iter().find(|t| t.index == stream_index) {
+            Some(track) => track,
+            None => return Vec::new(),
+        };
+
+        track
+            .references
+            .iter()
+            .flat_map(|r| r.track_ids.iter().map(move |&id| (r.reference_type(), id)))
+            .filter_map(|(reference_type, id)| {
+                let referenced = self.tracks.iter().find(|t| t.track_id == id)?;
+
+                Some((reference_type, referenced.index))
+            })
+            .collect()
+    }
+
+    /// Start timecode of a stream, from its own samples for a timecode track
+    /// or else from the timecode track it references with `tmcd`.
+    pub fn timecode(&self, stream_index: usize) -> Option<Timecode> {
+        let track = self.track(stream_index)?;
+
+        if track.timecode.is_some() {
+            return track.timecode;
+        }
+
+        track
+            .references
+            .iter()
+            .filter(|r| r.reference_type() == tref::ReferenceType::Timecode)
+            .flat_map(|r| &r.track_ids)
+            .find_map(|&id| self.tracks.iter().find(|t| t.track_id == id)?.timecode)
+    }
+
+    /// The `ftyp` box of the file, if it has one.
+    pub fn file_type(&self) -> Option<&ftyp::FileTypeBox<'static>> {
+        self.file_type.as_ref()
+    }
+
+    /// Metadata of the movie, empty until the headers are read.
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    /// Chapters of the movie, taken from a chapter track referenced with
+    /// `chap` or else from a Nero `chpl` box.
+    pub fn chapters(&self) -> &[Chapter] {
+        &self.chapters
+    }
+
+    /// Problems that were worked around so far, e.g. skipped boxes or
+    /// dropped tracks. Repeated messages are counted rather than kept again,
+    /// and only the first 256 distinct messages are kept.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    fn pos(&mut self, buf: &mut dyn Buffered) -> Result<u64, Mp4BoxError> {
+        Ok(buf.seek(SeekFrom::Current(0))?)
+    }
+
+    fn parse_streams(&mut self, buf: &mut dyn Buffered) -> Result<(), Mp4BoxError> {
+        if self.streaming {
+            // the parameters of mpeg-1 streams are filled in from their first
+            // sample, which would require seeking
+            return self.stream_until_moov(buf);
+        }
+
+        self.read_until_moov(buf)?;
+
+        let strict = self.parse_mode == ParseMode::Strict;
+
+        match self.read_chapter_track(buf) {
+            Err(e) if strict => return Err(e),
+            Err(e) => diagnostic!("could not read chapter track: {}", e),
+            Ok(()) => {}
+        }
+        match self.read_timecode_tracks(buf) {
+            Err(e) if strict => return Err(e),
+            Err(e) => diagnostic!("could not read timecode track: {}", e),
+            Ok(()) => {}
+        }
+
+        let padding = self.padding;
+
+        for idx in 0..self.tracks.len() {
+            let track = &self.tracks[idx];
+
+            // mp4v entries carrying MPEG-1 video leave the parameters to the
+            // first sample, other entries without a codec are left alone
+            let mpeg4 = matches!(track.sample_entry, stsd::SampleEntry::Mpeg4(_));
+
+            if mpeg4 && track.stream.params.kind.is_none() && !track.missing_data {
+                if let Some(sample) = track.current_sample() {
+                    let length = sample.data_length as u64;
+                    self.check_sample_range(buf, idx, sample.data_offset, length)?;
+
+                    let data = vec![0u8; sample.data_length as usize + padding];
+                    let sample = read_sample(self.data_input(buf, idx), sample, data)?;
+
+                    mpeg1::fill_codec_params(&sample.data, &mut self.tracks[idx].stream.params)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn read_until_moov(&mut self, buf: &mut dyn Buffered) -> Result<(), Mp4BoxError> {
+        // go through boxes until we find moov
+        loop {
+            let pos = self.pos(buf)?;
+            let boks = match Boks::peek(buf) {
+                Ok(boks) => boks,
+                Err(Mp4BoxError::UnexpectedEos) => {
+                    return Err(Mp4BoxError::RequiredBoxNotFound(BoxPrint(*b"moov")))
+                }
+                Err(e) => return Err(e),
+            };
+
+            debug!("{}: {:?}", pos, boks);
+
+            match &boks.name {
+                // b"mdat" => self.mdat_offset = Some(self.offset),
+                b"ftyp" => {
+                    self.file_type = Some(ftyp::FileTypeBox::read(buf).context(pos, &boks)?);
+                }
+                b"moov" => {
+                    debug!("found moov box: {}", pos);
+                    self.check_box_range(buf, pos, &boks)?;
+
+                    let moov = moov::MovieBox::read(buf).context(pos, &boks)?;
+                    self.add_moov(moov);
+                    self.next_box_offset = pos.saturating_add(boks.size);
+
+                    return Ok(());
+                }
+                // QuickTime movies often have the movie after the media data
+                // and padding
+                b"mdat" | b"free" | b"skip" | b"wide" | b"pnot" => {
+                    debug!("skipping box {:?}", boks);
+                    skip(buf, boks.size)?;
+                }
+                _ => {
+                    diagnostic!(at pos, "skipping box {:?}", boks);
+                    skip(buf, boks.size)?;
+                }
+            }
+        }
+    }
+
+    // QuickTime chapters are the samples of a text track
+    fn read_chapter_track(&mut self, buf: &mut dyn Buffered) -> Result<(), Mp4BoxError> {
+        let track_id = self
+            .tracks
+            .iter()
+            .flat_map(|t| &t.references)
+            .filter(|r| r.reference_type() == tref::ReferenceType::Chapter)
+            .find_map(|r| r.track_ids.first().copied());
+
+        let track = match track_id.and_then(|id| self.tracks.iter().find(|t| t.track_id == id)) {
+            Some(track) => track,
+            None => return Ok(()),
+        };
+
+        let timescale = (*track.timebase.denom()).max(1) as i128;
+        let time =
+            |t: i64| Duration::from_nanos((t.max(0) as i128 * 1_000_000_000 / timescale) as u64);
+
+        let input: &mut dyn Buffered = match track.data_source {
+            Some(idx) => &mut *self.data_sources[idx].1,
+            None if track.missing_data => return Ok(()),
+            None => buf,
+        };
+
+        let mut chapters = Vec::new();
+
+        for number in 0..track.samples.sample_count() {
+            let sample = match track.samples.get(number) {
+                Some(sample) => sample,
+                None => break,
+            };
+
+            goto(input, sample.data_offset)?;
+            let data = read_bytes(input, sample.data_length as u64)?;
+
+            let (start, _) = track.edit_list.map(sample.decode_time as i64);
+
+            chapters.push(Chapter {
+                start: time(start),
+                end: time(start + sample.duration as i64),
+                title: decode_text_sample(&data).unwrap_or_default(),
+            });
+        }
+
+        if !chapters.is_empty() {
+            self.chapters = chapters;
+        }
+
+        Ok(())
+    }
+
+    // the first sample of a timecode track holds the frame number of the
+    // start, later samples only follow discontinuities
+    fn read_timecode_tracks(&mut self, buf: &mut dyn Buffered) -> Result<(), Mp4BoxError> {
+        for idx in 0..self.tracks.len() {
+            let track = &self.tracks[idx];
+
+            let entry = match &track.sample_entry {
+                stsd::SampleEntry::Timecode(entry) => entry,
+                _ => continue,
+            };
+
+            let sample = match track.samples.get(0) {
+                Some(sample) if sample.data_length >= 4 && !track.missing_data => sample,
+                _ => continue,
+            };
+
+            // counters do not tell the time of day
+            if entry.flags.contains(tmcd::TimecodeFlags::COUNTER) {
+                diagnostic!(
+                    "track {}: timecode counters are not supported",
+                    track.track_id
+                );
+                continue;
+            }
+
+            let fps = entry.number_of_frames as u32;
+            let drop_frame = entry.is_drop_frame();
+
+            let mut frame = [0u8; 4];
+            let input = self.data_input(buf, idx);
+            goto(input, sample.data_offset)?;
+            input.read_exact(&mut frame)?;
+
+            // negative times are not representable, start at zero instead
+            self.tracks[idx].timecode = Some(Timecode::from_frame_number(
+                i32::from_be_bytes(frame).max(0) as u32,
+                fps,
+                drop_frame,
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn add_moov(&mut self, moov: moov::MovieBox) {
+        let movie_timescale = moov.mvhd.timescale;
+        let mut tracks = Vec::with_capacity(moov.tracks.len());
+
+        let trex = moov.mvex.as_ref().map(|mvex| &mvex.trex[..]).unwrap_or(&[]);
+
+        for (i, trak) in moov.tracks.into_iter().enumerate() {
+            match Track::from_trak(i as u32, trak, movie_timescale) {
+                Ok(mut track) => {
+                    if let Some(trex) = trex.iter().find(|t| t.track_id == track.track_id) {
+                        track.fragment_defaults = trex.into();
+                    }
+
+                    tracks.push(track);
+                }
+                Err(e) => {
+                    diagnostic!("dropping track {}: {}", i, e);
+                }
+            }
+        }
+        for track in &mut tracks {
+            track.trick_play = self.trick_play;
+        }
+
+        self.tracks = tracks;
+        self.fragmented = moov.mvex.is_some();
+        self.open_data_sources();
+
+        if let Some(udta) = &moov.udta {
+            self.metadata = Metadata::from_udta(udta);
+
+            if let Some(chpl) = &udta.chpl {
+                let duration = Duration::from_secs_f64(
+                    moov.mvhd.duration as f64 / movie_timescale.max(1) as f64,
+                );
+
+                self.chapters = Chapter::from_chpl(chpl, duration);
+            }
+        }
+
+        self.apply_track_selection();
+    }
+
+    // reads top-level boxes following moov until the next moof has been
+    // queued, returns false once there are no more fragments
+    fn read_next_fragment(&mut self, buf: &mut dyn Buffered) -> Result<bool, Mp4BoxError> {
+        loop {
+            let pos = self.next_box_offset;
+            goto(buf, pos)?;
+
+            let boks = match Boks::peek(buf) {
+                Ok(boks) => boks,
+                Err(Mp4BoxError::UnexpectedEos) => return Ok(false),
+                Err(Mp4BoxError::Io(e)) if e.kind() == ErrorKind::UnexpectedEof => {
+                    return Ok(false)
+                }
+                Err(e) => return Err(e),
+            };
+
+            debug!("{}: {:?}", pos, boks);
+
+            self.next_box_offset = pos.saturating_add(boks.size);
+
+            match &boks.name {
+                b"moof" => {
+                    self.check_box_range(buf, pos, &boks)?;
+
+                    let moof = moof::MovieFragmentBox::read(buf).context(pos, &boks)?;
+                    self.add_fragment(pos, moof)?;
+
+                    return Ok(true);
+                }
+                _ => {
+                    debug!("skipping box {:?}", boks);
+                }
+            }
+        }
+    }
+
+    fn add_fragment(
+        &mut self,
+        moof_offset: u64,
+        moof: moof::MovieFragmentBox,
+    ) -> Result<(), Mp4BoxError> {
+        debug!("fragment {} at {}", moof.mfhd.sequence_number, moof_offset);
+
+        // without an explicit base offset, the first traf starts at the moof
+        // and every following one where the data of the previous ended
+        let mut data_end = moof_offset;
+
+        for traf in moof.trafs {
+            let track_id = traf.tfhd.track_id;
+
+            let base_data_offset = match traf.tfhd.base_data_offset {
+                Some(offset) => offset,
+                None if traf.tfhd.default_base_is_moof => moof_offset,
+                None => data_end,
+            };
+
+            let track = match self.tracks.iter_mut().find(|t| t.track_id == track_id) {
+                Some(track) => track,
+                None => {
+                    deviation!(at moof_offset, "skipping traf for unknown track {}", track_id)?;
+                    // the data of the following trafs still comes after it
+                    data_end = track_fragment_end(base_data_offset, &traf);
+                    continue;
+                }
+            };
+
+            data_end = track.add_track_fragment(base_data_offset, traf);
+        }
+
+        Ok(())
+    }
+
+    fn read_next_event(&mut self, buf: &mut dyn Buffered) -> AvResult<Event> {
+        let reorder_window = match self.strategy {
+            ReadStrategy::Interleaved => 0,
+            ReadStrategy::FileOrder { reorder_window } => reorder_window,
+        };
+
+        loop {
+            if self.reorder.len() > reorder_window {
+                if let Some(packet) = self.pop_earliest() {
+                    return Ok(Event::NewPacket(packet));
+                }
+            }
+
+            let event = if self.streaming {
+                self.stream_next_event(buf)?
+            } else {
+                self.read_unordered_event(buf)?
+            };
+
+            match event {
+                Event::NewPacket(packet) if reorder_window > 0 => self.reorder.push(packet),
+                Event::Eof => {
+                    return Ok(match self.pop_earliest() {
+                        Some(packet) => Event::NewPacket(packet),
+                        None => Event::Eof,
+                    })
+                }
+                event => return Ok(event),
+            }
+        }
+    }
+
+    fn pop_earliest(&mut self) -> Option<Packet> {
+        let idx = (0..self.reorder.len())
+            .min_by(|&a, &b| decode_order(&self.reorder[a].t, &self.reorder[b].t))?;
+
+        Some(self.reorder.remove(idx))
+    }
+
+    fn read_unordered_event(&mut self, buf: &mut dyn Buffered) -> AvResult<Event> {
+        loop {
+            if let Some(packet) = self.queued.pop_front() {
+                return Ok(Event::NewPacket(packet));
+            }
+
+            for track in &mut self.tracks {
+                track.skip_dropped();
+            }
+
+            let next_samples = self
+                .tracks
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, t)| t.next_sample().map(|s| (idx, s)));
+
+            match self.strategy {
+                ReadStrategy::Interleaved => {
+                    if let Some((track, sample)) = next_samples.min_by_key(|(_idx, s)| s.time) {
+                        return self.read_packet(buf, track, sample);
+                    }
+                }
+                ReadStrategy::FileOrder { .. } => {
+                    if let Some((track, sample)) =
+                        next_samples.min_by_key(|(_idx, s)| s.data_offset)
+                    {
+                        if self.shared_source.is_some() {
+                            return self.read_packet(buf, track, sample);
+                        }
+
+                        self.read_coalesced(buf, track, sample)?;
+                        continue;
+                    }
+                }
+            }
+
+            if !self.fragmented || !self.read_next_fragment(buf)? {
+                return Ok(Event::Eof);
+            }
+        }
+    }
+
+    // reads the sample together with the following samples of the track that
+    // are adjacent in the file, and queues their packets
+    fn read_coalesced(
+        &mut self,
+        buf: &mut dyn Buffered,
+        track: usize,
+        first: SampleRef,
+    ) -> Result<(), Mp4BoxError> {
+        let mut samples = vec![first];
+        let mut end = first.data_offset.saturating_add(first.data_length as u64);
+
+        while let Some(next) = self.tracks[track].upcoming_sample(samples.len()) {
+            let next_end = end.saturating_add(next.data_length as u64);
+
+            if next.data_offset != end
+                || next_end - first.data_offset > MAX_COALESCED_READ
+                || !self.tracks[track].keeps(&next)
+            {
+                break;
+            }
+
+            samples.push(next);
+            end = next_end;
+        }
+
+        self.check_sample_range(buf, track, first.data_offset, end - first.data_offset)?;
+
+        let mut data = vec![0u8; (end - first.data_offset) as usize];
+
+        let input = self.data_input(buf, track);
+        goto(input, first.data_offset)?;
+        input.read_exact(&mut data)?;
+
+        let mut pos = 0;
+
+        for sample in samples {
+            let len = sample.data_length as usize;
+
+            let mut sample_data = self.sample_buffer(len);
+            sample_data[..len].copy_from_slice(&data[pos..pos + len]);
+            pos += len;
+
+            let packet = self.make_packet(track, sample.with_data(sample_data));
+            self.queued.push_back(packet);
+        }
+
+        Ok(())
+    }
+
+    fn read_packet(
+        &mut self,
+        buf: &mut dyn Buffered,
+        track: usize,
+        sample: SampleRef,
+    ) -> AvResult<Event> {
+        let sample = match self.shared_sample(track, sample) {
+            Some(sample) => sample,
+            None => {
+                self.check_sample_range(buf, track, sample.data_offset, sample.data_length as u64)?;
+
+                let data = self.sample_buffer(sample.data_length as usize);
+                read_sample(self.data_input(buf, track), sample, data)?
+            }
+        };
+
+        Ok(Event::NewPacket(self.make_packet(track, sample)))
+    }
+
+    fn make_packet(&mut self, track: usize, sample: Sample) -> Packet {
+        let padding = if sample.shared_data.is_some() {
+            0
+        } else {
+            self.padding
+        };
+
+        let track = &mut self.tracks[track];
+
+        let subsamples = match sample.number {
+            Some(number) => track.samples.subsamples(number).cloned().collect(),
+            None => track
+                .fragment_subsamples
+                .front()
+                .cloned()
+                .unwrap_or_default(),
+        };
+
+        track.advance();
+
+        let dts = (sample.time as i64).saturating_sub(track.dts_shift);
+        let pts = (sample.time as i64).saturating_add(sample.composition_offset as i64);
+
+        // samples keep their decode order, the whole sample is moved onto
+        // the presentation timeline
+        let (presentation_pts, in_edit) = track.edit_list.map(pts);
+        let dts = dts.saturating_add(presentation_pts.saturating_sub(pts));
+
+        let sample_groups = sample
+            .number
+            .map(|number| track.samples.sample_groups(number).cloned().collect())
+            .unwrap_or_default();
+
+        let info = PacketInfo {
+            discard: !in_edit || sample.number.map_or(false, |n| n < track.preroll_end),
+            padding,
+            shared_data: sample.shared_data,
+            sample_groups,
+            dependency: sample.dependency,
+            subsamples,
+        };
+
+        let time = TimeInfo {
+            pts: Some(presentation_pts),
+            dts: Some(dts),
+            duration: Some(sample.duration as u64),
+            timebase: Some(track.timebase),
+            user_private: Some(Arc::new(info)),
+        };
+
+        Packet {
+            data: sample.data,
+            pos: None,
+            stream_index: track.index as isize,
+            t: time,
+            is_key: sample.keyframe,
+            is_corrupted: false,
+        }
+    }
+
+    fn stream_until_moov(&mut self, buf: &mut dyn Buffered) -> Result<(), Mp4BoxError> {
+        loop {
+            // finish skipping a box that was incomplete before
+            if let Some(missing) = self.stream_skip_to(buf, self.next_box_offset)? {
+                return Err(Mp4BoxError::MoreDataNeeded(missing));
+            }
+
+            let pos = self.stream_pos;
+            let boks = match self.stream_peek_box_header(buf)? {
+                Streamed::Ready(boks) => boks,
+                Streamed::MoreDataNeeded(missing) => {
+                    return Err(Mp4BoxError::MoreDataNeeded(missing))
+                }
+                Streamed::End => return Err(Mp4BoxError::MoreDataNeeded(8)),
+            };
+
+            debug!("{}: {:?}", pos, boks);
+
+            // a box extending to the end of the input leaves no room for moov
+            let box_end = match boks.size {
+                0 if &boks.name != b"moov" => {
+                    return Err(Mp4BoxError::RequiredBoxNotFound(BoxPrint(*b"moov")))
+                }
+                size => pos.saturating_add(size),
+            };
+
+            match &boks.name {
+                b"ftyp" => {
+                    let mut reader = match self.stream_read_box(buf, &boks)? {
+                        Streamed::Ready(reader) => reader,
+                        Streamed::MoreDataNeeded(missing) => {
+                            return Err(Mp4BoxError::MoreDataNeeded(missing))
+                        }
+                        Streamed::End => return Err(Mp4BoxError::MoreDataNeeded(8)),
+                    };
+
+                    self.file_type =
+                        Some(ftyp::FileTypeBox::read(&mut reader).context(pos, &boks)?);
+                    self.next_box_offset = self.stream_pos;
+                }
+                b"moov" => {
+                    let mut reader = match self.stream_read_box(buf, &boks)? {
+                        Streamed::Ready(reader) => reader,
+                        Streamed::MoreDataNeeded(missing) => {
+                            return Err(Mp4BoxError::MoreDataNeeded(missing))
+                        }
+                        Streamed::End => return Err(Mp4BoxError::MoreDataNeeded(8)),
+                    };
+
+                    let moov = moov::MovieBox::read(&mut reader).context(pos, &boks)?;
+                    self.add_moov(moov);
+                    self.next_box_offset = self.stream_pos;
+
+                    return Ok(());
+                }
+                b"mdat" => return Err(Mp4BoxError::MediaDataBeforeMovie),
+                b"free" | b"skip" | b"wide" | b"pnot" => {
+                    debug!("skipping box {:?}", boks);
+                    self.next_box_offset = box_end;
+                }
+                _ => {
+                    diagnostic!(at pos, "skipping box {:?}", boks);
+                    self.next_box_offset = box_end;
+                }
+            }
+        }
+    }
+
+    // returns samples in file order, reading the top-level boxes one after
+    // another; samples within an mdat are read while inside of it
+    fn stream_next_event(&mut self, buf: &mut dyn Buffered) -> Result<Event, Mp4BoxError> {
+        loop {
+            for track in &mut self.tracks {
+                track.skip_dropped();
+            }
+
+            let next_track_sample = self
+                .tracks
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, t)| t.next_sample().map(|s| (idx, s)))
+                .min_by_key(|(_idx, s)| s.data_offset);
+
+            if let Some((track, sample)) = next_track_sample {
+                if sample.data_offset < self.stream_pos {
+                    diagnostic!(
+                        "dropping sample at {} which precedes the read position {}",
+                        sample.data_offset,
+                        self.stream_pos
+                    );
+                    self.tracks[track].advance();
+                    continue;
+                }
+
+                if sample.data_offset.saturating_add(sample.data_length as u64)
+                    <= self.next_box_offset
+                {
+                    if let Some(missing) = self.stream_skip_to(buf, sample.data_offset)? {
+                        return Ok(Event::MoreDataNeeded(missing));
+                    }
+
+                    let len = sample.data_length as usize;
+                    let mut data = self.sample_buffer(len);
+
+                    if let Some(missing) = self.stream_read(buf, &mut data[..len])? {
+                        return Ok(Event::MoreDataNeeded(missing));
+                    }
+
+                    let packet = self.make_packet(track, sample.with_data(data));
+
+                    return Ok(Event::NewPacket(packet));
+                }
+            } else if !self.fragmented {
+                return Ok(Event::Eof);
+            }
+
+            // the next sample lies in a following box
+            if let Some(missing) = self.stream_skip_to(buf, self.next_box_offset)? {
+                return Ok(Event::MoreDataNeeded(missing));
+            }
+
+            let pos = self.stream_pos;
+            let boks = match self.stream_peek_box_header(buf)? {
+                Streamed::Ready(boks) => boks,
+                Streamed::MoreDataNeeded(missing) => return Ok(Event::MoreDataNeeded(missing)),
+                // samples still refer to data beyond the end of the input
+                Streamed::End if next_track_sample.is_some() => {
+                    return Ok(Event::MoreDataNeeded(8))
+                }
+                Streamed::End => return Ok(Event::Eof),
+            };
+
+            debug!("{}: {:?}", pos, boks);
+
+            // box extends to the end of the file
+            self.next_box_offset = if boks.size == 0 {
+                u64::MAX
+            } else {
+                pos.saturating_add(boks.size)
+            };
+
+            match &boks.name {
+                b"moof" => match self.stream_read_box(buf, &boks)? {
+                    Streamed::Ready(mut reader) => {
+                        let moof = moof::MovieFragmentBox::read(&mut reader).context(pos, &boks)?;
+                        self.add_fragment(pos, moof)?;
+                    }
+                    Streamed::MoreDataNeeded(missing) => {
+                        // the buffered part is read again with the rest
+                        self.next_box_offset = pos;
+
+                        return Ok(Event::MoreDataNeeded(missing));
+                    }
+                    Streamed::End => return Ok(Event::MoreDataNeeded(8)),
+                },
+                // samples are read from within the box
+                b"mdat" => {}
+                _ => {
+                    debug!("skipping box {:?}", boks);
+                }
+            }
+        }
+    }
+
+    // makes `len` bytes from the stream position available in the stream
+    // buffer, returns the number of missing bytes if the input ended before
+    fn stream_fill(
+        &mut self,
+        buf: &mut dyn Buffered,
+        len: usize,
+    ) -> Result<Option<usize>, Mp4BoxError> {
+        let mut filled = self.stream_buffer.len();
+
+        if filled >= len {
+            return Ok(None);
+        }
+
+        self.stream_buffer.resize(len, 0);
+
+        let result = loop {
+            if filled == len {
+                break Ok(None);
+            }
+
+            match buf.read(&mut self.stream_buffer[filled..]) {
+                Ok(0) => break Ok(Some(len - filled)),
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => break Err(e.into()),
+            }
+        };
+
+        self.stream_buffer.truncate(filled);
+
+        result
+    }
+
+    // moves the stream position past buffered bytes
+    fn stream_consume(&mut self, len: usize) {
+        self.stream_buffer.drain(..len);
+        self.stream_pos += len as u64;
+    }
+
+    // reads `data.len()` bytes from the stream position, returns the number of
+    // missing bytes if the input ended before
+    fn stream_read(
+        &mut self,
+        buf: &mut dyn Buffered,
+        data: &mut [u8],
+    ) -> Result<Option<usize>, Mp4BoxError> {
+        if let Some(missing) = self.stream_fill(buf, data.len())? {
+            return Ok(Some(missing));
+        }
+
+        data.copy_from_slice(&self.stream_buffer[..data.len()]);
+        self.stream_consume(data.len());
+
+        Ok(None)
+    }
+
+    // discards the input up to `offset`, returns the number of missing bytes
+    // if the input ended before
+    fn stream_skip_to(
+        &mut self,
+        buf: &mut dyn Buffered,
+        offset: u64,
+    ) -> Result<Option<usize>, Mp4BoxError> {
+        let buffered = offset
+            .saturating_sub(self.stream_pos)
+            .min(self.stream_buffer.len() as u64);
+        self.stream_consume(buffered as usize);
+
+        let mut scratch = [0u8; 4096];
+
+        while self.stream_pos < offset {
+            let len = (offset - self.stream_pos).min(scratch.len() as u64) as usize;
+
+            match buf.read(&mut scratch[..len]) {
+                // skipped data does not need to be buffered at once
+                Ok(0) => return Ok(Some(len)),
+                Ok(n) => self.stream_pos += n as u64,
+                Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        Ok(None)
+    }
+
+    // reads the header of the box at the stream position, which stays
+    // buffered until the box is read or skipped
+    fn stream_peek_box_header(
+        &mut self,
+        buf: &mut dyn Buffered,
+    ) -> Result<Streamed<Boks>, Mp4BoxError> {
+        match self.stream_fill(buf, 8)? {
+            Some(8) => return Ok(Streamed::End),
+            Some(missing) => return Ok(Streamed::MoreDataNeeded(missing)),
+            None => {}
+        }
+
+        let header = &self.stream_buffer;
+        let mut boks = Boks {
+            name: header[4..8].try_into().unwrap(),
+            size: u32::from_be_bytes(header[0..4].try_into().unwrap()) as u64,
+            read_size: 8,
+        };
+
+        if boks.size == 1 {
+            if let Some(missing) = self.stream_fill(buf, 16)? {
+                return Ok(Streamed::MoreDataNeeded(missing));
+            }
+
+            boks.size = u64::from_be_bytes(self.stream_buffer[8..16].try_into().unwrap());
+            boks.read_size = 16;
+        }
+
+        // 0 extends the box to the end of the input
+        if boks.size != 0 && boks.size < boks.read_size as u64 {
+            return Err(Mp4BoxError::InvalidBoxSize(BoxPrint(boks.name), boks.size));
+        }
+
+        Ok(Streamed::Ready(boks))
+    }
+
+    // reads the top-level box at the stream position, whose header has been
+    // peeked, so it can be parsed from memory
+    fn stream_read_box(
+        &mut self,
+        buf: &mut dyn Buffered,
+        boks: &Boks,
+    ) -> Result<Streamed<AccReader<StreamedBox>>, Mp4BoxError> {
+        // the end of a pipe is unknown, so the size is capped instead
+        if boks.size == 0 || boks.size > MAX_STREAMED_BOX_SIZE {
+            return Err(Mp4BoxError::InvalidBoxSize(BoxPrint(boks.name), boks.size));
+        }
+
+        let size = boks.size as usize;
+
+        if let Some(missing) = self.stream_fill(buf, size)? {
+            return Ok(Streamed::MoreDataNeeded(missing));
+        }
+
+        let rest = self.stream_buffer.split_off(size);
+        let data = std::mem::replace(&mut self.stream_buffer, rest);
+
+        let streamed = StreamedBox {
+            data: Cursor::new(data),
+            offset: self.stream_pos,
+        };
+        self.stream_pos += boks.size;
+
+        Ok(Streamed::Ready(AccReader::new(streamed)))
+    }
+}
+
+impl Demuxer for Mp4Demuxer {
+    fn read_headers(
+        &mut self,
+        buf: &mut dyn Buffered,
+        info: &mut GlobalInfo,
+    ) -> AvResult<SeekFrom> {
+        let mode = self.parse_mode;
+        let (res, diagnostics) = diagnostics::collect(mode, || self.parse_streams(buf));
+        for diagnostic in diagnostics {
+            diagnostics::merge(&mut self.diagnostics, diagnostic);
+        }
+
+        if let Err(e) = res {
+            error!("{}", e);
+            return Err(e.into());
+        }
+
+        info.streams = self.tracks.iter().map(|t| t.stream.clone()).collect();
+
+        Ok(SeekFrom::Current(0))
+    }
+
+    fn read_event(&mut self, buf: &mut dyn Buffered) -> AvResult<(SeekFrom, Event)> {
+        let mode = self.parse_mode;
+        let (event, diagnostics) = diagnostics::collect(mode, || self.read_next_event(buf));
+        for diagnostic in diagnostics {
+            diagnostics::merge(&mut self.diagnostics, diagnostic);
+        }
+
+        Ok((SeekFrom::Current(0), event?))
+    }
+}
+
+struct Des {
+    d: Descr,
+}
+
+impl Descriptor for Des {
+    fn create(&self) -> Box<dyn Demuxer> {
+        Box::new(Mp4Demuxer::new())
+    }
+    fn describe(&self) -> &Descr {
+        &self.d
+    }
+    fn probe(&self, data: &[u8]) -> u8 {
+        probe(data)
+    }
+}
+
+const PROBE_SCORE_MAX: u8 = 100;
+
+fn brand_score(brand: &BoxName) -> u8 {
+    match ftyp::BrandFamily::from_brand(brand) {
+        Some(ftyp::BrandFamily::Iso)
+        | Some(ftyp::BrandFamily::Cmaf)
+        | Some(ftyp::BrandFamily::ThreeGpp)
+        | Some(ftyp::BrandFamily::QuickTime) => PROBE_SCORE_MAX,
+        // image items are not demuxed, only image sequences are in tracks
+        Some(ftyp::BrandFamily::Heif) => match brand {
+            b"msf1" | b"hevc" | b"hevx" | b"avis" => PROBE_SCORE_MAX,
+            _ => 25,
+        },
+        None => 80,
+    }
+}
+
+// scores the leading top-level boxes, files without ftyp (e.g. older
+// QuickTime movies) are recognised by a chain of well-formed known boxes
+fn probe(data: &[u8]) -> u8 {
+    let mut offset = 0;
+    let mut known_boxes = 0;
+    let mut has_movie_data = false;
+
+    while data.len() >= offset + 8 {
+        let header = &data[offset..];
+        let size = u32::from_be_bytes(header[0..4].try_into().unwrap()) as u64;
+        let name: BoxName = header[4..8].try_into().unwrap();
+
+        let header_size = if size == 1 { 16 } else { 8 };
+
+        let size = match size {
+            // box extends to the end of the file
+            0 => u64::MAX,
+            1 if header.len() >= 16 => u64::from_be_bytes(header[8..16].try_into().unwrap()),
+            1 => u64::MAX,
+            size => size,
+        };
+
+        if size < 8 {
+            break;
+        }
+
+        match &name {
+            b"ftyp" | b"styp" => {
+                if header.len() < header_size + 4 {
+                    return 50;
+                }
+
+                // major brand and compatible brands, minor version skipped
+                let end = (size.min(header.len() as u64) as usize) & !3;
+                let major = header[header_size..header_size + 4].try_into().unwrap();
+                let compatible = header
+                    .get(header_size + 8..end)
+                    .unwrap_or(&[])
+                    .chunks_exact(4);
+
+                return compatible
+                    .map(|brand| brand_score(&brand.try_into().unwrap()))
+                    .fold(brand_score(&major), u8::max);
+            }
+            b"moov" | b"mdat" => has_movie_data = true,
+            b"free" | b"skip" | b"wide" | b"pnot" => {}
+            _ => break,
+        }
+
+        known_boxes += 1;
+
+        match (offset as u64).checked_add(size) {
+            Some(next) if next <= data.len() as u64 => offset = next as usize,
+            _ => break,
+        }
+    }
+
+    match (known_boxes, has_movie_data) {
+        (0, _) => 0,
+        (_, true) => 75,
+        (_, false) => 25,
+    }
+}
+
+pub const MP4_DESC: &dyn Descriptor = &Des {
+    d: Descr {
         name: "mp4",
         demuxer: "mp4",
         description: "MP4 demuxer",
-        extensions: &["mp4"],
-        mime: &["video/mp4", "audio/mp4"],
+        extensions: &["mp4", "m4v", "m4a", "3gp", "3g2", "mov"],
+        mime: &[
+            "video/mp4",
+            "audio/mp4",
+            "video/3gpp",
+            "audio/3gpp",
+            "video/quicktime",
+        ],
     },
 };
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_box;
+
+    use crate::boxes::ctts::CompositionOffsetEntry;
+    use crate::boxes::elst::EditListEntry;
+
+    fn edit_list(entries: Vec<EditListEntry>, media_timescale: u32) -> EditList {
+        let edts = edts::EditBox::new(elst::EditListBox::new(entries));
+
+        EditList::new(Some(&edts), 1000, media_timescale)
+    }
+
+    fn rate(segment_duration: u64, media_time: i64, rate: i32) -> EditListEntry {
+        EditListEntry {
+            segment_duration,
+            media_time,
+            media_rate_integer: (rate >> 16) as i16,
+            media_rate_fraction: rate as i16,
+        }
+    }
+
+    #[test]
+    fn without_edits() {
+        let edits = EditList::new(None, 1000, 1000);
+
+        assert_eq!(edits.start(), None);
+        assert_eq!(edits.map(-100), (-100, true));
+        assert_eq!(edits.map(5000), (5000, true));
+    }
+
+    #[test]
+    fn empty_edit_and_media_time() {
+        let edits = edit_list(
+            vec![EditListEntry::empty(500), EditListEntry::new(2000, 1000)],
+            1000,
+        );
+
+        assert_eq!(edits.start(), Some(500));
+        assert_eq!(edits.map(1000), (500, true));
+        assert_eq!(edits.map(2999), (2499, true));
+        // before and after the edit
+        assert_eq!(edits.map(999), (499, false));
+        assert_eq!(edits.map(3000), (2500, false));
+    }
+
+    #[test]
+    fn rescaled_edits() {
+        let edits = edit_list(
+            vec![EditListEntry::empty(10), EditListEntry::new(1000, 1024)],
+            48000,
+        );
+
+        assert_eq!(edits.start(), Some(480));
+        assert_eq!(edits.map(1024), (480, true));
+        assert_eq!(edits.map(0), (-544, false));
+    }
+
+    #[test]
+    fn dwell_edit() {
+        let edits = edit_list(
+            vec![rate(1000, 200, 0), EditListEntry::new(1000, 200)],
+            1000,
+        );
+
+        assert_eq!(edits.map(200), (0, true));
+        assert_eq!(edits.map(300), (1100, true));
+        assert_eq!(edits.map(100), (0, false));
+    }
+
+    #[test]
+    fn slow_motion_edit() {
+        let edits = edit_list(vec![rate(1000, 0, 0x8000)], 1000);
+
+        assert_eq!(edits.map(250), (500, true));
+        assert_eq!(edits.map(499), (998, true));
+        assert_eq!(edits.map(500), (1000, false));
+    }
+
+    #[test]
+    fn dts_shift() {
+        let offsets = |offsets: &[i32]| {
+            ctts::CompositionOffsetBox::new(
+                offsets
+                    .iter()
+                    .map(|&offset| CompositionOffsetEntry { count: 1, offset })
+                    .collect(),
+            )
+        };
+        let cslg = |shift| cslg::CompositionToDecodeBox::new(shift, 0, 0, 0, 0);
+
+        assert_eq!(get_dts_shift(None, None), 0);
+        assert_eq!(get_dts_shift(Some(&offsets(&[0, 200, 100])), None), 0);
+        assert_eq!(get_dts_shift(Some(&offsets(&[0, 200, -100])), None), 100);
+        // cslg takes precedence over the offsets
+        assert_eq!(
+            get_dts_shift(Some(&offsets(&[0, 200, -100])), Some(&cslg(250))),
+            250
+        );
+        assert_eq!(
+            get_dts_shift(Some(&offsets(&[0, 200, -100])), Some(&cslg(-10))),
+            0
+        );
+    }
+
+    fn ftyp(major: &[u8; 4], compatible: &[&[u8; 4]]) -> Vec<u8> {
+        let mut contents = major.to_vec();
+        contents.extend(&[0; 4]);
+        for brand in compatible {
+            contents.extend(*brand);
+        }
+
+        test_box(b"ftyp", &contents)
+    }
+
+    // the same box with a 64-bit size
+    fn large(boks: &[u8]) -> Vec<u8> {
+        let mut data = vec![0, 0, 0, 1];
+        data.extend(&boks[4..8]);
+        data.extend(&(boks.len() as u64 + 8).to_be_bytes());
+        data.extend(&boks[8..]);
+        data
+    }
+
+    #[test]
+    fn probe_brands() {
+        assert_eq!(probe(&ftyp(b"isom", &[b"isom", b"avc1"])), PROBE_SCORE_MAX);
+        assert_eq!(probe(&ftyp(b"abcd", &[b"mp41"])), PROBE_SCORE_MAX);
+        assert_eq!(probe(&ftyp(b"heic", &[b"mif1"])), 25);
+        assert_eq!(probe(&ftyp(b"abcd", &[])), 80);
+        assert_eq!(probe(&ftyp(b"heic", &[b"mif1", b"msf1"])), PROBE_SCORE_MAX);
+    }
+
+    #[test]
+    fn probe_large_ftyp() {
+        assert_eq!(probe(&large(&ftyp(b"heic", &[b"mif1"]))), 25);
+        assert_eq!(
+            probe(&large(&ftyp(b"heic", &[b"mif1", b"msf1"]))),
+            PROBE_SCORE_MAX
+        );
+        assert_eq!(probe(&large(&ftyp(b"isom", &[]))), PROBE_SCORE_MAX);
+    }
+
+    #[test]
+    fn probe_without_ftyp() {
+        let mut data = test_box(b"wide", &[]);
+        data.extend(test_box(b"mdat", &[0; 16]));
+
+        assert_eq!(probe(&data), 75);
+        assert_eq!(probe(&test_box(b"free", &[0; 16])), 25);
+        assert_eq!(probe(b"not a movie at all"), 0);
+    }
+}
@@ -0,0 +1,222 @@
+//! Problems that were worked around while reading, e.g. skipped boxes or
+//! dropped tracks. They are logged as warnings and, while a demuxer reads,
+//! collected for `Mp4Demuxer::diagnostics`.
+//!
+//! Deviations from the specification are governed by the `ParseMode`: they
+//! fail the read in strict mode and become diagnostics in lenient mode.
+
+use log::*;
+
+use crate::Mp4BoxError;
+
+use std::cell::{Cell, RefCell};
+use std::fmt;
+
+// distinct messages kept, any further ones are only logged
+const MAX_DIAGNOSTICS: usize = 256;
+
+/// Records a diagnostic, optionally with the offset of the box concerned:
+/// `diagnostic!(at pos, "skipping box {:?}", boks)`.
+macro_rules! diagnostic {
+    (at $offset:expr, $($arg:tt)+) => {
+        $crate::diagnostics::record(Some($offset), format!($($arg)+))
+    };
+    ($($arg:tt)+) => {
+        $crate::diagnostics::record(None, format!($($arg)+))
+    };
+}
+
+/// Reports a deviation from the specification, an error in strict mode:
+/// `deviation!(at pos, "{} trailing bytes", len)?`.
+macro_rules! deviation {
+    (at $offset:expr, $($arg:tt)+) => {
+        $crate::diagnostics::tolerate(
+            Some($offset),
+            $crate::Mp4BoxError::Nonconforming(format!($($arg)+)),
+        )
+    };
+    ($($arg:tt)+) => {
+        $crate::diagnostics::tolerate(
+            None,
+            $crate::Mp4BoxError::Nonconforming(format!($($arg)+)),
+        )
+    };
+}
+
+/// How deviations from the specification are handled while reading, e.g.
+/// missing required boxes, out-of-range values, trailing bytes or entry
+/// counts that disagree.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ParseMode {
+    /// Deviations are errors, for validating files.
+    Strict,
+    /// Deviations are worked around where possible and recorded as
+    /// diagnostics, for playback.
+    #[default]
+    Lenient,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    /// Offset in the input of the box concerned, if any, the first one for
+    /// repeated messages.
+    pub offset: Option<u64>,
+    pub message: String,
+    /// How often the same message was recorded after the first time.
+    pub repeated: u32,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self.offset {
+            Some(offset) => write!(formatter, "{}: {}", offset, self.message)?,
+            None => write!(formatter, "{}", self.message)?,
+        }
+
+        if self.repeated > 0 {
+            write!(formatter, " (repeated {} times)", self.repeated)?;
+        }
+
+        Ok(())
+    }
+}
+
+thread_local! {
+    static MODE: Cell<ParseMode> = Cell::new(ParseMode::Lenient);
+    static COLLECTED: RefCell<Option<Vec<Diagnostic>>> = RefCell::new(None);
+}
+
+pub(crate) fn is_strict() -> bool {
+    MODE.with(|mode| mode.get()) == ParseMode::Strict
+}
+
+/// Fails with `error` in strict mode, records it otherwise.
+pub(crate) fn tolerate(offset: Option<u64>, error: Mp4BoxError) -> Result<(), Mp4BoxError> {
+    if is_strict() {
+        Err(error)
+    } else {
+        record(offset, error.to_string());
+
+        Ok(())
+    }
+}
+
+pub(crate) fn record(offset: Option<u64>, message: String) {
+    let diagnostic = Diagnostic {
+        offset,
+        message,
+        repeated: 0,
+    };
+
+    warn!("{}", diagnostic);
+
+    COLLECTED.with(|collected| {
+        if let Some(collected) = collected.borrow_mut().as_mut() {
+            merge(collected, diagnostic);
+        }
+    });
+}
+
+/// Adds `diagnostic` to `diagnostics`, counting it as a repetition of an
+/// earlier one with the same message. Only the first 256 distinct messages
+/// are kept.
+pub(crate) fn merge(diagnostics: &mut Vec<Diagnostic>, diagnostic: Diagnostic) {
+    match diagnostics
+        .iter_mut()
+        .find(|d| d.message == diagnostic.message)
+    {
+        Some(earlier) => {
+            earlier.repeated = earlier
+                .repeated
+                .saturating_add(diagnostic.repeated)
+                .saturating_add(1)
+        }
+        None if diagnostics.len() < MAX_DIAGNOSTICS => diagnostics.push(diagnostic),
+        None => {}
+    }
+}
+
+/// Runs `f` reading in `mode`, returning the diagnostics recorded meanwhile
+/// on this thread. Boxes read outside of it are read leniently.
+pub fn collect<T>(mode: ParseMode, f: impl FnOnce() -> T) -> (T, Vec<Diagnostic>) {
+    let _outer = Outer {
+        mode: MODE.with(|outer| outer.replace(mode)),
+        collected: COLLECTED.with(|collected| collected.replace(Some(Vec::new()))),
+    };
+
+    let res = f();
+    let diagnostics = COLLECTED.with(|collected| collected.borrow_mut().take());
+
+    (res, diagnostics.unwrap_or_default())
+}
+
+// the state of an enclosing `collect`, restored even if `f` panics
+struct Outer {
+    mode: ParseMode,
+    collected: Option<Vec<Diagnostic>>,
+}
+
+impl Drop for Outer {
+    fn drop(&mut self) {
+        MODE.with(|mode| mode.set(self.mode));
+        COLLECTED.with(|collected| collected.replace(self.collected.take()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_messages() {
+        let ((), diagnostics) = collect(ParseMode::Lenient, || {
+            for offset in 0..1000 {
+                record(Some(offset), "same".into());
+                record(Some(offset), format!("distinct {}", offset));
+            }
+        });
+
+        assert_eq!(diagnostics.len(), MAX_DIAGNOSTICS);
+        assert_eq!(diagnostics[0].message, "same");
+        assert_eq!(diagnostics[0].offset, Some(0));
+        assert_eq!(diagnostics[0].repeated, 999);
+    }
+
+    #[test]
+    fn strict_deviations() {
+        let (res, diagnostics) = collect(ParseMode::Strict, || deviation!(at 8, "deviation"));
+
+        assert!(matches!(res, Err(Mp4BoxError::Nonconforming(_))));
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn nested() {
+        let (inner, outer) = collect(ParseMode::Lenient, || {
+            record(None, "outer".into());
+
+            let ((), inner) = collect(ParseMode::Strict, || {
+                assert!(is_strict());
+                record(None, "inner".into());
+            });
+
+            assert!(!is_strict());
+            inner
+        });
+
+        assert_eq!(inner[0].message, "inner");
+        assert_eq!(outer.len(), 1);
+        assert_eq!(outer[0].message, "outer");
+    }
+
+    #[test]
+    fn restored_after_panic() {
+        let res = std::panic::catch_unwind(|| {
+            collect(ParseMode::Strict, || panic!("reading failed"));
+        });
+
+        assert!(res.is_err());
+        assert!(!is_strict());
+        assert!(COLLECTED.with(|collected| collected.borrow().is_none()));
+    }
+}
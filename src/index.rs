@@ -0,0 +1,633 @@
+use std::mem::size_of;
+
+use crate::boxes::sdtp::SampleDependency;
+use crate::boxes::sgpd::SampleGroupEntry;
+use crate::boxes::stbl::{ChunkOffsets, SampleTableBox};
+use crate::boxes::stsz::SampleSizes;
+use crate::boxes::subs::{SubSample, SubSampleLayout};
+use crate::{BoxName, BoxPrint};
+
+/// A sample resolved from the sample table. Times are in media timescale.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndexedSample {
+    pub number: u32,
+    pub data_offset: u64,
+    pub data_length: u32,
+    pub decode_time: u64,
+    pub composition_offset: i32,
+    pub duration: u32,
+    pub keyframe: bool,
+    pub sample_description_index: u32,
+}
+
+// samples_per_chunk samples in each chunk from first_chunk on
+#[derive(Clone, Copy)]
+struct ChunkRun {
+    first_sample: u32,
+    first_chunk: u32,
+    samples_per_chunk: u32,
+    sample_description_index: u32,
+}
+
+#[derive(Clone, Copy)]
+struct TimeRun {
+    first_sample: u32,
+    base: u64,
+    delta: u32,
+}
+
+#[derive(Clone, Copy)]
+struct OffsetRun {
+    first_sample: u32,
+    offset: i32,
+}
+
+#[derive(Clone, Copy)]
+struct GroupRun {
+    first_sample: u32,
+    // one-based, 0 if the samples are not in a group
+    description_index: u32,
+}
+
+struct SampleGroups {
+    grouping_type: BoxName,
+    runs: Vec<GroupRun>,
+    descriptions: Vec<SampleGroupEntry>,
+    // used for the samples after the last run
+    default_index: u32,
+}
+
+impl SampleGroups {
+    fn description_index(&self, sample: u32) -> u32 {
+        match self.runs.last() {
+            Some(last) if sample < last.first_sample => {
+                find_run(&self.runs, sample, |r| r.first_sample).map_or(0, |r| r.description_index)
+            }
+            _ => self.default_index,
+        }
+    }
+
+    fn get(&self, sample: u32) -> Option<&SampleGroupEntry> {
+        let index = self.description_index(sample);
+
+        self.descriptions.get(index.checked_sub(1)? as usize)
+    }
+}
+
+// the running size at every SIZE_SUM_INTERVAL-th sample, including the end
+fn running_sizes(sizes: impl Iterator<Item = u64>) -> Vec<u64> {
+    let mut sums = vec![0];
+    let mut sum = 0u64;
+
+    for (idx, size) in sizes.enumerate() {
+        sum = sum.saturating_add(size);
+
+        if (idx + 1) % SIZE_SUM_INTERVAL == 0 {
+            sums.push(sum);
+        }
+    }
+
+    sums.shrink_to_fit();
+    sums
+}
+
+// finds the run containing `sample`, runs are sorted by their first sample
+fn find_run<T>(runs: &[T], sample: u32, first_sample: impl Fn(&T) -> u32) -> Option<&T> {
+    let idx = runs.partition_point(|r| first_sample(r) <= sample);
+
+    runs.get(idx.checked_sub(1)?)
+}
+
+// samples between two entries of the running size table
+const SIZE_SUM_INTERVAL: usize = 64;
+
+/// Sample table of a track kept in its run-length coded form, so that memory
+/// stays proportional to the size of the `stbl` box rather than to the number
+/// of samples. On top of the tables themselves only the running sample size at
+/// every 64th sample is kept, 8 bytes per 64 sample sizes, see
+/// [`SampleIndex::heap_size`]. Lookups by sample number or decode time are
+/// binary searches over the runs; the data offset adds at most 64 sample sizes
+/// to the closest running size.
+pub struct SampleIndex {
+    sample_count: u32,
+    chunks: Vec<ChunkRun>,
+    chunk_offsets: ChunkOffsets,
+    sizes: SampleSizes,
+    // sum of the sizes before every SIZE_SUM_INTERVAL-th sample, empty for
+    // a constant sample size
+    size_sums: Vec<u64>,
+    times: Vec<TimeRun>,
+    composition_offsets: Vec<OffsetRun>,
+    // zero-based, sorted; None if every sample is a sync sample
+    sync_samples: Option<Vec<u32>>,
+    // one sdtp byte per sample
+    dependencies: Option<Vec<u8>>,
+    groups: Vec<SampleGroups>,
+    // zero-based sample numbers, sorted; only samples listed in subs
+    subsamples: Vec<(u32, SubSampleLayout)>,
+}
+
+impl SampleIndex {
+    pub fn new(stbl: SampleTableBox) -> Self {
+        let mut times = Vec::with_capacity(stbl.stts.entries.len());
+        let mut first_sample = 0u32;
+        let mut base = 0u64;
+
+        for entry in &stbl.stts.entries {
+            if entry.count == 0 {
+                continue;
+            }
+
+            times.push(TimeRun {
+                first_sample,
+                base,
+                delta: entry.delta,
+            });
+
+            first_sample = first_sample.saturating_add(entry.count);
+            base = base.saturating_add(entry.count as u64 * entry.delta as u64);
+        }
+
+        // a constant sample size leaves the sample count to the time table
+        let sample_count = match &stbl.stsz.sample_sizes {
+            SampleSizes::Constant(_) => first_sample,
+            SampleSizes::Variable(sizes) => sizes.len() as u32,
+            SampleSizes::Compact { sizes, .. } => sizes.len() as u32,
+        };
+
+        let mut composition_offsets = Vec::new();
+        let mut first_sample = 0u32;
+
+        for entry in stbl.ctts.iter().flat_map(|ctts| &ctts.entries) {
+            if entry.count == 0 {
+                continue;
+            }
+
+            composition_offsets.push(OffsetRun {
+                first_sample,
+                offset: entry.offset,
+            });
+
+            first_sample = first_sample.saturating_add(entry.count);
+        }
+
+        let chunk_count = stbl.chunk_offsets.len() as u32;
+        let mut chunks = Vec::with_capacity(stbl.stsc.entries.len());
+        let mut first_sample = 0u32;
+
+        for (idx, entry) in stbl.stsc.entries.iter().enumerate() {
+            let first_chunk = entry.first_chunk.saturating_sub(1);
+            let end_chunk = stbl
+                .stsc
+                .entries
+                .get(idx + 1)
+                .map(|next| next.first_chunk.saturating_sub(1))
+                .unwrap_or(chunk_count)
+                .min(chunk_count);
+
+            if end_chunk <= first_chunk || entry.samples_per_chunk == 0 {
+                continue;
+            }
+
+            chunks.push(ChunkRun {
+                first_sample,
+                first_chunk,
+                samples_per_chunk: entry.samples_per_chunk,
+                sample_description_index: entry.sample_description_index,
+            });
+
+            first_sample = first_sample
+                .saturating_add((end_chunk - first_chunk).saturating_mul(entry.samples_per_chunk));
+        }
+
+        let sync_samples = stbl.stss.map(|stss| {
+            let mut samples: Vec<u32> = stss
+                .sync_samples
+                .into_iter()
+                .filter_map(|s| s.checked_sub(1))
+                .collect();
+
+            samples.sort_unstable();
+            samples.dedup();
+            samples.shrink_to_fit();
+            samples
+        });
+
+        let dependencies = stbl.sdtp.map(|sdtp| {
+            let mut bytes: Vec<u8> = sdtp.entries.iter().map(|e| e.to_byte()).collect();
+
+            bytes.truncate(sample_count as usize);
+            bytes
+        });
+
+        let mut descriptions = stbl.sgpd;
+        let mut groups = Vec::with_capacity(descriptions.len());
+
+        for sbgp in stbl.sbgp {
+            let sgpd = match descriptions
+                .iter()
+                .position(|d| d.grouping_type == sbgp.grouping_type)
+            {
+                Some(idx) => descriptions.swap_remove(idx),
+                None => {
+                    diagnostic!(
+                        "no descriptions for sample group {:?}",
+                        BoxPrint(sbgp.grouping_type)
+                    );
+                    continue;
+                }
+            };
+
+            let mut runs = Vec::with_capacity(sbgp.entries.len());
+            let mut first_sample = 0u32;
+
+            for entry in &sbgp.entries {
+                if entry.sample_count == 0 {
+                    continue;
+                }
+
+                // indices above 0x10000 refer to descriptions in fragments
+                runs.push(GroupRun {
+                    first_sample,
+                    description_index: entry.group_description_index,
+                });
+
+                first_sample = first_sample.saturating_add(entry.sample_count);
+            }
+
+            // marks the end of the mapped samples
+            runs.push(GroupRun {
+                first_sample,
+                description_index: 0,
+            });
+
+            groups.push(SampleGroups {
+                grouping_type: sbgp.grouping_type,
+                runs,
+                descriptions: sgpd.entries,
+                default_index: sgpd.default_sample_description_index.unwrap_or(0),
+            });
+        }
+
+        // descriptions with a default apply to all samples without sbgp
+        for sgpd in descriptions {
+            if let Some(default_index) = sgpd.default_sample_description_index {
+                groups.push(SampleGroups {
+                    grouping_type: sgpd.grouping_type,
+                    runs: Vec::new(),
+                    descriptions: sgpd.entries,
+                    default_index,
+                });
+            }
+        }
+
+        let mut subsamples: Vec<_> = stbl
+            .subs
+            .iter()
+            .flat_map(|subs| subs.layouts())
+            .filter(|(number, _)| *number >= 1 && *number <= sample_count)
+            .map(|(number, layout)| (number - 1, layout))
+            .collect();
+        subsamples.sort_by_key(|(number, _)| *number);
+
+        let size_sums = match &stbl.stsz.sample_sizes {
+            SampleSizes::Constant(_) => Vec::new(),
+            SampleSizes::Variable(sizes) => running_sizes(sizes.iter().map(|&s| s as u64)),
+            SampleSizes::Compact { sizes, .. } => running_sizes(sizes.iter().map(|&s| s as u64)),
+        };
+
+        times.shrink_to_fit();
+        composition_offsets.shrink_to_fit();
+
+        SampleIndex {
+            sample_count,
+            chunks,
+            chunk_offsets: stbl.chunk_offsets,
+            sizes: stbl.stsz.sample_sizes,
+            size_sums,
+            times,
+            composition_offsets,
+            sync_samples,
+            dependencies,
+            groups,
+            subsamples,
+        }
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+
+    /// Resolves the zero-based sample `number`.
+    pub fn get(&self, number: u32) -> Option<IndexedSample> {
+        if number >= self.sample_count {
+            return None;
+        }
+
+        let run = find_run(&self.chunks, number, |r| r.first_sample)?;
+        let chunk_sample = number - run.first_sample;
+        let chunk = run.first_chunk as u64 + (chunk_sample / run.samples_per_chunk) as u64;
+        let first_in_chunk = number - chunk_sample % run.samples_per_chunk;
+
+        let data_length = self.size(number)?;
+        let preceding_size = self
+            .size_before(number)?
+            .checked_sub(self.size_before(first_in_chunk)?)?;
+        let data_offset = self
+            .chunk_offsets
+            .get(chunk as usize)?
+            .checked_add(preceding_size)?;
+
+        let times = find_run(&self.times, number, |r| r.first_sample)?;
+        let decode_time = times
+            .base
+            .saturating_add((number - times.first_sample) as u64 * times.delta as u64);
+
+        let composition_offset = find_run(&self.composition_offsets, number, |r| r.first_sample)
+            .map(|r| r.offset)
+            .unwrap_or(0);
+
+        Some(IndexedSample {
+            number,
+            data_offset,
+            data_length,
+            decode_time,
+            composition_offset,
+            duration: times.delta,
+            keyframe: self.is_random_access(number),
+            sample_description_index: run.sample_description_index,
+        })
+    }
+
+    fn size(&self, number: u32) -> Option<u32> {
+        self.sizes.get(number as usize)
+    }
+
+    // the summed size of the samples before `number`
+    fn size_before(&self, number: u32) -> Option<u64> {
+        let number = number as usize;
+        let base = number / SIZE_SUM_INTERVAL * SIZE_SUM_INTERVAL;
+        // the tables may disagree on the sample count in crafted files
+        let preceding = base..number;
+
+        match &self.sizes {
+            SampleSizes::Constant(size) => Some(*size as u64 * number as u64),
+            SampleSizes::Variable(sizes) => Some(
+                self.size_sums.get(number / SIZE_SUM_INTERVAL)?
+                    + sizes.get(preceding)?.iter().map(|&s| s as u64).sum::<u64>(),
+            ),
+            SampleSizes::Compact { sizes, .. } => Some(
+                self.size_sums.get(number / SIZE_SUM_INTERVAL)?
+                    + sizes.get(preceding)?.iter().map(|&s| s as u64).sum::<u64>(),
+            ),
+        }
+    }
+
+    pub fn is_sync_sample(&self, number: u32) -> bool {
+        self.sync_samples
+            .as_ref()
+            .map_or(true, |s| s.binary_search(&number).is_ok())
+    }
+
+    pub fn dependency(&self, number: u32) -> Option<SampleDependency> {
+        let byte = *self.dependencies.as_ref()?.get(number as usize)?;
+
+        Some(SampleDependency::from_byte(byte))
+    }
+
+    /// Sync samples and members of a `rap ` group, such as open-GOP pictures.
+    pub fn is_random_access(&self, number: u32) -> bool {
+        self.is_sync_sample(number) || self.sample_group(number, *b"rap ").is_some()
+    }
+
+    /// The description of the group of `grouping_type` that `number` belongs to.
+    pub fn sample_group(&self, number: u32, grouping_type: BoxName) -> Option<&SampleGroupEntry> {
+        self.groups
+            .iter()
+            .filter(|g| g.grouping_type == grouping_type)
+            .find_map(|g| g.get(number))
+    }
+
+    /// Descriptions of all groups `number` belongs to.
+    pub fn sample_groups(&self, number: u32) -> impl Iterator<Item = &SampleGroupEntry> {
+        self.groups.iter().filter_map(move |g| g.get(number))
+    }
+
+    /// Sub-sample layouts of `number`, one for each `subs` box listing it.
+    pub fn subsamples(&self, number: u32) -> impl Iterator<Item = &SubSampleLayout> {
+        let start = self.subsamples.partition_point(|(n, _)| *n < number);
+
+        self.subsamples[start..]
+            .iter()
+            .take_while(move |(n, _)| *n == number)
+            .map(|(_, layout)| layout)
+    }
+
+    /// Returns the sample being decoded at `decode_time`.
+    pub fn sample_at_time(&self, decode_time: u64) -> Option<u32> {
+        let idx = self.times.partition_point(|r| r.base <= decode_time);
+        let run = self.times.get(idx.checked_sub(1)?)?;
+
+        let offset = (decode_time - run.base) / run.delta.max(1) as u64;
+        let number = run.first_sample as u64 + offset;
+
+        // only the last run can end before the time
+        if number < self.sample_count as u64 {
+            Some(number as u32)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the closest sync sample at or before `number`.
+    pub fn sync_sample_before(&self, number: u32) -> Option<u32> {
+        match &self.sync_samples {
+            None => Some(number),
+            Some(samples) => {
+                let idx = samples.partition_point(|&s| s <= number);
+
+                samples.get(idx.checked_sub(1)?).copied()
+            }
+        }
+    }
+
+    /// Returns the closest random access sample at or before `number`.
+    pub fn random_access_before(&self, number: u32) -> Option<u32> {
+        let sync = self.sync_sample_before(number);
+
+        let rap = self
+            .groups
+            .iter()
+            .filter(|g| g.grouping_type == *b"rap ")
+            .filter_map(|g| {
+                if g.description_index(number) != 0 {
+                    return Some(number);
+                }
+
+                // the last grouped sample of an earlier run
+                let idx = g.runs.partition_point(|r| r.first_sample <= number);
+
+                g.runs[..idx]
+                    .windows(2)
+                    .rev()
+                    .find(|w| w[0].description_index != 0)
+                    .map(|w| w[1].first_sample - 1)
+            })
+            .max();
+
+        sync.max(rap)
+    }
+
+    /// Returns the sample decoding has to start from for `number` to be
+    /// presented correctly, i.e. the closest random access sample moved back
+    /// by its pre-roll.
+    pub fn seek_sample(&self, number: u32) -> Option<u32> {
+        let start = self.random_access_before(number)?;
+
+        let roll = match self
+            .sample_group(start, *b"roll")
+            .or_else(|| self.sample_group(start, *b"prol"))
+        {
+            Some(SampleGroupEntry::Roll(distance)) | Some(SampleGroupEntry::PreRoll(distance))
+                if *distance < 0 =>
+            {
+                distance.unsigned_abs() as u32
+            }
+            _ => 0,
+        };
+
+        Some(start.saturating_sub(roll))
+    }
+
+    /// Total decode duration of all samples.
+    pub fn duration(&self) -> u64 {
+        self.times
+            .last()
+            .map(|r| {
+                let count = self.sample_count.saturating_sub(r.first_sample);
+
+                r.base.saturating_add(count as u64 * r.delta as u64)
+            })
+            .unwrap_or(0)
+    }
+
+    /// Memory held by the index on the heap, in bytes.
+    pub fn heap_size(&self) -> usize {
+        let chunk_offsets = match &self.chunk_offsets {
+            ChunkOffsets::Stco(stco) => stco.chunk_offsets.capacity() * size_of::<u32>(),
+            ChunkOffsets::Co64(co64) => co64.chunk_offsets.capacity() * size_of::<u64>(),
+        };
+
+        let sizes = match &self.sizes {
+            SampleSizes::Constant(_) => 0,
+            SampleSizes::Variable(sizes) => sizes.capacity() * size_of::<u32>(),
+            SampleSizes::Compact { sizes, .. } => sizes.capacity() * size_of::<u16>(),
+        };
+
+        self.chunks.capacity() * size_of::<ChunkRun>()
+            + chunk_offsets
+            + sizes
+            + self.size_sums.capacity() * size_of::<u64>()
+            + self.times.capacity() * size_of::<TimeRun>()
+            + self.composition_offsets.capacity() * size_of::<OffsetRun>()
+            + self
+                .sync_samples
+                .as_ref()
+                .map_or(0, |s| s.capacity() * size_of::<u32>())
+            + self.dependencies.as_ref().map_or(0, |d| d.capacity())
+            + self
+                .groups
+                .iter()
+                .map(|g| g.runs.capacity() * size_of::<GroupRun>())
+                .sum::<usize>()
+            + self.subsamples.capacity() * size_of::<(u32, SubSampleLayout)>()
+            + self
+                .subsamples
+                .iter()
+                .map(|(_, l)| l.subsamples.capacity() * size_of::<SubSample>())
+                .sum::<usize>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::boxes::codec::stsd::SampleDescriptionBox;
+    use crate::boxes::stco::ChunkOffsetBox;
+    use crate::boxes::stsc::{SampleToChunkBox, SampleToChunkEntry};
+    use crate::boxes::stsz::SampleSizeBox;
+    use crate::boxes::stts::{TimeToSampleBox, TimeToSampleEntry};
+
+    // two chunks of 100 samples
+    fn index(sizes: SampleSizes) -> SampleIndex {
+        let stbl = SampleTableBox::new(
+            SampleDescriptionBox::new(Vec::new()),
+            TimeToSampleBox::new(vec![TimeToSampleEntry {
+                count: 200,
+                delta: 10,
+            }]),
+            None,
+            None,
+            SampleToChunkBox::new(vec![SampleToChunkEntry {
+                first_chunk: 1,
+                samples_per_chunk: 100,
+                sample_description_index: 1,
+            }]),
+            SampleSizeBox::new(sizes),
+            ChunkOffsets::Stco(ChunkOffsetBox::new(vec![1000, 100_000])),
+            None,
+        );
+
+        SampleIndex::new(stbl)
+    }
+
+    fn check_offsets(index: &SampleIndex, size: impl Fn(u32) -> u64) {
+        for number in 0..200 {
+            let (chunk_offset, first_in_chunk) = if number < 100 {
+                (1000, 0)
+            } else {
+                (100_000, 100)
+            };
+            let offset = chunk_offset + (first_in_chunk..number).map(&size).sum::<u64>();
+
+            let sample = index.get(number).unwrap();
+            assert_eq!(sample.data_offset, offset);
+            assert_eq!(sample.data_length as u64, size(number));
+            assert_eq!(sample.decode_time, number as u64 * 10);
+        }
+
+        assert_eq!(index.get(200), None);
+    }
+
+    #[test]
+    fn variable_sizes() {
+        let index = index(SampleSizes::Variable((1..=200).collect()));
+
+        check_offsets(&index, |n| n as u64 + 1);
+    }
+
+    #[test]
+    fn compact_sizes() {
+        let index = index(SampleSizes::compact((0..200).map(|n| n % 7).collect()));
+
+        check_offsets(&index, |n| n as u64 % 7);
+    }
+
+    #[test]
+    fn constant_size() {
+        let index = index(SampleSizes::Constant(3));
+
+        check_offsets(&index, |_| 3);
+    }
+
+    #[test]
+    fn sizes_missing() {
+        // the size table ends before the chunks do
+        let index = index(SampleSizes::Variable((1..=150).collect()));
+
+        assert_eq!(index.sample_count(), 150);
+        assert!(index.get(149).is_some());
+        assert_eq!(index.get(150), None);
+    }
+}
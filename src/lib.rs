@@ -1,3 +1,5 @@
+use av_data::packet::Packet;
+use av_data::timeinfo::TimeInfo;
 use av_format::buffer::Buffered;
 use av_format::error::Error as AvError;
 use av_format::error::Result as AvResult;
@@ -6,9 +8,15 @@ use byteorder::{BigEndian, ByteOrder};
 
 use log::*;
 
+use std::cell::Cell;
 use std::fmt;
-use std::io::{Error as IoError, SeekFrom, Write};
+use std::io::{Error as IoError, ErrorKind, Read, SeekFrom, Write};
+use std::ops::Range;
 use std::string::FromUtf8Error;
+use std::sync::Arc;
+
+#[macro_use]
+pub mod diagnostics;
 
 pub mod boxes {
     pub mod codec {
@@ -21,62 +29,212 @@ pub mod boxes {
         pub mod avc1;
         pub mod avcc;
 
+        pub mod mp4a;
         pub mod mp4v;
+
+        pub mod tmcd;
+        pub mod wave;
     }
 
+    pub mod chpl;
     pub mod dinf;
     pub mod dref;
+    pub mod edts;
+    pub mod elst;
     pub mod ftyp;
+    pub mod gmhd;
     pub mod hdlr;
+    pub mod hmhd;
+    pub mod ilst;
     pub mod mdat;
     pub mod mdhd;
     pub mod mdia;
     pub mod mehd;
+    pub mod meta;
+    pub mod mfhd;
     pub mod minf;
+    pub mod moof;
     pub mod moov;
     pub mod mvex;
     pub mod mvhd;
+    pub mod nmhd;
+    pub mod sbgp;
+    pub mod sdtp;
+    pub mod sgpd;
     pub mod smhd;
     pub mod stbl;
+    pub mod sthd;
+    pub mod tfdt;
+    pub mod tfhd;
     pub mod tkhd;
+    pub mod traf;
     pub mod trak;
+    pub mod tref;
     pub mod trex;
+    pub mod trun;
+    pub mod udta;
     pub mod url;
+    pub mod urn;
     pub mod vmhd;
 
     pub mod co64;
+    pub mod cslg;
+    pub mod ctts;
     pub mod stco;
     pub mod stsc;
     pub mod stss;
     pub mod stsz;
     pub mod stts;
+    pub mod subs;
 }
 
 pub mod demuxer;
+pub mod index;
+pub mod metadata;
 pub mod muxer;
+pub mod recovery;
+pub mod timecode;
+pub mod tree;
+
+/// Bytes of a sample shared with an in-memory input, e.g. a memory map.
+#[derive(Clone)]
+pub struct SharedData {
+    source: Arc<dyn AsRef<[u8]> + Send + Sync>,
+    range: Range<usize>,
+}
+
+impl SharedData {
+    pub fn new(source: Arc<dyn AsRef<[u8]> + Send + Sync>, range: Range<usize>) -> Option<Self> {
+        let len = (*source).as_ref().len();
+
+        if range.start <= range.end && range.end <= len {
+            Some(SharedData { source, range })
+        } else {
+            None
+        }
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        &(*self.source).as_ref()[self.range.clone()]
+    }
+}
+
+impl fmt::Debug for SharedData {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "SharedData({:?})", self.range)
+    }
+}
+
+/// Sample information that does not fit into `Packet`, carried in
+/// `TimeInfo::user_private` by the demuxer and picked up by the muxer.
+#[derive(Clone, Debug, Default)]
+pub struct PacketInfo {
+    /// The sample lies outside of the track's edit list; it has to be decoded
+    /// but not presented.
+    pub discard: bool,
+    /// Number of zero bytes appended to `Packet::data`.
+    pub padding: usize,
+    /// The sample data when it is shared with the input, `Packet::data` is
+    /// left empty then.
+    pub shared_data: Option<SharedData>,
+    /// Descriptions of the sample groups the sample belongs to, e.g. its
+    /// roll distance.
+    pub sample_groups: Vec<boxes::sgpd::SampleGroupEntry>,
+    /// Dependencies of the sample from `sdtp` or the fragment sample flags.
+    pub dependency: Option<boxes::sdtp::SampleDependency>,
+    /// Layout of the sample's sub-samples from `subs`, e.g. its NAL units.
+    pub subsamples: Vec<boxes::subs::SubSampleLayout>,
+}
+
+impl PacketInfo {
+    pub fn get(time: &TimeInfo) -> Option<&PacketInfo> {
+        time.user_private.as_ref()?.downcast_ref()
+    }
+
+    /// The sample data of a packet, without padding.
+    pub fn data(packet: &Packet) -> &[u8] {
+        match PacketInfo::get(&packet.t) {
+            Some(PacketInfo {
+                shared_data: Some(shared),
+                ..
+            }) => shared.as_slice(),
+            Some(info) => &packet.data[..packet.data.len().saturating_sub(info.padding)],
+            None => &packet.data,
+        }
+    }
+}
 
 pub struct BoksIterator {
     size: u64,
     start: u64,
+    // end of the child returned last, where the next one starts
+    child_end: Cell<Option<u64>>,
 }
 
 impl BoksIterator {
     pub fn new(reader: &mut dyn Buffered, size: u64) -> Self {
-        let start = reader.seek(SeekFrom::Current(0)).unwrap();
+        let start = reader.seek(SeekFrom::Current(0)).unwrap_or_default();
 
-        BoksIterator { size, start }
+        BoksIterator {
+            size,
+            start,
+            child_end: Cell::new(None),
+        }
     }
 
-    fn next(&self, reader: &mut dyn Buffered) -> Option<(u64, Boks)> {
-        let pos = reader.seek(SeekFrom::Current(0)).unwrap();
+    fn next(&self, reader: &mut dyn Buffered) -> Result<Option<(u64, Boks)>, Mp4BoxError> {
+        let mut pos = pos(reader)?;
+
+        // boxes may have been extended with fields that are not read, but
+        // must not have been read past their end
+        if let Some(end) = self.child_end.take() {
+            if pos > end {
+                deviation!(at end, "box was read {} bytes past its end", pos - end)?;
+            }
+
+            if pos != end {
+                goto(reader, end)?;
+                pos = end;
+            }
+        }
+
+        let remaining = match self.size.checked_sub(pos.saturating_sub(self.start)) {
+            Some(remaining) if remaining > 0 => remaining,
+            _ => return Ok(None),
+        };
 
-        if pos - self.start >= self.size {
-            return None;
+        // QuickTime may end a container with a four-byte zero terminator
+        if remaining >= 4 && peek(reader, 4).map_or(false, |b| BigEndian::read_u32(b) == 0) {
+            debug!("{}: end of container", pos);
+            return Ok(None);
         }
 
-        let boks = Boks::peek(reader).ok()?;
+        if remaining < 8 {
+            deviation!(at pos, "{} trailing bytes", remaining)?;
+            return Ok(None);
+        }
+
+        let boks = match Boks::peek(reader) {
+            Ok(boks) => boks,
+            Err(err) => {
+                deviation!(at pos, "invalid box: {}", err)?;
+                return Ok(None);
+            }
+        };
+
+        if boks.size > remaining {
+            deviation!(
+                at pos,
+                "{:?} exceeds its parent by {} bytes",
+                boks,
+                boks.size - remaining
+            )?;
+            return Ok(None);
+        }
 
-        Some((pos, boks))
+        self.child_end.set(Some(pos + boks.size));
+
+        Ok(Some((pos, boks)))
     }
 }
 
@@ -137,17 +295,153 @@ pub enum Mp4BoxError {
 
     #[error("Unsupported MPEG-4 codec {0:02x}")]
     UnsupportedMpeg4Codec(u8),
+
+    #[error("Media data precedes the movie box, which cannot be read without seeking")]
+    MediaDataBeforeMovie,
+
+    #[error("Invalid compact sample size field size {0}")]
+    InvalidFieldSize(u8),
+
+    #[error("Invalid size {1} of box {0:?}")]
+    InvalidBoxSize(BoxPrint, u64),
+
+    #[error("Box {0:?} claims {1} entries, more than its size allows")]
+    TooManyEntries(BoxPrint, u32),
+
+    #[error("Track {0} has no sample entries")]
+    NoSampleEntries(u32),
+
+    #[error("Unsupported version {1} of box {0:?}")]
+    UnsupportedVersion(BoxPrint, u8),
+
+    #[error("{0} more bytes are needed")]
+    MoreDataNeeded(usize),
+
+    #[error("Seeking is not possible in streaming mode")]
+    SeekWhileStreaming,
+
+    #[error("Samples in {0} bytes at offset {1} cannot be told apart")]
+    UnsplittableSamples(u64, u64),
+
+    #[error("{0}")]
+    Nonconforming(String),
+
+    #[error("{path} at offset {offset}: {source}")]
+    InBox {
+        path: BoxPath,
+        /// Offset of the innermost box of the path.
+        offset: u64,
+        source: Box<Mp4BoxError>,
+    },
+}
+
+impl Mp4BoxError {
+    /// The path of the box the error occurred in, e.g. `moov/trak[1]/mdia`.
+    pub fn path(&self) -> Option<&BoxPath> {
+        match self {
+            Mp4BoxError::InBox { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// The offset of the box the error occurred in.
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            Mp4BoxError::InBox { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
+
+    /// The error without its location.
+    pub fn kind(&self) -> &Mp4BoxError {
+        match self {
+            Mp4BoxError::InBox { source, .. } => source.kind(),
+            error => error,
+        }
+    }
+
+    // prepends the box that contains the location of the error
+    pub(crate) fn within(self, name: BoxName, index: Option<usize>, pos: u64) -> Self {
+        match self {
+            Mp4BoxError::InBox {
+                mut path,
+                offset,
+                source,
+            } => {
+                path.0.insert(0, (BoxPrint(name), index));
+
+                Mp4BoxError::InBox {
+                    path,
+                    offset,
+                    source,
+                }
+            }
+            error => Mp4BoxError::InBox {
+                path: BoxPath(vec![(BoxPrint(name), index)]),
+                offset: pos,
+                source: Box::new(error),
+            },
+        }
+    }
 }
 
 impl From<Mp4BoxError> for AvError {
     fn from(error: Mp4BoxError) -> AvError {
         match error {
             Mp4BoxError::Io(err) => AvError::Io(err),
-            _ => AvError::InvalidData,
+            Mp4BoxError::MoreDataNeeded(missing) => AvError::MoreDataNeeded(missing),
+            // the error stays retrievable with `io::Error::get_ref`
+            error => AvError::Io(IoError::new(ErrorKind::InvalidData, error)),
         }
     }
 }
 
+/// Location of a box, the names of the boxes containing it and its own,
+/// with indices for boxes that may repeat.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BoxPath(Vec<(BoxPrint, Option<usize>)>);
+
+impl BoxPath {
+    pub fn names(&self) -> impl Iterator<Item = BoxName> + '_ {
+        self.0.iter().map(|(name, _)| name.0)
+    }
+}
+
+impl fmt::Display for BoxPath {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        for (i, (name, index)) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(formatter, "/")?;
+            }
+
+            write!(formatter, "{}", name)?;
+
+            if let Some(index) = index {
+                write!(formatter, "[{}]", index)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Adds the location of a child box to the errors of reading it.
+pub(crate) trait BoxContext<T> {
+    fn context(self, pos: u64, boks: &Boks) -> Result<T, Mp4BoxError>;
+
+    fn nth_context(self, pos: u64, boks: &Boks, index: usize) -> Result<T, Mp4BoxError>;
+}
+
+impl<T> BoxContext<T> for Result<T, Mp4BoxError> {
+    fn context(self, pos: u64, boks: &Boks) -> Result<T, Mp4BoxError> {
+        self.map_err(|e| e.within(boks.name, None, pos))
+    }
+
+    fn nth_context(self, pos: u64, boks: &Boks, index: usize) -> Result<T, Mp4BoxError> {
+        self.map_err(|e| e.within(boks.name, Some(index), pos))
+    }
+}
+
 pub(crate) fn non_empty<T>(boxes: Vec<T>, name: BoxName) -> Result<Vec<T>, Mp4BoxError> {
     if !boxes.is_empty() {
         Ok(boxes)
@@ -164,18 +458,76 @@ pub(crate) fn require_box<T>(val: Option<T>, name: BoxName) -> Result<T, Mp4BoxE
     }
 }
 
-pub(crate) fn require_either_box<T>(
+/// A missing required box fails strict reads, lenient ones go on with
+/// `default`.
+pub(crate) fn require_box_or<T>(
+    val: Option<T>,
+    name: BoxName,
+    default: impl FnOnce() -> T,
+) -> Result<T, Mp4BoxError> {
+    match val {
+        Some(val) => Ok(val),
+        None => {
+            diagnostics::tolerate(None, Mp4BoxError::RequiredBoxNotFound(BoxPrint(name)))?;
+
+            Ok(default())
+        }
+    }
+}
+
+pub(crate) fn require_either_box_or<T>(
     val: Option<T>,
     a: BoxName,
     b: BoxName,
+    default: impl FnOnce() -> T,
 ) -> Result<T, Mp4BoxError> {
-    if let Some(val) = val {
-        Ok(val)
+    match val {
+        Some(val) => Ok(val),
+        None => {
+            let missing = Mp4BoxError::RequiredEitherBoxesNotFound(BoxPrint(a), BoxPrint(b));
+            diagnostics::tolerate(None, missing)?;
+
+            Ok(default())
+        }
+    }
+}
+
+/// Reads `size` bytes without trusting `size` for the allocation up front.
+pub(crate) fn read_bytes(buf: &mut dyn Buffered, size: u64) -> Result<Vec<u8>, Mp4BoxError> {
+    let mut bytes = Vec::new();
+    (&mut *buf).take(size).read_to_end(&mut bytes)?;
+
+    if bytes.len() as u64 == size {
+        Ok(bytes)
     } else {
-        Err(Mp4BoxError::RequiredEitherBoxesNotFound(
-            BoxPrint(a),
-            BoxPrint(b),
-        ))
+        Err(Mp4BoxError::UnexpectedEos)
+    }
+}
+
+// limit for tables whose entries take no bytes, e.g. `trun` boxes of
+// samples with default values only
+const MAX_EMPTY_ENTRIES: u32 = 1 << 20;
+
+/// Checks a table's entry count against the bytes left in its box, so
+/// that crafted counts cannot trigger huge allocations. Lenient reads go on
+/// with the entries that fit.
+pub(crate) fn entry_count(
+    count: u32,
+    entry_size: u64,
+    remaining: u64,
+    name: BoxName,
+) -> Result<usize, Mp4BoxError> {
+    let fitting = match entry_size {
+        0 => MAX_EMPTY_ENTRIES as u64,
+        _ => remaining / entry_size,
+    };
+
+    if count as u64 <= fitting {
+        Ok(count as usize)
+    } else {
+        diagnostics::tolerate(None, Mp4BoxError::TooManyEntries(BoxPrint(name), count))?;
+
+        Ok(fitting as usize)
     }
 }
 
@@ -190,20 +542,23 @@ pub(crate) fn pos(buf: &mut dyn Buffered) -> Result<u64, Mp4BoxError> {
 }
 
 pub(crate) fn skip(buf: &mut dyn Buffered, count: u64) -> Result<(), Mp4BoxError> {
-    buf.seek(SeekFrom::Current(count as i64))?;
+    use std::convert::TryFrom;
+
+    let count = i64::try_from(count).map_err(|_| Mp4BoxError::UnexpectedEos)?;
+    buf.seek(SeekFrom::Current(count))?;
 
     Ok(())
 }
 
 pub(crate) fn peek(buf: &mut dyn Buffered, size: usize) -> Result<&[u8], Mp4BoxError> {
-    if size < buf.data().len() {
+    if size <= buf.data().len() {
         Ok(&buf.data()[..size])
     } else {
         buf.fill_buf()?;
 
         let data = buf.data();
 
-        if size < data.len() {
+        if size <= data.len() {
             Ok(&data[..size])
         } else {
             Err(Mp4BoxError::UnexpectedEos)
@@ -240,20 +595,30 @@ impl Boks {
 
         let mut size = BigEndian::read_u32(&contents[0..]) as u64;
         let name = contents[4..].try_into().unwrap();
+        let mut header_size = 8;
 
         if size == 1 {
             let contents = peek(buf, 16)?;
             size = BigEndian::read_u64(&contents[8..]);
+            header_size = 16;
         }
 
         Ok(Boks {
             name,
-            size,
+            size: Self::check_size(buf, name, size, header_size, 0)?,
             read_size: 0,
         })
     }
 
     pub fn read(buf: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let mut boks = Self::read_raw(buf)?;
+        boks.size = Self::check_size(buf, boks.name, boks.size, boks.read_size, boks.read_size)?;
+
+        Ok(boks)
+    }
+
+    // the header as it is stored, for inputs whose sizes cannot be trusted
+    pub(crate) fn read_raw(buf: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
         use std::convert::TryInto;
 
         let mut contents = [0u8; 8];
@@ -277,33 +642,40 @@ impl Boks {
     }
 
     pub fn read_named(buf: &mut dyn Buffered, expected: BoxName) -> Result<Self, Mp4BoxError> {
-        use std::convert::TryInto;
-
-        let mut contents = [0u8; 8];
-        buf.read_exact(&mut contents)?;
+        let boks = Self::read(buf)?;
 
-        let mut read_size = 8;
-        let mut size = BigEndian::read_u32(&contents[0..]) as u64;
-        let name = contents[4..].try_into().unwrap();
-
-        if name != expected {
+        if boks.name != expected {
             return Err(Mp4BoxError::UnexpectedName(
                 BoxPrint(expected),
-                BoxPrint(name),
+                BoxPrint(boks.name),
             ));
         }
 
-        if size == 1 {
-            buf.read_exact(&mut contents)?;
-            size = BigEndian::read_u64(&contents[..]);
-            read_size = 16;
-        }
+        Ok(boks)
+    }
 
-        Ok(Boks {
-            name,
-            size,
-            read_size,
-        })
+    // a size of 0 extends the box to the end of the file, `read` are the
+    // header bytes already consumed
+    fn check_size(
+        buf: &mut dyn Buffered,
+        name: BoxName,
+        size: u64,
+        header_size: u8,
+        read: u8,
+    ) -> Result<u64, Mp4BoxError> {
+        match size {
+            0 => {
+                let current = buf.seek(SeekFrom::Current(0))?;
+                let end = buf.seek(SeekFrom::End(0))?;
+                buf.seek(SeekFrom::Start(current))?;
+
+                Ok((end + read as u64).saturating_sub(current))
+            }
+            size if size < header_size as u64 => {
+                Err(Mp4BoxError::InvalidBoxSize(BoxPrint(name), size))
+            }
+            size => Ok(size),
+        }
     }
 
     fn write(&self, writer: &mut dyn Write, size: u64) -> Result<(), Mp4BoxError> {
@@ -334,7 +706,7 @@ impl Boks {
     }
 
     pub fn remaining_size(&self) -> u64 {
-        self.size - self.read_size as u64
+        self.size.saturating_sub(self.read_size as u64)
     }
 }
 
@@ -407,7 +779,9 @@ impl FullBox {
     }
 
     pub fn remaining_size(&self) -> u64 {
-        self.boks.size - self.read_size as u64
+        self.boks
+            .remaining_size()
+            .saturating_sub(self.read_size as u64)
     }
 }
 
@@ -453,6 +827,20 @@ impl SampleEntry {
     fn size(&self, size: u64) -> u64 {
         self.boks.size(size + 8)
     }
+
+    pub fn name(&self) -> BoxName {
+        self.boks.name
+    }
+
+    /// One-based index of the `dref` entry locating the samples' data.
+    pub fn data_reference_index(&self) -> u16 {
+        self.data_reference_index
+    }
+
+    // size of the entry following the common fields
+    pub fn remaining_size(&self) -> u64 {
+        self.boks.remaining_size().saturating_sub(8)
+    }
 }
 
 #[derive(Debug)]
@@ -460,6 +848,8 @@ pub struct VisualSampleEntry {
     sample_entry: SampleEntry,
     width: u16,
     height: u16,
+    /// Name of the encoder, e.g. "Apple ProRes 422" in QuickTime files.
+    pub compressor_name: String,
     // clap: Option<CleanApertureBox>,
     // pasp: Option<PixelAspectRatioBox>,
 }
@@ -470,6 +860,7 @@ impl VisualSampleEntry {
             sample_entry: SampleEntry::new(name, data_reference_index),
             width,
             height,
+            compressor_name: String::new(),
         }
     }
 
@@ -479,13 +870,20 @@ impl VisualSampleEntry {
         let mut contents = [0u8; 70];
         buf.read_exact(&mut contents)?;
 
-        let width = BigEndian::read_u16(&contents[20..]);
-        let height = BigEndian::read_u16(&contents[24..]);
+        // QuickTime keeps its version, vendor and quality fields where ISO
+        // has reserved ones, so both share this layout
+        let width = BigEndian::read_u16(&contents[16..]);
+        let height = BigEndian::read_u16(&contents[18..]);
+
+        // a Pascal string padded to 32 bytes
+        let name_length = (contents[34] as usize).min(31);
+        let compressor_name = String::from_utf8_lossy(&contents[35..35 + name_length]).into_owned();
 
         Ok(VisualSampleEntry {
             sample_entry,
             width,
             height,
+            compressor_name,
         })
     }
 
@@ -498,6 +896,12 @@ impl VisualSampleEntry {
         BigEndian::write_u32(&mut bytes[20..], 0x0048_0000);
         BigEndian::write_u32(&mut bytes[24..], 0x0048_0000);
         BigEndian::write_u16(&mut bytes[32..], 1);
+
+        let name = self.compressor_name.as_bytes();
+        let name_length = name.len().min(31);
+        bytes[34] = name_length as u8;
+        bytes[35..35 + name_length].copy_from_slice(&name[..name_length]);
+
         BigEndian::write_u16(&mut bytes[66..], 0x0018);
         BigEndian::write_i16(&mut bytes[68..], -1);
 
@@ -511,6 +915,123 @@ impl VisualSampleEntry {
     }
 }
 
+#[derive(Debug)]
+pub struct AudioSampleEntry {
+    sample_entry: SampleEntry,
+    /// QuickTime sound description version, 1 and 2 add fields after the
+    /// common ones.
+    pub version: u16,
+    pub channel_count: u16,
+    pub sample_size: u16,
+    // 16.16 fixed point
+    pub sample_rate: u32,
+    // fields of QuickTime version 1 and 2 descriptions, kept as they are
+    quicktime_fields: Vec<u8>,
+}
+
+impl AudioSampleEntry {
+    pub fn new(
+        name: BoxName,
+        data_reference_index: u16,
+        channel_count: u16,
+        sample_size: u16,
+        sample_rate: u32,
+    ) -> Self {
+        AudioSampleEntry {
+            sample_entry: SampleEntry::new(name, data_reference_index),
+            version: 0,
+            channel_count,
+            sample_size,
+            sample_rate,
+            quicktime_fields: Vec::new(),
+        }
+    }
+
+    /// `stsd_version` tells QuickTime descriptions, which are in version 0
+    /// `stsd` boxes, from ISO entries of version 1 without extra fields.
+    pub fn read(buf: &mut dyn Buffered, stsd_version: u8) -> Result<Self, Mp4BoxError> {
+        let sample_entry = SampleEntry::read(buf)?;
+
+        let mut contents = [0u8; 20];
+        buf.read_exact(&mut contents)?;
+
+        let version = BigEndian::read_u16(&contents[0..]);
+        let channel_count = BigEndian::read_u16(&contents[8..]);
+        let sample_size = BigEndian::read_u16(&contents[10..]);
+        let sample_rate = BigEndian::read_u32(&contents[16..]);
+
+        let quicktime_size = match version {
+            1 if stsd_version == 0 => 16,
+            2 if stsd_version == 0 => 36,
+            _ => 0,
+        };
+
+        let mut quicktime_fields = vec![0u8; quicktime_size];
+        buf.read_exact(&mut quicktime_fields)?;
+
+        Ok(AudioSampleEntry {
+            sample_entry,
+            version,
+            channel_count,
+            sample_size,
+            sample_rate,
+            quicktime_fields,
+        })
+    }
+
+    // version 2 replaces the common fields, which hold fixed values then
+    fn version2_field(&self, offset: usize) -> Option<&[u8]> {
+        match self.version {
+            2 => self.quicktime_fields.get(offset..offset + 4),
+            _ => None,
+        }
+    }
+
+    /// The sample rate in Hz, version 2 stores it as a float.
+    pub fn sample_rate_hz(&self) -> f64 {
+        match self.quicktime_fields.get(4..12) {
+            Some(rate) if self.version == 2 => BigEndian::read_f64(rate),
+            _ => self.sample_rate as f64 / 65536.0,
+        }
+    }
+
+    pub fn channels(&self) -> u32 {
+        self.version2_field(12)
+            .map_or(self.channel_count as u32, BigEndian::read_u32)
+    }
+
+    pub fn bits_per_sample(&self) -> u32 {
+        self.version2_field(20)
+            .map_or(self.sample_size as u32, BigEndian::read_u32)
+    }
+
+    fn write(&self, writer: &mut dyn Write, size: u64) -> Result<(), Mp4BoxError> {
+        self.sample_entry.write(writer, size)?;
+
+        let mut bytes = [0u8; 20];
+        BigEndian::write_u16(&mut bytes[0..], self.version);
+        BigEndian::write_u16(&mut bytes[8..], self.channel_count);
+        BigEndian::write_u16(&mut bytes[10..], self.sample_size);
+        BigEndian::write_u32(&mut bytes[16..], self.sample_rate);
+
+        writer.write_all(&bytes[..])?;
+        writer.write_all(&self.quicktime_fields)?;
+
+        Ok(())
+    }
+
+    fn size(&self, size: u64) -> u64 {
+        self.sample_entry
+            .size(size + 20 + self.quicktime_fields.len() as u64)
+    }
+
+    pub fn remaining_size(&self) -> u64 {
+        self.sample_entry
+            .remaining_size()
+            .saturating_sub(20 + self.quicktime_fields.len() as u64)
+    }
+}
+
 pub type BoxName = [u8; 4];
 
 #[repr(transparent)]
@@ -540,13 +1061,18 @@ pub fn read_box_header(buf: &mut dyn Buffered) -> AvResult<(BoxName, u64, u64)>
     let mut size = BigEndian::read_u32(&size_type[..4]) as u64;
     let name = [size_type[4], size_type[5], size_type[6], size_type[7]];
 
-    if size == 1 {
+    let header_size = if size == 1 {
         buf.read_exact(&mut size_type)?;
         size = BigEndian::read_u64(&size_type[..]);
 
-        Ok((name, size, size - 16))
+        16
     } else {
-        Ok((name, size, size - 8))
+        8
+    };
+
+    match size.checked_sub(header_size) {
+        Some(remaining) => Ok((name, size, remaining)),
+        None => Err(AvError::InvalidData),
     }
 }
 
@@ -559,3 +1085,39 @@ pub fn read_box_flags(buf: &mut dyn Buffered) -> AvResult<(u8, u32)> {
 
     Ok((version, flags))
 }
+
+// a box of `contents` with a compact header
+#[cfg(test)]
+pub(crate) fn test_box(name: &[u8; 4], contents: &[u8]) -> Vec<u8> {
+    let mut data = ((contents.len() + 8) as u32).to_be_bytes().to_vec();
+    data.extend(name);
+    data.extend(contents);
+    data
+}
+
+#[cfg(test)]
+pub(crate) fn read_test_box<T: tree::TypedBox>(bytes: &[u8]) -> Result<T, Mp4BoxError> {
+    let mut reader = av_format::buffer::AccReader::new(std::io::Cursor::new(bytes.to_vec()));
+
+    T::read_typed(&mut reader)
+}
+
+// writes `boks` and reads it back, which has to be written the same again
+#[cfg(test)]
+pub(crate) fn round_trip<T: tree::TypedBox>(boks: T) -> (Vec<u8>, T) {
+    let mut written = Vec::new();
+    boks.write_typed(&mut written).unwrap();
+
+    assert_eq!(BigEndian::read_u32(&written) as usize, written.len());
+
+    let mut rewritten = Vec::new();
+    read_test_box::<T>(&written)
+        .unwrap()
+        .write_typed(&mut rewritten)
+        .unwrap();
+    assert_eq!(written, rewritten);
+
+    let read = read_test_box(&written).unwrap();
+
+    (written, read)
+}
@@ -0,0 +1,444 @@
+use byteorder::{BigEndian, ByteOrder};
+
+use log::*;
+
+use crate::boxes::chpl::{ChapterEntry, ChapterListBox};
+use crate::boxes::hdlr::HandlerBox;
+use crate::boxes::ilst::*;
+use crate::boxes::meta::MetaBox;
+use crate::boxes::udta::UserDataBox;
+use crate::{BoxName, BoxPrint};
+
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    Bmp,
+}
+
+impl ImageFormat {
+    /// Guesses the format from the leading bytes of an image.
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(&[0xff, 0xd8, 0xff]) {
+            Some(ImageFormat::Jpeg)
+        } else if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(ImageFormat::Png)
+        } else if data.starts_with(b"BM") {
+            Some(ImageFormat::Bmp)
+        } else {
+            None
+        }
+    }
+
+    fn data_type(&self) -> u32 {
+        match self {
+            ImageFormat::Jpeg => DATA_TYPE_JPEG,
+            ImageFormat::Png => DATA_TYPE_PNG,
+            ImageFormat::Bmp => DATA_TYPE_BMP,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CoverArt {
+    pub format: ImageFormat,
+    pub data: Vec<u8>,
+}
+
+/// A `----` item, identified by a reverse DNS `mean` and a `name`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FreeformTag {
+    pub mean: String,
+    pub name: String,
+    pub value: String,
+}
+
+/// iTunes-style metadata from `moov/udta/meta/ilst`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub album_artist: Option<String>,
+    pub composer: Option<String>,
+    pub genre: Option<String>,
+    pub date: Option<String>,
+    pub comment: Option<String>,
+    pub encoder: Option<String>,
+    pub sort_title: Option<String>,
+    pub sort_artist: Option<String>,
+    pub sort_album: Option<String>,
+    pub sort_album_artist: Option<String>,
+    pub sort_composer: Option<String>,
+    /// Track number and total number of tracks, 0 if unknown.
+    pub track_number: Option<(u16, u16)>,
+    /// Disc number and total number of discs, 0 if unknown.
+    pub disc_number: Option<(u16, u16)>,
+    pub cover_art: Vec<CoverArt>,
+    pub freeform: Vec<FreeformTag>,
+}
+
+// ID3v1 genres, stored one-based in gnre
+const GENRES: [&str; 80] = [
+    "Blues",
+    "Classic Rock",
+    "Country",
+    "Dance",
+    "Disco",
+    "Funk",
+    "Grunge",
+    "Hip-Hop",
+    "Jazz",
+    "Metal",
+    "New Age",
+    "Oldies",
+    "Other",
+    "Pop",
+    "R&B",
+    "Rap",
+    "Reggae",
+    "Rock",
+    "Techno",
+    "Industrial",
+    "Alternative",
+    "Ska",
+    "Death Metal",
+    "Pranks",
+    "Soundtrack",
+    "Euro-Techno",
+    "Ambient",
+    "Trip-Hop",
+    "Vocal",
+    "Jazz+Funk",
+    "Fusion",
+    "Trance",
+    "Classical",
+    "Instrumental",
+    "Acid",
+    "House",
+    "Game",
+    "Sound Clip",
+    "Gospel",
+    "Noise",
+    "AlternRock",
+    "Bass",
+    "Soul",
+    "Punk",
+    "Space",
+    "Meditative",
+    "Instrumental Pop",
+    "Instrumental Rock",
+    "Ethnic",
+    "Gothic",
+    "Darkwave",
+    "Techno-Industrial",
+    "Electronic",
+    "Pop-Folk",
+    "Eurodance",
+    "Dream",
+    "Southern Rock",
+    "Comedy",
+    "Cult",
+    "Gangsta",
+    "Top 40",
+    "Christian Rap",
+    "Pop/Funk",
+    "Jungle",
+    "Native American",
+    "Cabaret",
+    "New Wave",
+    "Psychadelic",
+    "Rave",
+    "Showtunes",
+    "Trailer",
+    "Lo-Fi",
+    "Tribal",
+    "Acid Punk",
+    "Acid Jazz",
+    "Polka",
+    "Retro",
+    "Musical",
+    "Rock & Roll",
+    "Hard Rock",
+];
+
+fn read_pair(value: &[u8]) -> Option<(u16, u16)> {
+    if value.len() < 6 {
+        return None;
+    }
+
+    Some((
+        BigEndian::read_u16(&value[2..]),
+        BigEndian::read_u16(&value[4..]),
+    ))
+}
+
+fn write_pair((number, total): (u16, u16), trailing: usize) -> Vec<u8> {
+    let mut value = vec![0u8; 6 + trailing];
+
+    BigEndian::write_u16(&mut value[2..], number);
+    BigEndian::write_u16(&mut value[4..], total);
+
+    value
+}
+
+impl Metadata {
+    pub fn is_empty(&self) -> bool {
+        *self == Metadata::default()
+    }
+
+    fn text_mut(&mut self, key: BoxName) -> Option<&mut Option<String>> {
+        Some(match &key {
+            b"\xa9nam" => &mut self.title,
+            b"\xa9ART" => &mut self.artist,
+            b"\xa9alb" => &mut self.album,
+            b"aART" => &mut self.album_artist,
+            b"\xa9wrt" => &mut self.composer,
+            b"\xa9gen" => &mut self.genre,
+            b"\xa9day" => &mut self.date,
+            b"\xa9cmt" => &mut self.comment,
+            b"\xa9too" => &mut self.encoder,
+            b"sonm" => &mut self.sort_title,
+            b"soar" => &mut self.sort_artist,
+            b"soal" => &mut self.sort_album,
+            b"soaa" => &mut self.sort_album_artist,
+            b"soco" => &mut self.sort_composer,
+            _ => return None,
+        })
+    }
+
+    fn texts(&self) -> [(BoxName, &Option<String>); 14] {
+        [
+            (*b"\xa9nam", &self.title),
+            (*b"\xa9ART", &self.artist),
+            (*b"\xa9alb", &self.album),
+            (*b"aART", &self.album_artist),
+            (*b"\xa9wrt", &self.composer),
+            (*b"\xa9gen", &self.genre),
+            (*b"\xa9day", &self.date),
+            (*b"\xa9cmt", &self.comment),
+            (*b"\xa9too", &self.encoder),
+            (*b"sonm", &self.sort_title),
+            (*b"soar", &self.sort_artist),
+            (*b"soal", &self.sort_album),
+            (*b"soaa", &self.sort_album_artist),
+            (*b"soco", &self.sort_composer),
+        ]
+    }
+
+    /// Sets a text field by its option name, e.g. `title` or `sort_artist`.
+    /// Returns false for unknown names.
+    pub fn set_text(&mut self, name: &str, value: &str) -> bool {
+        let field = match name {
+            "title" => &mut self.title,
+            "artist" => &mut self.artist,
+            "album" => &mut self.album,
+            "album_artist" => &mut self.album_artist,
+            "composer" => &mut self.composer,
+            "genre" => &mut self.genre,
+            "date" => &mut self.date,
+            "comment" => &mut self.comment,
+            "encoder" => &mut self.encoder,
+            "sort_title" => &mut self.sort_title,
+            "sort_artist" => &mut self.sort_artist,
+            "sort_album" => &mut self.sort_album,
+            "sort_album_artist" => &mut self.sort_album_artist,
+            "sort_composer" => &mut self.sort_composer,
+            _ => return false,
+        };
+
+        *field = Some(value.to_owned());
+
+        true
+    }
+
+    pub fn from_ilst(ilst: &ItemListBox) -> Self {
+        let mut metadata = Metadata::default();
+
+        for item in &ilst.items {
+            let key = item.key();
+
+            for data in &item.data {
+                let value = &data.value;
+
+                match &key {
+                    b"trkn" => metadata.track_number = read_pair(value),
+                    b"disk" => metadata.disc_number = read_pair(value),
+                    b"gnre" if metadata.genre.is_none() && value.len() >= 2 => {
+                        let genre = BigEndian::read_u16(value) as usize;
+
+                        metadata.genre = genre
+                            .checked_sub(1)
+                            .and_then(|idx| GENRES.get(idx))
+                            .map(|&g| g.to_owned());
+                    }
+                    b"covr" => {
+                        let format = match data.data_type {
+                            DATA_TYPE_JPEG => Some(ImageFormat::Jpeg),
+                            DATA_TYPE_PNG => Some(ImageFormat::Png),
+                            DATA_TYPE_BMP => Some(ImageFormat::Bmp),
+                            _ => ImageFormat::detect(value),
+                        };
+
+                        match format {
+                            Some(format) => metadata.cover_art.push(CoverArt {
+                                format,
+                                data: value.clone(),
+                            }),
+                            None => diagnostic!("skipping cover art of type {}", data.data_type),
+                        }
+                    }
+                    b"----" => {
+                        let mean = item.mean.as_ref().map(|b| b.value.clone());
+                        let name = item.name.as_ref().map(|b| b.value.clone());
+
+                        if let (Some(mean), Some(name), Ok(value)) =
+                            (mean, name, String::from_utf8(value.clone()))
+                        {
+                            metadata.freeform.push(FreeformTag { mean, name, value });
+                        }
+                    }
+                    _ => match (metadata.text_mut(key), data.data_type) {
+                        (Some(field), DATA_TYPE_UTF8) => {
+                            *field = String::from_utf8(value.clone()).ok();
+                        }
+                        _ => debug!("skipping metadata item {:?}", BoxPrint(key)),
+                    },
+                }
+            }
+        }
+
+        metadata
+    }
+
+    pub fn to_ilst(&self) -> ItemListBox {
+        let mut items = Vec::new();
+
+        for (key, text) in self.texts().iter() {
+            if let Some(text) = text {
+                let data = DataBox::new(DATA_TYPE_UTF8, text.as_bytes().to_vec());
+                items.push(MetadataItemBox::new(*key, vec![data]));
+            }
+        }
+
+        if let Some(track_number) = self.track_number {
+            let data = DataBox::new(DATA_TYPE_BINARY, write_pair(track_number, 2));
+            items.push(MetadataItemBox::new(*b"trkn", vec![data]));
+        }
+
+        if let Some(disc_number) = self.disc_number {
+            let data = DataBox::new(DATA_TYPE_BINARY, write_pair(disc_number, 0));
+            items.push(MetadataItemBox::new(*b"disk", vec![data]));
+        }
+
+        if !self.cover_art.is_empty() {
+            let data = self
+                .cover_art
+                .iter()
+                .map(|c| DataBox::new(c.format.data_type(), c.data.clone()))
+                .collect();
+
+            items.push(MetadataItemBox::new(*b"covr", data));
+        }
+
+        for tag in &self.freeform {
+            let data = DataBox::new(DATA_TYPE_UTF8, tag.value.as_bytes().to_vec());
+            items.push(MetadataItemBox::freeform(
+                tag.mean.clone(),
+                tag.name.clone(),
+                vec![data],
+            ));
+        }
+
+        ItemListBox::new(items)
+    }
+
+    pub fn from_udta(udta: &UserDataBox) -> Self {
+        udta.meta
+            .as_ref()
+            .and_then(|meta| meta.ilst.as_ref())
+            .map(Metadata::from_ilst)
+            .unwrap_or_default()
+    }
+
+    pub fn to_meta(&self) -> MetaBox {
+        let hdlr = HandlerBox::new(u32::from_be_bytes(*b"mdir"), String::new());
+
+        MetaBox::new(hdlr, Some(self.to_ilst()))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chapter {
+    pub start: Duration,
+    pub end: Duration,
+    pub title: String,
+}
+
+impl Chapter {
+    /// Chapters of a Nero chapter list, each one ends where the next one
+    /// starts and the last one at `duration`.
+    pub fn from_chpl(chpl: &ChapterListBox, duration: Duration) -> Vec<Chapter> {
+        let starts = chpl
+            .chapters
+            .iter()
+            .map(|c| Duration::from_nanos(c.start.saturating_mul(100)));
+        let ends = starts.clone().skip(1).chain(std::iter::once(duration));
+
+        chpl.chapters
+            .iter()
+            .zip(starts.zip(ends))
+            .map(|(chapter, (start, end))| Chapter {
+                start,
+                end: end.max(start),
+                title: chapter.title.clone(),
+            })
+            .collect()
+    }
+
+    pub fn to_chpl(chapters: &[Chapter]) -> ChapterListBox {
+        ChapterListBox::new(
+            chapters
+                .iter()
+                .map(|c| ChapterEntry {
+                    start: (c.start.as_nanos() / 100) as u64,
+                    title: c.title.clone(),
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Text of a QuickTime or 3GPP text sample, which starts with the length of
+/// the text and may be followed by modifier boxes.
+pub fn decode_text_sample(data: &[u8]) -> Option<String> {
+    if data.len() < 2 {
+        return None;
+    }
+
+    let len = BigEndian::read_u16(data) as usize;
+    let text = data.get(2..2 + len)?;
+
+    if text.starts_with(&[0xfe, 0xff]) {
+        let chars = text[2..]
+            .chunks_exact(2)
+            .map(BigEndian::read_u16)
+            .collect::<Vec<_>>();
+
+        String::from_utf16(&chars).ok()
+    } else {
+        String::from_utf8(text.to_vec()).ok()
+    }
+}
+
+pub fn encode_text_sample(text: &str) -> Vec<u8> {
+    let len = text.len().min(u16::MAX as usize);
+    let mut data = vec![0u8; 2];
+
+    BigEndian::write_u16(&mut data, len as u16);
+    data.extend_from_slice(&text.as_bytes()[..len]);
+
+    data
+}
@@ -15,13 +15,16 @@ use byteorder::{BigEndian, WriteBytesExt};
 
 use crate::boxes::*;
 use crate::boxes::codec::*;
-use crate::{AvError, Boks};
+use crate::metadata::{encode_text_sample, Chapter, CoverArt, ImageFormat, Metadata};
+use crate::timecode::Timecode;
+use crate::{AvError, Boks, BoxName, PacketInfo};
 
 use log::*;
 
 use std::io::{Seek, SeekFrom, Write};
 use std::mem;
 use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Mp4MuxerError {
@@ -36,6 +39,9 @@ pub enum Mp4MuxerError {
 
     #[error("Missing codec feature {0}")]
     MissingCodecFeature(u8),
+
+    #[error("Timecode {0} does not fit into a timecode sample")]
+    InvalidTimecode(Timecode),
 }
 
 impl From<Mp4MuxerError> for AvError {
@@ -44,6 +50,10 @@ impl From<Mp4MuxerError> for AvError {
     }
 }
 
+fn rescale(value: i64, from: u32, to: u32) -> u64 {
+    (value.max(0) as u128 * to as u128 / from.max(1) as u128) as u64
+}
+
 fn get_dimensions_for_codec(params: &CodecParams) -> Option<(usize, usize)> {
     match params.kind.as_ref()? {
         MediaKind::Video(video) => Some((video.width, video.height)),
@@ -113,9 +123,10 @@ fn get_sample_entry_for_codec(params: &CodecParams) -> Result<stsd::SampleEntry,
 
     match id.as_str() {
         "vp9" => {
-            let (width, height) = get_dimensions_for_codec(params).unwrap();
-            let format = get_formaton_for_codec(params).unwrap();
-            let extra = params.extradata.clone().unwrap();
+            let (width, height) =
+                get_dimensions_for_codec(params).ok_or(Mp4MuxerError::MissingInfo)?;
+            let format = get_formaton_for_codec(params).ok_or(Mp4MuxerError::MissingInfo)?;
+            let extra = params.extradata.clone().ok_or(Mp4MuxerError::MissingInfo)?;
 
             let data = parse_vpx_codec_data(&format, &extra)?;
 
@@ -140,18 +151,117 @@ fn get_sample_entry_for_codec(params: &CodecParams) -> Result<stsd::SampleEntry,
     }
 }
 
+// sample to group mapping of one grouping type, equal descriptions are shared
+struct SampleGroupBuilder {
+    grouping_type: BoxName,
+    descriptions: Vec<sgpd::SampleGroupEntry>,
+    entries: Vec<sbgp::SampleToGroupEntry>,
+}
+
+impl SampleGroupBuilder {
+    fn new(grouping_type: BoxName, preceding_samples: u32) -> Self {
+        let mut entries = Vec::new();
+
+        if preceding_samples > 0 {
+            entries.push(sbgp::SampleToGroupEntry {
+                sample_count: preceding_samples,
+                group_description_index: 0,
+            });
+        }
+
+        SampleGroupBuilder {
+            grouping_type,
+            descriptions: Vec::new(),
+            entries,
+        }
+    }
+
+    fn add_sample(&mut self, description: Option<&sgpd::SampleGroupEntry>) {
+        let index = match description {
+            Some(description) => match self.descriptions.iter().position(|d| d == description) {
+                Some(idx) => idx as u32 + 1,
+                None => {
+                    self.descriptions.push(description.clone());
+                    self.descriptions.len() as u32
+                }
+            },
+            None => 0,
+        };
+
+        match self.entries.last_mut() {
+            Some(last) if last.group_description_index == index => last.sample_count += 1,
+            _ => self.entries.push(sbgp::SampleToGroupEntry {
+                sample_count: 1,
+                group_description_index: index,
+            }),
+        }
+    }
+
+    fn into_boxes(mut self) -> (sgpd::SampleGroupDescriptionBox, sbgp::SampleToGroupBox) {
+        // trailing samples outside of any group need no entry
+        if let Some(sbgp::SampleToGroupEntry {
+            group_description_index: 0,
+            ..
+        }) = self.entries.last()
+        {
+            self.entries.pop();
+        }
+
+        (
+            sgpd::SampleGroupDescriptionBox::new(self.grouping_type, self.descriptions),
+            sbgp::SampleToGroupBox::new(self.grouping_type, self.entries),
+        )
+    }
+}
+
+struct SubSampleBuilder {
+    flags: u32,
+    entries: Vec<subs::SubSampleEntry>,
+    // one-based number of the last sample with an entry
+    last_sample: u32,
+}
+
+impl SubSampleBuilder {
+    fn add_sample(&mut self, sample_index: u32, subsamples: &[subs::SubSample]) {
+        let number = sample_index + 1;
+
+        self.entries.push(subs::SubSampleEntry {
+            sample_delta: number - self.last_sample,
+            subsamples: subsamples.to_vec(),
+        });
+        self.last_sample = number;
+    }
+}
+
 pub struct TrackChunkBuilder {
     stream_index: isize,
+    // written instead of an entry derived from the codec parameters
+    sample_entry: Option<stsd::SampleEntry>,
     chunks: Vec<stsc::SampleToChunkEntry>,
     times: Vec<stts::TimeToSampleEntry>,
+    composition_offsets: Vec<ctts::CompositionOffsetEntry>,
     sync_samples: Vec<u32>,
     sizes: Vec<u32>,
     offsets: Vec<u64>,
+    sample_groups: Vec<SampleGroupBuilder>,
+    // written as sdtp if any packet carried its dependencies
+    dependencies: Vec<sdtp::SampleDependency>,
+    has_dependencies: bool,
+    subsamples: Vec<SubSampleBuilder>,
     chunk_index: u32,
     sample_index: u32,
 
     current_chunk: Option<stsc::SampleToChunkEntry>,
     current_time: Option<stts::TimeToSampleEntry>,
+    current_composition_offset: Option<ctts::CompositionOffsetEntry>,
+
+    least_composition_offset: i32,
+    greatest_composition_offset: i32,
+    composition_start: Option<i64>,
+    composition_end: Option<i64>,
+
+    first_dts: Option<i64>,
+    presentation_start: Option<i64>,
 
     prev_ts: Option<i64>,
 
@@ -162,16 +272,31 @@ impl TrackChunkBuilder {
     pub fn new(stream_index: isize) -> Self {
         Self {
             stream_index,
+            sample_entry: None,
             chunks: Vec::new(),
             times: Vec::new(),
+            composition_offsets: Vec::new(),
             sizes: Vec::new(),
             offsets: Vec::new(),
             sync_samples: Vec::new(),
+            sample_groups: Vec::new(),
+            dependencies: Vec::new(),
+            has_dependencies: false,
+            subsamples: Vec::new(),
             chunk_index: 1,
             sample_index: 0,
 
             current_chunk: None,
             current_time: None,
+            current_composition_offset: None,
+
+            least_composition_offset: 0,
+            greatest_composition_offset: 0,
+            composition_start: None,
+            composition_end: None,
+
+            first_dts: None,
+            presentation_start: None,
 
             prev_ts: None,
 
@@ -188,44 +313,188 @@ impl TrackChunkBuilder {
             self.times.push(time);
         }
 
+        if let Some(offset) = self.current_composition_offset {
+            self.composition_offsets.push(offset);
+        }
+
         self.current_chunk = None;
         self.current_time = None;
+        self.current_composition_offset = None;
+    }
+
+    fn has_composition_offsets(&self) -> bool {
+        self.least_composition_offset != 0 || self.greatest_composition_offset != 0
+    }
+
+    fn take_composition_boxes(
+        &mut self,
+    ) -> (
+        Option<ctts::CompositionOffsetBox>,
+        Option<cslg::CompositionToDecodeBox>,
+    ) {
+        if !self.has_composition_offsets() {
+            return (None, None);
+        }
+
+        let ctts = ctts::CompositionOffsetBox::new(mem::take(&mut self.composition_offsets));
+
+        // negative offsets need a version 1 ctts, which in turn needs cslg
+        // to tell readers how far decode times have to be shifted
+        let cslg = if self.least_composition_offset < 0 {
+            Some(cslg::CompositionToDecodeBox::new(
+                -(self.least_composition_offset as i64),
+                self.least_composition_offset as i64,
+                self.greatest_composition_offset as i64,
+                self.composition_start.unwrap_or(0),
+                self.composition_end.unwrap_or(0),
+            ))
+        } else {
+            None
+        };
+
+        (Some(ctts), cslg)
     }
 
-    pub fn into_trak(self, stream: &Stream) -> trak::TrackBox {
+    // media times always start at zero, so an edit list is needed whenever
+    // the first presented sample is not at zero on the stream's timeline
+    fn edit_box(
+        &self,
+        stream: &Stream,
+        timebase: u32,
+        movie_timescale: u32,
+    ) -> Option<edts::EditBox> {
+        let first_dts = self.first_dts?;
+        let presentation_start = self.presentation_start?;
+        let composition_end = self.composition_end?;
+
+        // encoder delay (e.g. AAC priming) is signalled as pre-roll
+        let preroll = stream.params.delay as i64;
+
+        let media_start = presentation_start + preroll;
+        let media_time = (media_start - first_dts).max(0);
+        let empty_duration = if preroll == 0 {
+            presentation_start.max(0)
+        } else {
+            0
+        };
+
+        if media_time == 0 && empty_duration == 0 {
+            return None;
+        }
+
+        let mut entries = Vec::new();
+
+        if empty_duration > 0 {
+            entries.push(elst::EditListEntry::empty(rescale(
+                empty_duration,
+                timebase,
+                movie_timescale,
+            )));
+        }
+
+        entries.push(elst::EditListEntry::new(
+            rescale(composition_end - media_start, timebase, movie_timescale),
+            media_time,
+        ));
+
+        Some(edts::EditBox::new(elst::EditListBox::new(entries)))
+    }
+
+    pub fn into_trak(
+        mut self,
+        stream: &Stream,
+        track_id: u32,
+        movie_timescale: u32,
+    ) -> trak::TrackBox {
+        let (ctts, cslg) = self.take_composition_boxes();
+
         let timebase = (stream.timebase.denom() / stream.timebase.numer()) as u32;
+        let edts = self.edit_box(stream, timebase, movie_timescale);
+
+        let media_duration = self
+            .times
+            .iter()
+            .map(|t| t.count as u64 * t.delta as u64)
+            .sum::<u64>();
+        // the presentation is as long as its edits, if there are any
+        let duration = match edts.as_ref().and_then(|edts| edts.elst.as_ref()) {
+            Some(elst) => elst.entries.iter().map(|e| e.segment_duration).sum(),
+            None => rescale(media_duration as i64, timebase, movie_timescale),
+        };
 
         let (width, height) = get_dimensions_for_codec(&stream.params)
             .map(|(w, h)| (w as u32, h as u32))
             .unwrap_or((0, 0));
 
+        let (handler, media_header) = match &stream.params.kind {
+            Some(MediaKind::Audio(_)) => (
+                hdlr::HandlerBox::new(
+                    hdlr::HandlerType::Sound.into(),
+                    String::from("Sound Handler"),
+                ),
+                minf::MediaHeader::Sound(smhd::SoundMediaHeaderBox::new()),
+            ),
+            _ => (
+                hdlr::HandlerBox::new(
+                    hdlr::HandlerType::Video.into(),
+                    String::from("Video Handler"),
+                ),
+                minf::MediaHeader::Video(vmhd::VideoMediaHeaderBox::new()),
+            ),
+        };
+
+        let sample_entry = match self.sample_entry.take() {
+            Some(entry) => entry,
+            None => get_sample_entry_for_codec(&stream.params).unwrap(),
+        };
+
+        let mut stbl = stbl::SampleTableBox::new(
+            stsd::SampleDescriptionBox::new(vec![sample_entry]),
+            stts::TimeToSampleBox::new(self.times),
+            ctts,
+            cslg,
+            stsc::SampleToChunkBox::new(self.chunks),
+            stsz::SampleSizeBox::new(stsz::SampleSizes::compact(self.sizes)),
+            stbl::ChunkOffsets::Co64(co64::ChunkLargeOffsetBox::new(self.offsets)),
+            Some(stss::SyncSampleBox::new(self.sync_samples)),
+        );
+
+        for group in self.sample_groups {
+            let (sgpd, sbgp) = group.into_boxes();
+
+            stbl.sgpd.push(sgpd);
+            stbl.sbgp.push(sbgp);
+        }
+
+        if self.has_dependencies {
+            stbl.sdtp = Some(sdtp::SampleDependencyTypeBox::new(self.dependencies));
+        }
+
+        for builder in self.subsamples {
+            let subs = subs::SubSampleInformationBox::new(builder.flags, builder.entries);
+
+            stbl.subs.push(subs);
+        }
+
         trak::TrackBox::new(
             tkhd::TrackHeaderBox::new(
                 tkhd::TrackHeaderFlags::ENABLED | tkhd::TrackHeaderFlags::IN_MOVIE,
-                1,
-                0,
+                track_id,
+                duration,
                 width.into(),
                 height.into(),
             ),
+            None,
+            edts,
             mdia::MediaBox::new(
-                mdhd::MediaHeaderBox::new(timebase, 0),
-                hdlr::HandlerBox::new(0x76696465, String::from("Video Handler")),
+                mdhd::MediaHeaderBox::new(timebase, media_duration),
+                handler,
                 minf::MediaInformationBox::new(
-                    minf::MediaHeader::Video(vmhd::VideoMediaHeaderBox::new()),
+                    media_header,
                     dinf::DataInformationBox::new(dref::DataReferenceBox::new(vec![
-                        url::DataEntryUrlBox::new(String::from("")),
+                        dref::DataEntry::Url(url::DataEntryUrlBox::new(String::new())),
                     ])),
-                    stbl::SampleTableBox::new(
-                        stsd::SampleDescriptionBox::new(vec![get_sample_entry_for_codec(
-                            &stream.params,
-                        )
-                        .unwrap()]),
-                        stts::TimeToSampleBox::new(self.times),
-                        stsc::SampleToChunkBox::new(self.chunks),
-                        stsz::SampleSizeBox::new(stsz::SampleSizes::Variable(self.sizes)),
-                        stbl::ChunkOffsets::Co64(co64::ChunkLargeOffsetBox::new(self.offsets)),
-                        Some(stss::SyncSampleBox::new(self.sync_samples)),
-                    ),
+                    stbl,
                 ),
             ),
         )
@@ -251,6 +520,91 @@ impl TrackChunkBuilder {
         delta
     }
 
+    fn add_presentation_time(&mut self, packet: &Packet) {
+        if self.first_dts.is_none() {
+            self.first_dts = packet.t.dts.or(packet.t.pts);
+        }
+
+        // discarded samples are pre-roll, presentation starts after them
+        let discard = PacketInfo::get(&packet.t).map_or(false, |info| info.discard);
+
+        if let (false, Some(pts)) = (discard, packet.t.pts.or(packet.t.dts)) {
+            self.presentation_start = Some(self.presentation_start.map_or(pts, |s| s.min(pts)));
+        }
+    }
+
+    fn add_composition_offset(&mut self, packet: &Packet, delta: u32) {
+        let (pts, offset) = match (packet.t.pts, packet.t.dts) {
+            (Some(pts), Some(dts)) => (pts, (pts - dts) as i32),
+            (Some(pts), None) => (pts, 0),
+            (None, Some(dts)) => (dts, 0),
+            (None, None) => return,
+        };
+
+        self.least_composition_offset = self.least_composition_offset.min(offset);
+        self.greatest_composition_offset = self.greatest_composition_offset.max(offset);
+
+        let end = pts + delta as i64;
+        self.composition_start = Some(self.composition_start.map_or(pts, |s| s.min(pts)));
+        self.composition_end = Some(self.composition_end.map_or(end, |e| e.max(end)));
+
+        match self.current_composition_offset.as_mut() {
+            Some(current) if current.offset == offset => current.count += 1,
+            Some(current) => {
+                self.composition_offsets.push(*current);
+
+                *current = ctts::CompositionOffsetEntry { count: 1, offset };
+            }
+            None => {
+                self.current_composition_offset =
+                    Some(ctts::CompositionOffsetEntry { count: 1, offset });
+            }
+        }
+    }
+
+    fn add_sample_groups(&mut self, packet: &Packet) {
+        let groups = PacketInfo::get(&packet.t).map_or(&[][..], |info| &info.sample_groups[..]);
+
+        for description in groups {
+            let grouping_type = description.grouping_type();
+
+            if !self
+                .sample_groups
+                .iter()
+                .any(|g| g.grouping_type == grouping_type)
+            {
+                self.sample_groups
+                    .push(SampleGroupBuilder::new(grouping_type, self.sample_index));
+            }
+        }
+
+        for group in &mut self.sample_groups {
+            let grouping_type = group.grouping_type;
+
+            group.add_sample(groups.iter().find(|d| d.grouping_type() == grouping_type));
+        }
+    }
+
+    fn add_subsamples(&mut self, packet: &Packet) {
+        let layouts = PacketInfo::get(&packet.t).map_or(&[][..], |info| &info.subsamples[..]);
+
+        for layout in layouts {
+            let idx = match self.subsamples.iter().position(|b| b.flags == layout.flags) {
+                Some(idx) => idx,
+                None => {
+                    self.subsamples.push(SubSampleBuilder {
+                        flags: layout.flags,
+                        entries: Vec::new(),
+                        last_sample: 0,
+                    });
+                    self.subsamples.len() - 1
+                }
+            };
+
+            self.subsamples[idx].add_sample(self.sample_index, &layout.subsamples);
+        }
+    }
+
     pub fn add_packet(&mut self, prev_stream: isize, offset: u64, packet: &Packet) {
         let delta = self.take_time_delta(packet).unwrap_or(0);
 
@@ -296,23 +650,221 @@ impl TrackChunkBuilder {
             *current_time = stts::TimeToSampleEntry { count: 1, delta };
         }
 
+        self.add_presentation_time(packet);
+        self.add_composition_offset(packet, delta);
+
         if packet.is_key {
             self.sync_samples.push(self.sample_index);
         }
 
-        self.sizes.push(packet.data.len() as u32);
+        self.sizes.push(PacketInfo::data(packet).len() as u32);
+        self.add_sample_groups(packet);
+
+        let dependency = PacketInfo::get(&packet.t).and_then(|info| info.dependency);
+        self.has_dependencies |= dependency.is_some();
+        self.dependencies.push(dependency.unwrap_or_default());
+        self.add_subsamples(packet);
 
         self.sample_index += 1;
         self.first_packet = false;
     }
 }
 
+const CHAPTER_TIMESCALE: u32 = 1000;
+
+// default tx3g sample entry: bottom-centered white text on a transparent
+// background, with a single font
+const TEXT_SAMPLE_ENTRY: [u8; 48] = [
+    0, 0, 0, 0, // display flags
+    0x01, 0xff, // justification
+    0, 0, 0, 0, // background color
+    0, 0, 0, 0, 0, 0, 0, 0, // text box
+    0, 0, 0, 0, 0, 1, 0, 0x12, 0xff, 0xff, 0xff, 0xff, // style record
+    0, 0, 0, 0x12, b'f', b't', b'a', b'b', // font table
+    0, 1, 0, 1, 5, b'S', b'e', b'r', b'i', b'f', // font record
+];
+
+fn chapter_millis(time: Duration) -> u64 {
+    time.as_millis() as u64
+}
+
+// a disabled text track holding one sample per chapter, which are stored
+// contiguously from `offset`
+fn chapter_track(
+    chapters: &[Chapter],
+    offset: u64,
+    track_id: u32,
+    movie_timescale: u32,
+) -> trak::TrackBox {
+    let start = chapters.first().map_or(0, |c| chapter_millis(c.start));
+    let mut end = start;
+
+    let mut times = Vec::with_capacity(chapters.len());
+    let mut sizes = Vec::with_capacity(chapters.len());
+
+    for (i, chapter) in chapters.iter().enumerate() {
+        // samples are played back to back, a gap extends the previous chapter
+        let chapter_end = chapters.get(i + 1).map_or(chapter.end, |next| next.start);
+        let chapter_end = chapter_millis(chapter_end).max(end);
+
+        times.push(stts::TimeToSampleEntry {
+            count: 1,
+            delta: (chapter_end - end) as u32,
+        });
+        sizes.push(encode_text_sample(&chapter.title).len() as u32);
+
+        end = chapter_end;
+    }
+
+    let edts = if start > 0 {
+        Some(edts::EditBox::new(elst::EditListBox::new(vec![
+            elst::EditListEntry::empty(rescale(start as i64, CHAPTER_TIMESCALE, movie_timescale)),
+            elst::EditListEntry::new(
+                rescale((end - start) as i64, CHAPTER_TIMESCALE, movie_timescale),
+                0,
+            ),
+        ])))
+    } else {
+        None
+    };
+
+    let sample_entry = stsd::UnknownSampleEntryBox {
+        sample_entry: crate::SampleEntry::new(*b"tx3g", 1),
+        data: TEXT_SAMPLE_ENTRY.to_vec(),
+    };
+
+    trak::TrackBox::new(
+        tkhd::TrackHeaderBox::new(
+            tkhd::TrackHeaderFlags::empty(),
+            track_id,
+            rescale((end - start) as i64, CHAPTER_TIMESCALE, movie_timescale),
+            0.into(),
+            0.into(),
+        ),
+        None,
+        edts,
+        mdia::MediaBox::new(
+            mdhd::MediaHeaderBox::new(CHAPTER_TIMESCALE, end - start),
+            hdlr::HandlerBox::new(hdlr::HandlerType::Text.into(), String::from("Chapters")),
+            minf::MediaInformationBox::new(
+                minf::MediaHeader::Null(nmhd::NullMediaHeaderBox::new()),
+                dinf::DataInformationBox::new(dref::DataReferenceBox::new(vec![
+                    dref::DataEntry::Url(url::DataEntryUrlBox::new(String::new())),
+                ])),
+                stbl::SampleTableBox::new(
+                    stsd::SampleDescriptionBox::new(vec![stsd::SampleEntry::Unknown(sample_entry)]),
+                    stts::TimeToSampleBox::new(times),
+                    None,
+                    None,
+                    stsc::SampleToChunkBox::new(vec![stsc::SampleToChunkEntry {
+                        first_chunk: 1,
+                        samples_per_chunk: chapters.len() as u32,
+                        sample_description_index: 1,
+                    }]),
+                    stsz::SampleSizeBox::new(stsz::SampleSizes::compact(sizes)),
+                    stbl::ChunkOffsets::Co64(co64::ChunkLargeOffsetBox::new(vec![offset])),
+                    None,
+                ),
+            ),
+        ),
+    )
+}
+
+// gmhd of timecode tracks: gmin with the default graphics mode, and tmcd
+// with tcmi text in black on white and no font name
+const TIMECODE_MEDIA_HEADER: [u8; 65] = [
+    0, 0, 0, 24, b'g', b'm', b'i', b'n', 0, 0, 0, 0, // gmin
+    0, 0x40, 0x80, 0, 0x80, 0, 0x80, 0, 0, 0, 0, 0, // graphics mode, opcolor, balance
+    0, 0, 0, 41, b't', b'm', b'c', b'd', // tmcd
+    0, 0, 0, 33, b't', b'c', b'm', b'i', 0, 0, 0, 0, // tcmi
+    0, 0, 0, 0, 0, 12, 0, 0, // font, face, size
+    0, 0, 0, 0, 0, 0, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0, // colors, font name
+];
+
+// frames per second of a timecode, rounded up
+fn timecode_frames(timescale: u32, frame_duration: u32) -> u32 {
+    let frame_duration = frame_duration.max(1);
+
+    (timescale + frame_duration - 1) / frame_duration
+}
+
+// a timecode track with a single sample holding the frame number of the
+// start, lasting `duration` in the movie timescale
+fn timecode_track(
+    start: Timecode,
+    timescale: u32,
+    frame_duration: u32,
+    offset: u64,
+    track_id: u32,
+    duration: u64,
+    movie_timescale: u32,
+) -> trak::TrackBox {
+    let number_of_frames = timecode_frames(timescale, frame_duration);
+    let media_duration = rescale(duration as i64, movie_timescale, timescale);
+
+    let mut flags = tmcd::TimecodeFlags::MAX_24_HOUR;
+    flags.set(tmcd::TimecodeFlags::DROP_FRAME, start.drop_frame);
+
+    let sample_entry =
+        tmcd::TimecodeSampleEntryBox::new(flags, timescale, frame_duration, number_of_frames as u8);
+
+    trak::TrackBox::new(
+        tkhd::TrackHeaderBox::new(
+            tkhd::TrackHeaderFlags::empty(),
+            track_id,
+            duration,
+            0.into(),
+            0.into(),
+        ),
+        None,
+        None,
+        mdia::MediaBox::new(
+            mdhd::MediaHeaderBox::new(timescale, media_duration),
+            hdlr::HandlerBox::new(hdlr::HandlerType::Timecode.into(), String::from("Timecode")),
+            minf::MediaInformationBox::new(
+                minf::MediaHeader::Base(gmhd::BaseMediaHeaderBox::new(
+                    TIMECODE_MEDIA_HEADER.to_vec(),
+                )),
+                dinf::DataInformationBox::new(dref::DataReferenceBox::new(vec![
+                    dref::DataEntry::Url(url::DataEntryUrlBox::new(String::new())),
+                ])),
+                stbl::SampleTableBox::new(
+                    stsd::SampleDescriptionBox::new(vec![stsd::SampleEntry::Timecode(
+                        sample_entry,
+                    )]),
+                    stts::TimeToSampleBox::new(vec![stts::TimeToSampleEntry {
+                        count: 1,
+                        delta: media_duration as u32,
+                    }]),
+                    None,
+                    None,
+                    stsc::SampleToChunkBox::new(vec![stsc::SampleToChunkEntry {
+                        first_chunk: 1,
+                        samples_per_chunk: 1,
+                        sample_description_index: 1,
+                    }]),
+                    stsz::SampleSizeBox::new(stsz::SampleSizes::Variable(vec![4])),
+                    stbl::ChunkOffsets::Co64(co64::ChunkLargeOffsetBox::new(vec![offset])),
+                    None,
+                ),
+            ),
+        ),
+    )
+}
+
 pub struct Mp4Muxer {
     info: Option<GlobalInfo>,
     mdat_start: u64,
     mdat_offset: u64,
     tracks: Vec<TrackChunkBuilder>,
     prev_index: isize,
+    metadata: Metadata,
+    chapters: Vec<Chapter>,
+    // referencing and referenced stream indices
+    references: Vec<(usize, tref::ReferenceType, usize)>,
+    // stream index, start, timescale and frame duration of timecode tracks
+    timecodes: Vec<(usize, Timecode, u32, u32)>,
+    sample_entries: Vec<(usize, stsd::SampleEntry)>,
 }
 
 impl Default for Mp4Muxer {
@@ -329,9 +881,62 @@ impl Mp4Muxer {
             mdat_offset: 0,
             tracks: Vec::new(),
             prev_index: 0,
+            metadata: Metadata::default(),
+            chapters: Vec::new(),
+            references: Vec::new(),
+            timecodes: Vec::new(),
+            sample_entries: Vec::new(),
         }
     }
 
+    /// Adds a `tref` reference from the track of one stream to the track of
+    /// another, e.g. from a subtitle stream to the video it belongs to.
+    pub fn add_track_reference(
+        &mut self,
+        stream_index: usize,
+        reference_type: tref::ReferenceType,
+        referenced_stream_index: usize,
+    ) {
+        self.references
+            .push((stream_index, reference_type, referenced_stream_index));
+    }
+
+    /// Adds a timecode track starting at `start`, referenced from the track
+    /// of the stream with `tmcd`. The frame rate is `timescale` divided by
+    /// `frame_duration`, e.g. 30000 and 1001 for drop-frame 29.97 fps.
+    pub fn set_timecode(
+        &mut self,
+        stream_index: usize,
+        start: Timecode,
+        timescale: u32,
+        frame_duration: u32,
+    ) {
+        self.timecodes.retain(|t| t.0 != stream_index);
+        self.timecodes
+            .push((stream_index, start, timescale, frame_duration));
+    }
+
+    /// Writes `entry` as the sample description of a stream instead of one
+    /// derived from its codec parameters, e.g. to copy it from another file.
+    pub fn set_sample_entry(&mut self, stream_index: usize, entry: stsd::SampleEntry) {
+        self.sample_entries.retain(|e| e.0 != stream_index);
+        self.sample_entries.push((stream_index, entry));
+    }
+
+    /// Chapters written both as a chapter track and as a Nero `chpl` box.
+    pub fn set_chapters(&mut self, chapters: Vec<Chapter>) {
+        self.chapters = chapters;
+    }
+
+    /// Metadata written to `moov/udta`, replaces anything set through options.
+    pub fn set_metadata(&mut self, metadata: Metadata) {
+        self.metadata = metadata;
+    }
+
+    pub fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
     fn flush(&mut self) {
         for track in &mut self.tracks {
             track.flush();
@@ -346,15 +951,26 @@ impl Mp4Muxer {
             .find(|s| s.index == stream_index)
     }
 
-    pub fn take_tracks(&mut self) -> Vec<trak::TrackBox> {
+    pub fn take_tracks(&mut self, movie_timescale: u32) -> Vec<trak::TrackBox> {
         let mut tracks = Vec::new();
         mem::swap(&mut self.tracks, &mut tracks);
 
+        for track in &mut tracks {
+            if let Some(idx) = self
+                .sample_entries
+                .iter()
+                .position(|e| e.0 == track.stream_index as usize)
+            {
+                track.sample_entry = Some(self.sample_entries.remove(idx).1);
+            }
+        }
+
         tracks
             .into_iter()
-            .map(|t| {
+            .enumerate()
+            .map(|(i, t)| {
                 let stream = self.stream_for_index(t.stream_index as usize).unwrap();
-                t.into_trak(stream)
+                t.into_trak(stream, i as u32 + 1, movie_timescale)
             })
             .collect::<Vec<_>>()
     }
@@ -367,7 +983,35 @@ impl Muxer for Mp4Muxer {
         Ok(())
     }
 
-    fn set_option<'a>(&mut self, _key: &str, _val: Value<'a>) -> AvResult<()> {
+    fn set_option<'a>(&mut self, key: &str, val: Value<'a>) -> AvResult<()> {
+        match (key, val) {
+            ("track" | "disc", Value::Pair(number, total)) => {
+                let pair = Some((number as u16, total as u16));
+
+                if key == "track" {
+                    self.metadata.track_number = pair;
+                } else {
+                    self.metadata.disc_number = pair;
+                }
+            }
+            ("track", Value::U64(number)) => self.metadata.track_number = Some((number as u16, 0)),
+            ("disc", Value::U64(number)) => self.metadata.disc_number = Some((number as u16, 0)),
+            ("cover", Value::Bytes(data)) => {
+                let format = ImageFormat::detect(data).ok_or(AvError::InvalidData)?;
+
+                self.metadata.cover_art.push(CoverArt {
+                    format,
+                    data: data.to_vec(),
+                });
+            }
+            (key, Value::Str(value)) => {
+                if !self.metadata.set_text(key, value) {
+                    debug!("ignoring option {}", key);
+                }
+            }
+            (key, _) => debug!("ignoring option {}", key),
+        }
+
         Ok(())
     }
 
@@ -395,8 +1039,9 @@ impl Muxer for Mp4Muxer {
 
     fn write_packet(&mut self, out: &mut Writer, packet: Arc<Packet>) -> AvResult<()> {
         let offset = self.mdat_offset;
-        out.write_all(&packet.data)?;
-        self.mdat_offset += packet.data.len() as u64;
+        let data = PacketInfo::data(&packet);
+        out.write_all(data)?;
+        self.mdat_offset += data.len() as u64;
 
         if let Some(builder) = self
             .tracks
@@ -425,11 +1070,116 @@ impl Muxer for Mp4Muxer {
             .map(|t| (t.denom() / t.numer()) as u32)
             .unwrap_or(10_000);
 
-        let moov = moov::MovieBox::new(
-            mvhd::MovieHeaderBox::new(timebase, 0),
-            None,
-            self.take_tracks(),
-        );
+        // tracks are numbered in the order their first packet was written
+        let track_ids = self
+            .tracks
+            .iter()
+            .map(|t| t.stream_index as usize)
+            .collect::<Vec<_>>();
+        let track_id = |stream_index| {
+            track_ids
+                .iter()
+                .position(|&s| s == stream_index)
+                .map(|i| i as u32 + 1)
+        };
+
+        let mut tracks = self.take_tracks(timebase);
+
+        for &(stream_index, reference_type, referenced) in &self.references {
+            match (track_id(stream_index), track_id(referenced)) {
+                (Some(from), Some(to)) => tracks[from as usize - 1]
+                    .tref
+                    .get_or_insert_with(|| tref::TrackReferenceBox::new(Vec::new()))
+                    .add(reference_type, to),
+                _ => warn!(
+                    "skipping reference from stream {} to {} without packets",
+                    stream_index, referenced
+                ),
+            }
+        }
+
+        if !self.chapters.is_empty() {
+            let offset = self.mdat_offset;
+
+            for chapter in &self.chapters {
+                let data = encode_text_sample(&chapter.title);
+                out.write_all(&data)?;
+                self.mdat_offset += data.len() as u64;
+            }
+
+            let track_id = tracks.len() as u32 + 1;
+
+            for track in &mut tracks {
+                track
+                    .tref
+                    .get_or_insert_with(|| tref::TrackReferenceBox::new(Vec::new()))
+                    .add(tref::ReferenceType::Chapter, track_id);
+            }
+
+            tracks.push(chapter_track(&self.chapters, offset, track_id, timebase));
+        }
+
+        for &(stream_index, start, timescale, frame_duration) in &self.timecodes {
+            let from = match track_id(stream_index) {
+                Some(from) => from as usize - 1,
+                None => {
+                    warn!(
+                        "skipping timecode of stream {} without packets",
+                        stream_index
+                    );
+                    continue;
+                }
+            };
+
+            let offset = self.mdat_offset;
+            let fps = timecode_frames(timescale, frame_duration);
+
+            let frame = start
+                .to_frame_number(fps)
+                .ok_or(Mp4MuxerError::InvalidTimecode(start))?;
+
+            out.write_u32::<BigEndian>(frame)?;
+            self.mdat_offset += 4;
+
+            let track_id = tracks.len() as u32 + 1;
+            let duration = tracks[from].tkhd.duration;
+
+            tracks[from]
+                .tref
+                .get_or_insert_with(|| tref::TrackReferenceBox::new(Vec::new()))
+                .add(tref::ReferenceType::Timecode, track_id);
+
+            tracks.push(timecode_track(
+                start,
+                timescale,
+                frame_duration,
+                offset,
+                track_id,
+                duration,
+                timebase,
+            ));
+        }
+
+        let mut mvhd = mvhd::MovieHeaderBox::new(timebase, 0);
+        mvhd.next_track_id = tracks.len() as u32 + 1;
+
+        let meta = if self.metadata.is_empty() {
+            None
+        } else {
+            Some(self.metadata.to_meta())
+        };
+        let chpl = if self.chapters.is_empty() {
+            None
+        } else {
+            Some(Chapter::to_chpl(&self.chapters))
+        };
+        let udta = if meta.is_some() || chpl.is_some() {
+            Some(udta::UserDataBox::new(meta, chpl))
+        } else {
+            None
+        };
+
+        let moov = moov::MovieBox::new(mvhd, None, tracks, udta);
 
         moov.write(out)?;
 
@@ -444,3 +1194,252 @@ impl Muxer for Mp4Muxer {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use av_data::params::VideoInfo;
+    use av_data::timeinfo::TimeInfo;
+    use av_format::buffer::AccReader;
+    use av_format::demuxer::{Context as DemuxerCtx, Event};
+    use av_format::muxer::Context as MuxerCtx;
+    use av_format::rational::Rational64;
+
+    use crate::demuxer::Mp4Demuxer;
+
+    use std::io::{self, Cursor};
+    use std::sync::Mutex;
+
+    // a seekable output that can still be read once the writer took it
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Cursor<Vec<u8>>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for Output {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.0.lock().unwrap().seek(pos)
+        }
+    }
+
+    fn stream(delay: usize) -> Stream {
+        Stream {
+            id: 0,
+            index: 0,
+            params: CodecParams {
+                kind: Some(MediaKind::Video(VideoInfo {
+                    width: 0,
+                    height: 0,
+                    format: None,
+                })),
+                codec_id: None,
+                extradata: None,
+                bit_rate: 0,
+                convergence_window: 0,
+                delay,
+            },
+            start: None,
+            duration: None,
+            timebase: Rational64::new(1, 1000),
+            user_private: None,
+        }
+    }
+
+    fn packet(dts: i64, pts: i64, discard: bool) -> Packet {
+        let info = PacketInfo {
+            discard,
+            ..PacketInfo::default()
+        };
+
+        Packet {
+            data: vec![dts as u8; 4],
+            pos: None,
+            stream_index: 0,
+            t: TimeInfo {
+                pts: Some(pts),
+                dts: Some(dts),
+                duration: Some(100),
+                timebase: Some(Rational64::new(1, 1000)),
+                user_private: Some(Arc::new(info)),
+            },
+            is_key: true,
+            is_corrupted: false,
+        }
+    }
+
+    // the edits written for `packets`, as segment durations and media times
+    fn edits(packets: &[Packet], delay: usize) -> Option<Vec<(u64, i64)>> {
+        let mut builder = TrackChunkBuilder::new(0);
+
+        for (i, packet) in packets.iter().enumerate() {
+            builder.add_packet(0, i as u64 * 4, packet);
+        }
+        builder.flush();
+
+        let edts = builder.edit_box(&stream(delay), 1000, 1000)?;
+        let entries = &edts.elst.as_ref().unwrap().entries;
+
+        Some(
+            entries
+                .iter()
+                .map(|e| (e.segment_duration, e.media_time))
+                .collect(),
+        )
+    }
+
+    // the pts, dts and discard flag of `packets` after muxing and demuxing
+    fn remux(packets: Vec<Packet>) -> Vec<(i64, i64, bool)> {
+        let output = Output::default();
+
+        let mut muxer = Mp4Muxer::new();
+        muxer.set_sample_entry(
+            0,
+            stsd::SampleEntry::Unknown(stsd::UnknownSampleEntryBox {
+                sample_entry: crate::SampleEntry::new(*b"test", 1),
+                data: Vec::new(),
+            }),
+        );
+
+        let mut ctx = MuxerCtx::new(
+            Box::new(muxer),
+            Writer::from_seekable(Box::new(output.clone())),
+        );
+        ctx.set_global_info(GlobalInfo {
+            duration: None,
+            timebase: Some(Rational64::new(1, 1000)),
+            streams: vec![stream(0)],
+        })
+        .unwrap();
+        ctx.write_header().unwrap();
+        for packet in packets {
+            ctx.write_packet(Arc::new(packet)).unwrap();
+        }
+        ctx.write_trailer().unwrap();
+
+        let data = output.0.lock().unwrap().get_ref().clone();
+        let input = AccReader::new(Cursor::new(data));
+        let mut demuxer = DemuxerCtx::new(Box::new(Mp4Demuxer::new()), Box::new(input));
+        demuxer.read_headers().unwrap();
+
+        let mut times = Vec::new();
+
+        loop {
+            match demuxer.read_event().unwrap() {
+                Event::NewPacket(packet) => times.push((
+                    packet.t.pts.unwrap(),
+                    packet.t.dts.unwrap(),
+                    PacketInfo::get(&packet.t).map_or(false, |info| info.discard),
+                )),
+                Event::Continue => {}
+                _ => break,
+            }
+        }
+
+        times
+    }
+
+    #[test]
+    fn no_edits() {
+        let packets = (0..4)
+            .map(|i| packet(i * 100, i * 100, false))
+            .collect::<Vec<_>>();
+
+        assert_eq!(edits(&packets, 0), None);
+    }
+
+    #[test]
+    fn empty_edit() {
+        let packets = (5..9)
+            .map(|i| packet(i * 100, i * 100, false))
+            .collect::<Vec<_>>();
+
+        assert_eq!(edits(&packets, 0), Some(vec![(500, -1), (400, 0)]));
+        assert_eq!(
+            remux(packets),
+            [
+                (500, 500, false),
+                (600, 600, false),
+                (700, 700, false),
+                (800, 800, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn discarded_samples() {
+        let packets = (0..5)
+            .map(|i| packet(i * 100, i * 100, i == 0))
+            .collect::<Vec<_>>();
+
+        // the presentation starts with the second sample
+        assert_eq!(edits(&packets, 0), Some(vec![(100, -1), (400, 100)]));
+        assert_eq!(
+            remux(packets),
+            [
+                (0, 0, true),
+                (100, 100, false),
+                (200, 200, false),
+                (300, 300, false),
+                (400, 400, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn encoder_delay() {
+        let packets = (0..5)
+            .map(|i| packet(i * 100, i * 100, false))
+            .collect::<Vec<_>>();
+
+        // pre-roll is cut by the media time, without an empty edit
+        assert_eq!(edits(&packets, 100), Some(vec![(400, 100)]));
+    }
+
+    #[test]
+    fn negative_composition_offsets() {
+        let packets = vec![
+            packet(0, 0, false),
+            packet(100, 300, false),
+            packet(200, 100, false),
+            packet(300, 200, false),
+        ];
+
+        let mut builder = TrackChunkBuilder::new(0);
+        for (i, packet) in packets.iter().enumerate() {
+            builder.add_packet(0, i as u64 * 4, packet);
+        }
+        builder.flush();
+
+        let (ctts, cslg) = builder.take_composition_boxes();
+        let ctts = ctts.unwrap();
+        let offsets = ctts
+            .entries
+            .iter()
+            .map(|e| (e.count, e.offset))
+            .collect::<Vec<_>>();
+
+        assert_eq!(ctts.version(), 1);
+        assert_eq!(offsets, [(1, 0), (1, 200), (2, -100)]);
+        assert_eq!(cslg.unwrap().composition_to_dts_shift, 100);
+
+        // decode times are shifted so that none follows its presentation
+        assert_eq!(
+            remux(packets),
+            [
+                (0, -100, false),
+                (300, 0, false),
+                (100, 100, false),
+                (200, 200, false)
+            ]
+        );
+    }
+}
@@ -0,0 +1,1441 @@
+use av_data::{
+    packet::Packet,
+    params::{CodecParams, MediaKind, VideoInfo},
+    timeinfo::TimeInfo,
+};
+use av_format::error::Result as AvResult;
+use av_format::{
+    buffer::Buffered,
+    common::GlobalInfo,
+    muxer::{Context as MuxerCtx, Writer},
+    rational::Rational64,
+    stream::Stream,
+};
+
+use byteorder::{BigEndian, ByteOrder};
+
+use log::*;
+
+use crate::boxes::codec::*;
+use crate::boxes::*;
+use crate::muxer::Mp4Muxer;
+use crate::{goto, skip, Boks, BoksIterator, BoxName, BoxPrint, FullBox, Mp4BoxError};
+
+use std::io::SeekFrom;
+use std::sync::Arc;
+
+// bytes read from the damaged file at once while scanning
+const WINDOW_SIZE: usize = 1 << 20;
+
+// samples larger than this are taken for misdetections without a reference
+const MAX_SAMPLE_SIZE: u32 = 16 << 20;
+
+const MOVIE_TIMESCALE: u32 = 1000;
+
+/// How the samples of a track are told apart in `mdat`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameFormat {
+    /// NAL units with big-endian length prefixes of `length_size` bytes.
+    Avc {
+        length_size: u8,
+    },
+    Hevc {
+        length_size: u8,
+    },
+    /// AAC frames with ADTS headers, which are stripped.
+    Adts,
+    /// Raw AAC frames, which only fill the space between samples of other
+    /// tracks. They can only be told apart if the reference uses a constant
+    /// frame size.
+    Aac,
+    /// VP9 frames, which only fill the space between samples of other tracks
+    /// like raw AAC. Consecutive frames are told apart by superframe indices.
+    Vp9,
+}
+
+impl FrameFormat {
+    // formats whose samples carry their size
+    fn is_delimited(&self) -> bool {
+        match self {
+            FrameFormat::Avc { .. } | FrameFormat::Hevc { .. } | FrameFormat::Adts => true,
+            FrameFormat::Aac | FrameFormat::Vp9 => false,
+        }
+    }
+
+    // whether a sample of an undelimited format may start with `byte`
+    fn may_start(&self, byte: u8) -> bool {
+        match self {
+            // the first syntax element is not ID_END
+            FrameFormat::Aac => byte >> 5 != 7,
+            // frame marker
+            FrameFormat::Vp9 => byte >> 6 == 0b10,
+            _ => false,
+        }
+    }
+}
+
+/// A sample found in the damaged file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RecoveredSample {
+    pub track: usize,
+    pub offset: u64,
+    pub size: u32,
+    pub keyframe: bool,
+}
+
+pub struct RecoveryTrack {
+    pub format: FrameFormat,
+    pub params: CodecParams,
+    pub timescale: u32,
+    /// Duration of every sample, which is assumed to be constant.
+    pub sample_duration: u32,
+    sample_entry: Option<stsd::SampleEntry>,
+    max_sample_size: u32,
+    // size of every raw AAC frame, if the reference uses a constant size
+    frame_size: Option<u32>,
+    // in-band parameter sets, for tracks without a reference
+    sps: Option<Vec<u8>>,
+    pps: Option<Vec<u8>>,
+    error: Option<Mp4BoxError>,
+}
+
+impl RecoveryTrack {
+    /// Why the samples of the track could not be recovered by `scan`, which
+    /// leaves the track out of the written movie.
+    pub fn error(&self) -> Option<&Mp4BoxError> {
+        self.error.as_ref()
+    }
+}
+
+/// Rebuilds the sample tables of a recording that ended without a movie box,
+/// e.g. after a camera lost power, by scanning its `mdat`.
+///
+/// The tracks are taken from a healthy recording of the same device. Without
+/// one, only a single AVC track with 4-byte length prefixes and in-band
+/// parameter sets is looked for. Sample durations are assumed to be constant
+/// and composition offsets are lost.
+///
+/// Tracks whose samples cannot be told apart are left out, see
+/// [`RecoveryTrack::error`].
+pub struct Recovery {
+    tracks: Vec<RecoveryTrack>,
+    movie_timescale: u32,
+    samples: Vec<RecoveredSample>,
+}
+
+impl Default for Recovery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Recovery {
+    pub fn new() -> Self {
+        Recovery {
+            tracks: vec![RecoveryTrack {
+                format: FrameFormat::Avc { length_size: 4 },
+                params: CodecParams {
+                    kind: Some(MediaKind::Video(VideoInfo {
+                        width: 0,
+                        height: 0,
+                        format: None,
+                    })),
+                    codec_id: Some("h264".into()),
+                    extradata: None,
+                    bit_rate: 0,
+                    convergence_window: 0,
+                    delay: 0,
+                },
+                timescale: 30000,
+                sample_duration: 1001,
+                sample_entry: None,
+                max_sample_size: MAX_SAMPLE_SIZE,
+                frame_size: None,
+                sps: None,
+                pps: None,
+                error: None,
+            }],
+            movie_timescale: MOVIE_TIMESCALE,
+            samples: Vec::new(),
+        }
+    }
+
+    /// Takes the tracks, their sample descriptions and durations from a
+    /// healthy recording of the same device.
+    pub fn with_reference(reference: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let moov_pos = find_box(reference, *b"moov")?;
+        let moov = moov::MovieBox::read(reference)?;
+
+        let mut entries = read_raw_sample_entries(reference, moov_pos)?;
+
+        let mut tracks = Vec::new();
+
+        for trak in moov.tracks {
+            let raw_entry = entries
+                .iter()
+                .position(|(track_id, _)| *track_id == trak.tkhd.track_id)
+                .map(|idx| entries.swap_remove(idx).1);
+
+            let handler = trak.mdia.hdlr.handler();
+            let timescale = trak.mdia.mdhd.timescale;
+            let stbl = &trak.mdia.minf.stbl;
+
+            let (entry, raw_entry) = match (stbl.stsd.entries.first(), raw_entry) {
+                (Some(entry), Some(raw_entry)) => (entry, raw_entry),
+                _ => continue,
+            };
+
+            let format = match entry {
+                stsd::SampleEntry::Avc(avc1) => FrameFormat::Avc {
+                    length_size: avc1.avcc.config.length_size_minus_one + 1,
+                },
+                stsd::SampleEntry::Mpeg4Audio(_) => FrameFormat::Aac,
+                stsd::SampleEntry::Vp9(_) => FrameFormat::Vp9,
+                stsd::SampleEntry::Unknown(entry)
+                    if matches!(&entry.sample_entry.name(), b"hvc1" | b"hev1") =>
+                {
+                    FrameFormat::Hevc {
+                        length_size: hevc_length_size(&entry.data).unwrap_or(4),
+                    }
+                }
+                _ => {
+                    warn!(
+                        "track {}: cannot recover samples of {:?}",
+                        trak.tkhd.track_id,
+                        BoxPrint(raw_entry.sample_entry.name())
+                    );
+                    continue;
+                }
+            };
+
+            let sizes = &stbl.stsz.sample_sizes;
+            let count = stbl.stts.entries.iter().map(|e| e.count).sum::<u32>() as usize;
+            let max_size = (0..count).filter_map(|i| sizes.get(i)).max().unwrap_or(0);
+            let min_size = (0..count).filter_map(|i| sizes.get(i)).min().unwrap_or(0);
+
+            // the most common duration
+            let sample_duration = stbl
+                .stts
+                .entries
+                .iter()
+                .max_by_key(|e| e.count)
+                .map_or(1, |e| e.delta);
+
+            tracks.push(RecoveryTrack {
+                format,
+                params: entry.as_codec_params(handler, timescale)?,
+                timescale,
+                sample_duration,
+                sample_entry: Some(stsd::SampleEntry::Unknown(raw_entry)),
+                // leave room for samples larger than any in the reference
+                max_sample_size: max_size.saturating_mul(4).max(1 << 16),
+                frame_size: if min_size == max_size && max_size > 0 {
+                    Some(max_size)
+                } else {
+                    None
+                },
+                sps: None,
+                pps: None,
+                error: None,
+            });
+        }
+
+        if tracks.is_empty() {
+            return Err(Mp4BoxError::RequiredBoxNotFound(BoxPrint(*b"trak")));
+        }
+
+        Ok(Recovery {
+            tracks,
+            movie_timescale: moov.mvhd.timescale,
+            samples: Vec::new(),
+        })
+    }
+
+    pub fn tracks(&self) -> &[RecoveryTrack] {
+        &self.tracks
+    }
+
+    /// The tracks, e.g. to correct the guessed frame rate of a recovery
+    /// without a reference.
+    pub fn tracks_mut(&mut self) -> &mut [RecoveryTrack] {
+        &mut self.tracks
+    }
+
+    /// The samples found by `scan`, in file order.
+    pub fn samples(&self) -> &[RecoveredSample] {
+        &self.samples
+    }
+
+    /// Finds the samples in the `mdat` of the damaged file, which may be cut
+    /// off or have a placeholder size.
+    pub fn scan(&mut self, damaged: &mut dyn Buffered) -> Result<(), Mp4BoxError> {
+        let (start, end) = find_media_data(damaged)?;
+
+        debug!("scanning media data from {} to {}", start, end);
+
+        let mut window = Window::new(damaged, end);
+        let mut pos = start;
+        let mut gap_start = None;
+
+        self.samples.clear();
+
+        for track in &mut self.tracks {
+            track.error = None;
+        }
+
+        while pos < end {
+            match self.probe(&mut window, pos)? {
+                Some((sample, next)) => {
+                    if let Some(gap_start) = gap_start.take() {
+                        self.fill_gap(&mut window, gap_start, pos)?;
+                    }
+
+                    self.samples.push(sample);
+                    pos = next;
+                }
+                None => {
+                    gap_start.get_or_insert(pos);
+                    pos += 1;
+                }
+            }
+        }
+
+        if let Some(gap_start) = gap_start {
+            self.fill_gap(&mut window, gap_start, end)?;
+        }
+
+        let tracks = &self.tracks;
+        self.samples.retain(|s| tracks[s.track].error.is_none());
+
+        debug!("recovered {} samples", self.samples.len());
+
+        Ok(())
+    }
+
+    // a sample of a track with delimited samples starting at `pos`, and the
+    // position following it
+    fn probe(
+        &mut self,
+        window: &mut Window,
+        pos: u64,
+    ) -> Result<Option<(RecoveredSample, u64)>, Mp4BoxError> {
+        for idx in 0..self.tracks.len() {
+            let track = &self.tracks[idx];
+
+            let found = match track.format {
+                FrameFormat::Avc { length_size } => {
+                    probe_nal_sample(window, pos, length_size, false, track.max_sample_size)?
+                }
+                FrameFormat::Hevc { length_size } => {
+                    probe_nal_sample(window, pos, length_size, true, track.max_sample_size)?
+                }
+                FrameFormat::Adts => probe_adts_frame(window, pos)?,
+                FrameFormat::Aac | FrameFormat::Vp9 => None,
+            };
+
+            if let Some((offset, size, keyframe)) = found {
+                if let FrameFormat::Avc { length_size } = track.format {
+                    if track.sample_entry.is_none() {
+                        self.collect_parameter_sets(window, idx, pos, size, length_size)?;
+                    }
+                }
+
+                let sample = RecoveredSample {
+                    track: idx,
+                    offset,
+                    size,
+                    keyframe,
+                };
+
+                return Ok(Some((sample, offset + size as u64)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // the bytes between delimited samples belong to a track with undelimited
+    // samples, or are skipped
+    fn fill_gap(&mut self, window: &mut Window, start: u64, end: u64) -> Result<(), Mp4BoxError> {
+        let head = window.get(start, 16)?;
+
+        if head.iter().all(|&b| b == 0) {
+            debug!("skipping {} zero bytes at {}", end - start, start);
+            return Ok(());
+        }
+
+        let first = head[0];
+
+        let idx = match self
+            .tracks
+            .iter()
+            .position(|t| !t.format.is_delimited() && t.format.may_start(first))
+        {
+            Some(idx) => idx,
+            None => {
+                debug!("skipping {} bytes at {}", end - start, start);
+                return Ok(());
+            }
+        };
+
+        let track = &mut self.tracks[idx];
+
+        let samples = match (track.format, track.frame_size) {
+            (FrameFormat::Aac, Some(frame_size)) => {
+                let count = (end - start) / frame_size as u64;
+
+                Some(
+                    (0..count)
+                        .map(|i| (start + i * frame_size as u64, frame_size, true))
+                        .collect(),
+                )
+            }
+            (FrameFormat::Vp9, _) => split_vp9_frames(window, start, end, track.max_sample_size)?,
+            // raw AAC frames do not carry their size
+            _ => None,
+        };
+
+        match samples {
+            Some(samples) => {
+                for (offset, size, keyframe) in samples {
+                    self.samples.push(RecoveredSample {
+                        track: idx,
+                        offset,
+                        size,
+                        keyframe,
+                    });
+                }
+            }
+            None if track.error.is_none() => {
+                warn!(
+                    "track {}: cannot tell apart the samples of {} bytes at {}",
+                    idx,
+                    end - start,
+                    start
+                );
+                track.error = Some(Mp4BoxError::UnsplittableSamples(end - start, start));
+            }
+            None => {}
+        }
+
+        Ok(())
+    }
+
+    fn collect_parameter_sets(
+        &mut self,
+        window: &mut Window,
+        track: usize,
+        pos: u64,
+        size: u32,
+        length_size: u8,
+    ) -> Result<(), Mp4BoxError> {
+        let track = &mut self.tracks[track];
+        let end = pos + size as u64;
+        let mut pos = pos;
+
+        while pos < end && (track.sps.is_none() || track.pps.is_none()) {
+            let length = read_length(window.get(pos, length_size as usize)?) as u64;
+            let nal = window
+                .get(pos + length_size as u64, length as usize)?
+                .to_vec();
+
+            match nal.first().map(|b| b & 0x1f) {
+                Some(7) if track.sps.is_none() => track.sps = Some(nal),
+                Some(8) if track.pps.is_none() => track.pps = Some(nal),
+                _ => {}
+            }
+
+            pos += length_size as u64 + length;
+        }
+
+        Ok(())
+    }
+
+    // tracks without a reference describe themselves by their parameter sets
+    fn finish_tracks(&mut self) -> Result<(), Mp4BoxError> {
+        for track in &mut self.tracks {
+            if track.sample_entry.is_some() {
+                continue;
+            }
+
+            let (sps, pps) = match (track.sps.take(), track.pps.take()) {
+                (Some(sps), Some(pps)) if sps.len() >= 4 => (sps, pps),
+                _ => return Err(Mp4BoxError::RequiredBoxNotFound(BoxPrint(*b"avcC"))),
+            };
+
+            let mut extradata = vec![0, 0, 1];
+            extradata.extend(&sps);
+            extradata.extend(&[0, 0, 1]);
+            extradata.extend(&pps);
+            track.params.extradata = Some(extradata);
+
+            let config = avcc::AvcDecoderConfigurationRecord {
+                profile_indication: sps[1],
+                profile_compatibility: sps[2],
+                level_indication: sps[3],
+                length_size_minus_one: 3,
+                sequence_parameter_sets: vec![avcc::SequenceParameterSet(sps)],
+                picture_parameter_sets: vec![avcc::PictureParameterSet(pps)],
+            };
+
+            track.sample_entry = Some(stsd::SampleEntry::Avc(avc1::AvcSampleEntryBox::new(
+                0,
+                0,
+                avcc::AvcConfigurationBox::new(config),
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Writes the samples found by `scan` to a new movie.
+    pub fn write(mut self, damaged: &mut dyn Buffered, writer: Writer) -> AvResult<()> {
+        self.finish_tracks()?;
+
+        let mut muxer = Mp4Muxer::new();
+        let mut streams = Vec::with_capacity(self.tracks.len());
+        let mut durations = Vec::with_capacity(self.tracks.len());
+        let mut stream_indices = Vec::with_capacity(self.tracks.len());
+
+        for track in self.tracks {
+            if track.error.is_some() {
+                stream_indices.push(None);
+                continue;
+            }
+
+            let index = streams.len();
+            stream_indices.push(Some(index));
+
+            if let Some(entry) = track.sample_entry {
+                muxer.set_sample_entry(index, entry);
+            }
+
+            let timebase = Rational64::new(1, track.timescale as i64);
+
+            streams.push(Stream {
+                id: index as isize,
+                index,
+                params: track.params,
+                start: None,
+                duration: None,
+                timebase,
+                user_private: None,
+            });
+            durations.push((track.sample_duration, timebase));
+        }
+
+        let mut ctx = MuxerCtx::new(Box::new(muxer), writer);
+
+        ctx.set_global_info(GlobalInfo {
+            duration: None,
+            timebase: Some(Rational64::new(1, self.movie_timescale as i64)),
+            streams,
+        })?;
+        ctx.write_header()?;
+
+        let mut times = vec![0i64; durations.len()];
+
+        for sample in &self.samples {
+            let index = match stream_indices[sample.track] {
+                Some(index) => index,
+                None => continue,
+            };
+
+            let mut data = vec![0u8; sample.size as usize];
+            goto(damaged, sample.offset)?;
+            damaged.read_exact(&mut data)?;
+
+            let (duration, timebase) = durations[index];
+            let time = times[index];
+            times[index] += duration as i64;
+
+            let packet = Packet {
+                data,
+                pos: Some(sample.offset as usize),
+                stream_index: index as isize,
+                t: TimeInfo {
+                    pts: Some(time),
+                    dts: Some(time),
+                    duration: Some(duration as u64),
+                    timebase: Some(timebase),
+                    user_private: None,
+                },
+                is_key: sample.keyframe,
+                is_corrupted: false,
+            };
+
+            ctx.write_packet(Arc::new(packet))?;
+        }
+
+        ctx.write_trailer()?;
+
+        Ok(())
+    }
+}
+
+// buffered random access to the media data
+struct Window<'a> {
+    input: &'a mut dyn Buffered,
+    end: u64,
+    start: u64,
+    data: Vec<u8>,
+}
+
+impl<'a> Window<'a> {
+    fn new(input: &'a mut dyn Buffered, end: u64) -> Self {
+        Window {
+            input,
+            end,
+            start: 0,
+            data: Vec::new(),
+        }
+    }
+
+    // up to `size` bytes from `pos`, fewer at the end of the media data
+    fn get(&mut self, pos: u64, size: usize) -> Result<&[u8], Mp4BoxError> {
+        let size = size.min(self.end.saturating_sub(pos) as usize);
+
+        if size == 0 {
+            return Ok(&[]);
+        }
+
+        if pos < self.start || pos + size as u64 > self.start + self.data.len() as u64 {
+            let length = size.max(WINDOW_SIZE).min((self.end - pos) as usize);
+
+            goto(self.input, pos)?;
+            self.data.resize(length, 0);
+            self.input.read_exact(&mut self.data)?;
+            self.start = pos;
+        }
+
+        let offset = (pos - self.start) as usize;
+
+        Ok(&self.data[offset..offset + size])
+    }
+}
+
+fn read_length(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |length, &b| length << 8 | b as u32)
+}
+
+// NAL units of one access unit: the offset, size and whether it is a random
+// access point
+fn probe_nal_sample(
+    window: &mut Window,
+    start: u64,
+    length_size: u8,
+    hevc: bool,
+    max_size: u32,
+) -> Result<Option<(u64, u32, bool)>, Mp4BoxError> {
+    let length_size = length_size as u64;
+    let header_size = if hevc { 3 } else { 2 };
+
+    let mut pos = start;
+    let mut has_slice = false;
+    let mut keyframe = false;
+
+    loop {
+        let bytes = window.get(pos, (length_size + header_size) as usize)?;
+
+        if bytes.len() < (length_size + header_size) as usize {
+            break;
+        }
+
+        let length = read_length(&bytes[..length_size as usize]) as u64;
+
+        let mut header = [0u8; 3];
+        header[..header_size as usize].copy_from_slice(&bytes[length_size as usize..]);
+
+        if length < header_size || pos + length_size + length - start > max_size as u64 {
+            break;
+        }
+        if window.get(pos + length_size, length as usize)?.len() < length as usize {
+            break;
+        }
+
+        let nal = if hevc {
+            classify_hevc_nal(&header)
+        } else {
+            classify_avc_nal(&header)
+        };
+
+        let nal = match nal {
+            Some(nal) => nal,
+            None => break,
+        };
+
+        // the next access unit starts here
+        if has_slice && nal.starts_access_unit {
+            break;
+        }
+        // a sample starts with the first NAL unit of an access unit
+        if pos == start && !nal.starts_access_unit {
+            return Ok(None);
+        }
+
+        has_slice |= nal.is_slice;
+        keyframe |= nal.is_random_access;
+
+        pos += length_size + length;
+    }
+
+    if !has_slice {
+        return Ok(None);
+    }
+
+    Ok(Some((start, (pos - start) as u32, keyframe)))
+}
+
+struct NalInfo {
+    is_slice: bool,
+    is_random_access: bool,
+    starts_access_unit: bool,
+}
+
+fn classify_avc_nal(header: &[u8]) -> Option<NalInfo> {
+    // forbidden_zero_bit
+    if header[0] & 0x80 != 0 {
+        return None;
+    }
+
+    let nal_type = header[0] & 0x1f;
+    // first_mb_in_slice is 0 when its exp-Golomb code starts with a one
+    let first_slice = header[1] & 0x80 != 0;
+
+    match nal_type {
+        1..=5 => Some(NalInfo {
+            is_slice: true,
+            is_random_access: nal_type == 5,
+            starts_access_unit: first_slice,
+        }),
+        // SEI, SPS, PPS and access unit delimiters
+        6..=9 => Some(NalInfo {
+            is_slice: false,
+            is_random_access: false,
+            starts_access_unit: true,
+        }),
+        10..=13 | 14 | 15 | 19 | 20 => Some(NalInfo {
+            is_slice: false,
+            is_random_access: false,
+            starts_access_unit: false,
+        }),
+        _ => None,
+    }
+}
+
+fn classify_hevc_nal(header: &[u8]) -> Option<NalInfo> {
+    // forbidden_zero_bit and a non-zero nuh_temporal_id_plus1
+    if header[0] & 0x80 != 0 || header[1] & 0x07 == 0 {
+        return None;
+    }
+
+    let nal_type = (header[0] >> 1) & 0x3f;
+    let first_slice = header[2] & 0x80 != 0;
+
+    match nal_type {
+        0..=9 | 16..=21 => Some(NalInfo {
+            is_slice: true,
+            is_random_access: (16..=21).contains(&nal_type),
+            starts_access_unit: first_slice,
+        }),
+        // VPS, SPS, PPS, access unit delimiters and prefix SEI
+        32..=35 | 39 => Some(NalInfo {
+            is_slice: false,
+            is_random_access: false,
+            starts_access_unit: true,
+        }),
+        36..=38 | 40 => Some(NalInfo {
+            is_slice: false,
+            is_random_access: false,
+            starts_access_unit: false,
+        }),
+        _ => None,
+    }
+}
+
+// the payload of an ADTS frame
+fn probe_adts_frame(
+    window: &mut Window,
+    pos: u64,
+) -> Result<Option<(u64, u32, bool)>, Mp4BoxError> {
+    let header = window.get(pos, 7)?;
+
+    // syncword and layer 0
+    if header.len() < 7 || header[0] != 0xff || header[1] & 0xf6 != 0xf0 {
+        return Ok(None);
+    }
+
+    let protection_absent = header[1] & 0x01 != 0;
+    let header_size = if protection_absent { 7 } else { 9 };
+
+    let frame_length =
+        ((header[3] as u32 & 0x03) << 11) | (header[4] as u32) << 3 | (header[5] as u32) >> 5;
+
+    if frame_length <= header_size {
+        return Ok(None);
+    }
+    if (window.get(pos, frame_length as usize)?.len() as u32) < frame_length {
+        return Ok(None);
+    }
+
+    Ok(Some((
+        pos + header_size as u64,
+        frame_length - header_size,
+        true,
+    )))
+}
+
+// VP9 samples between `start` and `end`, split at superframe indices from the
+// end; frames outside of superframes do not carry their size, so the bytes
+// before the last index are taken for a single frame. None if that frame is
+// larger than `max_size` or does not start with a frame marker.
+fn split_vp9_frames(
+    window: &mut Window,
+    start: u64,
+    end: u64,
+    max_size: u32,
+) -> Result<Option<Vec<(u64, u32, bool)>>, Mp4BoxError> {
+    let mut samples = Vec::new();
+    let mut sample_end = end;
+
+    while sample_end > start {
+        let sample_start = match vp9_superframe_size(window, start, sample_end)? {
+            Some(size) => sample_end - size,
+            None => start,
+        };
+
+        let size = sample_end - sample_start;
+        let first = window.get(sample_start, 1)?.first().copied().unwrap_or(0);
+
+        if size > max_size as u64 || !FrameFormat::Vp9.may_start(first) {
+            return Ok(None);
+        }
+
+        samples.push((sample_start, size as u32, vp9_keyframe(first)));
+        sample_end = sample_start;
+    }
+
+    samples.reverse();
+
+    Ok(Some(samples))
+}
+
+// the size of the superframe ending at `end`, whose index lists the sizes of
+// its frames between two equal marker bytes
+fn vp9_superframe_size(
+    window: &mut Window,
+    start: u64,
+    end: u64,
+) -> Result<Option<u64>, Mp4BoxError> {
+    let marker = match window.get(end - 1, 1)?.first() {
+        Some(&marker) if marker & 0xe0 == 0xc0 => marker,
+        _ => return Ok(None),
+    };
+
+    let frames = (marker & 0x07) as u64 + 1;
+    let size_bytes = ((marker >> 3) & 0x03) as u64 + 1;
+    let index_size = 2 + frames * size_bytes;
+
+    if end - start < index_size {
+        return Ok(None);
+    }
+
+    let index = window.get(end - index_size, index_size as usize)?;
+
+    if index[0] != marker {
+        return Ok(None);
+    }
+
+    // little-endian frame sizes
+    let frames_size: u64 = index[1..index.len() - 1]
+        .chunks(size_bytes as usize)
+        .map(|size| size.iter().rev().fold(0, |s, &b| s << 8 | b as u64))
+        .sum();
+    let size = frames_size + index_size;
+
+    if size > end - start {
+        return Ok(None);
+    }
+
+    Ok(Some(size))
+}
+
+// show_existing_frame and frame_type in the uncompressed header, which has
+// a reserved bit before them in profile 3
+fn vp9_keyframe(byte: u8) -> bool {
+    let profile = (byte >> 5) & 1 | (byte >> 3) & 2;
+    let flags = if profile == 3 { byte << 1 } else { byte };
+
+    flags & 0x0c == 0
+}
+
+// lengthSizeMinusOne from the hvcC box following the visual sample entry
+fn hevc_length_size(data: &[u8]) -> Option<u8> {
+    let mut children = data.get(70..)?;
+
+    while children.len() >= 8 {
+        let size = BigEndian::read_u32(children) as usize;
+
+        if size < 8 || size > children.len() {
+            break;
+        }
+
+        if &children[4..8] == b"hvcC" {
+            return children.get(8 + 21).map(|b| (b & 0x03) + 1);
+        }
+
+        children = &children[size..];
+    }
+
+    None
+}
+
+// the offset of the first box named `name` at the top level
+fn find_box(input: &mut dyn Buffered, name: BoxName) -> Result<u64, Mp4BoxError> {
+    goto(input, 0)?;
+
+    loop {
+        let pos = input.seek(SeekFrom::Current(0))?;
+        let boks = Boks::peek(input)?;
+
+        if boks.name == name {
+            return Ok(pos);
+        }
+
+        skip(input, boks.size)?;
+    }
+}
+
+// the start and end of the media data, up to the end of the file when its size
+// is a placeholder
+fn find_media_data(input: &mut dyn Buffered) -> Result<(u64, u64), Mp4BoxError> {
+    let file_size = input.seek(SeekFrom::End(0))?;
+    goto(input, 0)?;
+
+    let mut pos = 0;
+
+    while pos + 8 <= file_size {
+        let boks = Boks::read_raw(input)?;
+        let header_size = boks.read_size as u64;
+
+        if boks.name == *b"mdat" {
+            let end = if boks.size < header_size || pos + boks.size > file_size {
+                file_size
+            } else {
+                pos + boks.size
+            };
+
+            return Ok((pos + header_size, end));
+        }
+
+        if boks.size < header_size || pos + boks.size > file_size {
+            break;
+        }
+
+        pos += boks.size;
+        goto(input, pos)?;
+    }
+
+    Err(Mp4BoxError::RequiredBoxNotFound(BoxPrint(*b"mdat")))
+}
+
+// the first sample entry of every track as it is, with the id of the track
+fn read_raw_sample_entries(
+    input: &mut dyn Buffered,
+    moov_pos: u64,
+) -> Result<Vec<(u32, stsd::UnknownSampleEntryBox)>, Mp4BoxError> {
+    goto(input, moov_pos)?;
+    let moov = Boks::read_named(input, *b"moov")?;
+
+    let mut entries = Vec::new();
+
+    let iter = BoksIterator::new(input, moov.remaining_size());
+    while let Some((pos, boks)) = iter.next(input)? {
+        if boks.name == *b"trak" {
+            let trak = Boks::read(input)?;
+
+            let mut track_id = None;
+            let mut entry = None;
+
+            let children = BoksIterator::new(input, trak.remaining_size());
+            while let Some((_, child)) = children.next(input)? {
+                match &child.name {
+                    b"tkhd" => track_id = Some(tkhd::TrackHeaderBox::read(input)?.track_id),
+                    b"mdia" => {
+                        let mdia = Boks::read(input)?;
+
+                        if let Some(stsd) = find_child_path(
+                            input,
+                            mdia.remaining_size(),
+                            &[*b"minf", *b"stbl", *b"stsd"],
+                        )? {
+                            // entry count
+                            if stsd.remaining_size() > 4 {
+                                skip(input, 4)?;
+                                entry = Some(stsd::UnknownSampleEntryBox::read(input)?);
+                            }
+                        }
+                    }
+                    _ => skip(input, child.size)?,
+                }
+            }
+
+            if let (Some(track_id), Some(entry)) = (track_id, entry) {
+                entries.push((track_id, entry));
+            }
+        }
+
+        goto(input, pos + boks.size)?;
+    }
+
+    Ok(entries)
+}
+
+// descends into the children named by `path`, leaving the reader after the
+// header of the last one
+fn find_child_path(
+    input: &mut dyn Buffered,
+    mut size: u64,
+    path: &[BoxName],
+) -> Result<Option<FullBox>, Mp4BoxError> {
+    for (i, &name) in path.iter().enumerate() {
+        let iter = BoksIterator::new(input, size);
+        let mut found = false;
+
+        while let Some((_, boks)) = iter.next(input)? {
+            if boks.name != name {
+                skip(input, boks.size)?;
+                continue;
+            }
+
+            if i + 1 == path.len() {
+                return Ok(Some(FullBox::read_named(input, name)?));
+            }
+
+            size = Boks::read(input)?.remaining_size();
+            found = true;
+            break;
+        }
+
+        if !found {
+            break;
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use av_format::buffer::AccReader;
+    use std::io::Cursor;
+
+    use crate::test_box;
+
+    const SPS: &[u8] = &[0x67, 0x42, 0x00, 0x1e];
+    const PPS: &[u8] = &[0x68, 0xce, 0x38, 0x80];
+    const IDR: &[u8] = &[0x65, 0x88, 0x84, 0x00, 0x10];
+    const SLICE: &[u8] = &[0x41, 0x9a, 0x02, 0x03];
+    // a second slice of the same picture
+    const NEXT_SLICE: &[u8] = &[0x41, 0x1a, 0x04];
+
+    fn input(data: &[u8]) -> AccReader<Cursor<Vec<u8>>> {
+        AccReader::new(Cursor::new(data.to_vec()))
+    }
+
+    // NAL units with length prefixes of `length_size` bytes
+    fn nals(length_size: usize, units: &[&[u8]]) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        for unit in units {
+            let length = (unit.len() as u32).to_be_bytes();
+            data.extend(&length[4 - length_size..]);
+            data.extend(*unit);
+        }
+
+        data
+    }
+
+    fn probe(data: &[u8], start: u64, length_size: u8, hevc: bool) -> Option<(u64, u32, bool)> {
+        let mut reader = input(data);
+        let mut window = Window::new(&mut reader, data.len() as u64);
+
+        probe_nal_sample(&mut window, start, length_size, hevc, MAX_SAMPLE_SIZE).unwrap()
+    }
+
+    fn track(format: FrameFormat, frame_size: Option<u32>) -> RecoveryTrack {
+        RecoveryTrack {
+            format,
+            params: CodecParams {
+                kind: None,
+                codec_id: None,
+                extradata: None,
+                bit_rate: 0,
+                convergence_window: 0,
+                delay: 0,
+            },
+            timescale: 48000,
+            sample_duration: 1024,
+            sample_entry: None,
+            max_sample_size: 1 << 16,
+            frame_size,
+            sps: None,
+            pps: None,
+            error: None,
+        }
+    }
+
+    fn with_tracks(tracks: Vec<RecoveryTrack>) -> Recovery {
+        Recovery {
+            tracks,
+            movie_timescale: MOVIE_TIMESCALE,
+            samples: Vec::new(),
+        }
+    }
+
+    fn adts_frame(payload: &[u8], crc: bool) -> Vec<u8> {
+        let header_size = if crc { 9 } else { 7 };
+        let length = payload.len() + header_size;
+
+        let mut data = vec![
+            0xff,
+            if crc { 0xf0 } else { 0xf1 },
+            0x50,
+            0x80 | (length >> 11) as u8 & 0x03,
+            (length >> 3) as u8,
+            (length << 5) as u8 | 0x1f,
+            0xfc,
+        ];
+        if crc {
+            data.extend(&[0x12, 0x34]);
+        }
+        data.extend(payload);
+        data
+    }
+
+    // frames in a superframe with an index of 1-byte sizes
+    fn vp9_superframe(frames: &[&[u8]]) -> Vec<u8> {
+        let marker = 0xc0 | (frames.len() - 1) as u8;
+
+        let mut data = Vec::new();
+        for frame in frames {
+            data.extend(*frame);
+        }
+        data.push(marker);
+        data.extend(frames.iter().map(|frame| frame.len() as u8));
+        data.push(marker);
+        data
+    }
+
+    #[test]
+    fn window() {
+        let data = (0..100).collect::<Vec<u8>>();
+        let mut reader = input(&data);
+        let mut window = Window::new(&mut reader, 80);
+
+        assert_eq!(window.get(0, 4).unwrap(), &[0, 1, 2, 3]);
+        assert_eq!(window.get(70, 20).unwrap(), &data[70..80]);
+        assert_eq!(window.get(80, 4).unwrap(), &[] as &[u8]);
+        assert_eq!(window.get(90, 4).unwrap(), &[] as &[u8]);
+        assert_eq!(window.get(10, 2).unwrap(), &[10, 11]);
+    }
+
+    #[test]
+    fn avc_nal_units() {
+        let idr = classify_avc_nal(&[0x65, 0x88]).unwrap();
+        assert!(idr.is_slice && idr.is_random_access && idr.starts_access_unit);
+
+        let slice = classify_avc_nal(&[0x41, 0x1a]).unwrap();
+        assert!(slice.is_slice && !slice.is_random_access && !slice.starts_access_unit);
+
+        let sei = classify_avc_nal(&[0x06, 0x05]).unwrap();
+        assert!(!sei.is_slice && sei.starts_access_unit);
+
+        let filler = classify_avc_nal(&[0x0c, 0xff]).unwrap();
+        assert!(!filler.is_slice && !filler.starts_access_unit);
+
+        // forbidden_zero_bit and unspecified types
+        assert!(classify_avc_nal(&[0xe5, 0x88]).is_none());
+        assert!(classify_avc_nal(&[0x00, 0x88]).is_none());
+        assert!(classify_avc_nal(&[0x18, 0x88]).is_none());
+    }
+
+    #[test]
+    fn hevc_nal_units() {
+        let idr = classify_hevc_nal(&[0x26, 0x01, 0xaf]).unwrap();
+        assert!(idr.is_slice && idr.is_random_access && idr.starts_access_unit);
+
+        let slice = classify_hevc_nal(&[0x02, 0x01, 0x50]).unwrap();
+        assert!(slice.is_slice && !slice.is_random_access && !slice.starts_access_unit);
+
+        let vps = classify_hevc_nal(&[0x40, 0x01, 0x0c]).unwrap();
+        assert!(!vps.is_slice && vps.starts_access_unit);
+
+        let suffix_sei = classify_hevc_nal(&[0x50, 0x01, 0x00]).unwrap();
+        assert!(!suffix_sei.is_slice && !suffix_sei.starts_access_unit);
+
+        // forbidden_zero_bit, nuh_temporal_id_plus1 of 0 and reserved types
+        assert!(classify_hevc_nal(&[0xa6, 0x01, 0xaf]).is_none());
+        assert!(classify_hevc_nal(&[0x26, 0x00, 0xaf]).is_none());
+        assert!(classify_hevc_nal(&[0x60, 0x01, 0x00]).is_none());
+    }
+
+    #[test]
+    fn avc_access_units() {
+        let first = nals(4, &[SPS, PPS, IDR, NEXT_SLICE]);
+        let second = nals(4, &[SLICE]);
+        let data = [first.clone(), second.clone()].concat();
+
+        assert_eq!(
+            probe(&data, 0, 4, false),
+            Some((0, first.len() as u32, true))
+        );
+        assert_eq!(
+            probe(&data, first.len() as u64, 4, false),
+            Some((first.len() as u64, second.len() as u32, false))
+        );
+
+        // not the first NAL unit of an access unit
+        let start = nals(4, &[SPS, PPS, IDR]).len() as u64;
+        assert_eq!(probe(&data, start, 4, false), None);
+
+        // a length prefix of 2 bytes
+        let data = nals(2, &[IDR, NEXT_SLICE]);
+        assert_eq!(
+            probe(&data, 0, 2, false),
+            Some((0, data.len() as u32, true))
+        );
+    }
+
+    #[test]
+    fn avc_cut_off_access_unit() {
+        let complete = nals(4, &[SPS, PPS, IDR]);
+        let mut data = [complete.clone(), nals(4, &[&[0x41, 0x1a, 0, 0, 0, 0]])].concat();
+        data.truncate(data.len() - 2);
+
+        // the cut-off slice is left out
+        assert_eq!(
+            probe(&data, 0, 4, false),
+            Some((0, complete.len() as u32, true))
+        );
+
+        // an access unit without a complete slice
+        let mut data = nals(4, &[SPS, SLICE]);
+        data.truncate(data.len() - 1);
+
+        assert_eq!(probe(&data, 0, 4, false), None);
+    }
+
+    #[test]
+    fn hevc_access_units() {
+        let vps: &[u8] = &[0x40, 0x01, 0x0c];
+        let sps: &[u8] = &[0x42, 0x01, 0x01];
+        let pps: &[u8] = &[0x44, 0x01, 0xc1];
+        let idr: &[u8] = &[0x26, 0x01, 0xaf, 0x10];
+        let next_slice: &[u8] = &[0x26, 0x01, 0x50, 0x20];
+        let slice: &[u8] = &[0x02, 0x01, 0xd0, 0x30];
+
+        let first = nals(4, &[vps, sps, pps, idr, next_slice]);
+        let second = nals(4, &[slice]);
+        let data = [first.clone(), second.clone()].concat();
+
+        assert_eq!(
+            probe(&data, 0, 4, true),
+            Some((0, first.len() as u32, true))
+        );
+        assert_eq!(
+            probe(&data, first.len() as u64, 4, true),
+            Some((first.len() as u64, second.len() as u32, false))
+        );
+
+        // the second slice is cut off
+        let complete = nals(4, &[vps, sps, pps, idr]);
+        let mut data = first;
+        data.truncate(data.len() - 1);
+
+        assert_eq!(
+            probe(&data, 0, 4, true),
+            Some((0, complete.len() as u32, true))
+        );
+    }
+
+    #[test]
+    fn adts_frames() {
+        let payload = [0x21, 0x10, 0x05, 0x40];
+
+        for &crc in &[false, true] {
+            let header_size = if crc { 9 } else { 7 };
+            let data = adts_frame(&payload, crc);
+
+            let mut reader = input(&data);
+            let mut window = Window::new(&mut reader, data.len() as u64);
+
+            assert_eq!(
+                probe_adts_frame(&mut window, 0).unwrap(),
+                Some((header_size, payload.len() as u32, true))
+            );
+            assert_eq!(probe_adts_frame(&mut window, 1).unwrap(), None);
+
+            // cut off
+            let mut reader = input(&data);
+            let mut window = Window::new(&mut reader, data.len() as u64 - 1);
+
+            assert_eq!(probe_adts_frame(&mut window, 0).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn raw_aac_gap() {
+        let data = [0x21u8; 35];
+
+        let mut reader = input(&data);
+        let mut window = Window::new(&mut reader, data.len() as u64);
+        let mut recovery = with_tracks(vec![track(FrameFormat::Aac, Some(10))]);
+
+        recovery.fill_gap(&mut window, 0, 30).unwrap();
+
+        let offsets = recovery
+            .samples
+            .iter()
+            .map(|s| s.offset)
+            .collect::<Vec<_>>();
+        assert_eq!(offsets, [0, 10, 20]);
+        assert!(recovery.samples.iter().all(|s| s.size == 10 && s.keyframe));
+        assert!(recovery.tracks[0].error().is_none());
+
+        // frames of different sizes cannot be told apart
+        let mut recovery = with_tracks(vec![track(FrameFormat::Aac, None)]);
+
+        recovery.fill_gap(&mut window, 0, 30).unwrap();
+
+        assert!(recovery.samples.is_empty());
+        assert!(matches!(
+            recovery.tracks[0].error(),
+            Some(Mp4BoxError::UnsplittableSamples(30, 0))
+        ));
+    }
+
+    #[test]
+    fn zero_gap() {
+        let data = [0u8; 32];
+
+        let mut reader = input(&data);
+        let mut window = Window::new(&mut reader, data.len() as u64);
+        let mut recovery = with_tracks(vec![track(FrameFormat::Aac, None)]);
+
+        recovery.fill_gap(&mut window, 0, 32).unwrap();
+
+        assert!(recovery.samples.is_empty());
+        assert!(recovery.tracks[0].error().is_none());
+    }
+
+    #[test]
+    fn vp9_superframes() {
+        let keyframe = vp9_superframe(&[&[0x82, 1, 2, 3, 4], &[0x86, 5, 6]]);
+        let inter = vp9_superframe(&[&[0x86, 1, 2, 3], &[0x86, 4]]);
+        let data = [keyframe.clone(), inter.clone()].concat();
+        let end = data.len() as u64;
+
+        let mut reader = input(&data);
+        let mut window = Window::new(&mut reader, end);
+
+        assert_eq!(
+            vp9_superframe_size(&mut window, 0, end).unwrap(),
+            Some(inter.len() as u64)
+        );
+        assert_eq!(
+            vp9_superframe_size(&mut window, 0, keyframe.len() as u64).unwrap(),
+            Some(keyframe.len() as u64)
+        );
+        // the index does not fit
+        assert_eq!(
+            vp9_superframe_size(&mut window, end - 3, end).unwrap(),
+            None
+        );
+        // not the end of a superframe
+        assert_eq!(vp9_superframe_size(&mut window, 0, end - 1).unwrap(), None);
+
+        assert_eq!(
+            split_vp9_frames(&mut window, 0, end, 1 << 16).unwrap(),
+            Some(vec![
+                (0, keyframe.len() as u32, true),
+                (keyframe.len() as u64, inter.len() as u32, false),
+            ])
+        );
+
+        // a frame outside of a superframe is taken as a whole
+        let frame = [0x86, 0, 0, 0, 0, 0, 0];
+        let data = [&frame[..], &keyframe[..]].concat();
+        let end = data.len() as u64;
+
+        let mut reader = input(&data);
+        let mut window = Window::new(&mut reader, end);
+
+        assert_eq!(
+            split_vp9_frames(&mut window, 0, end, 1 << 16).unwrap(),
+            Some(vec![(0, 7, false), (7, keyframe.len() as u32, true)])
+        );
+        assert_eq!(split_vp9_frames(&mut window, 0, end, 8).unwrap(), None);
+        // no frame marker
+        assert_eq!(
+            split_vp9_frames(&mut window, 1, end, 1 << 16).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn vp9_frame_types() {
+        assert!(vp9_keyframe(0x82));
+        assert!(!vp9_keyframe(0x86));
+        // profile 3 has a reserved bit
+        assert!(vp9_keyframe(0xb0));
+        assert!(!vp9_keyframe(0xb4));
+    }
+
+    #[test]
+    fn scan_truncated_media_data() {
+        let first = nals(4, &[SPS, PPS, IDR]);
+        let second = nals(4, &[SLICE]);
+        let mut third = nals(4, &[&[0x41, 0x9a, 1, 2, 3, 4, 5, 6]]);
+        third.truncate(10);
+
+        let media_data = [first.clone(), second.clone(), third].concat();
+        let ftyp = test_box(b"ftyp", b"isom\0\0\0\0isomavc1");
+
+        // extending to the end of the file, or beyond it
+        for &size in &[0u32, 0x10000] {
+            let mut data = ftyp.clone();
+            data.extend(&size.to_be_bytes());
+            data.extend(b"mdat");
+            data.extend(&media_data);
+
+            let start = ftyp.len() as u64 + 8;
+
+            let mut recovery = Recovery::new();
+            recovery.scan(&mut input(&data)).unwrap();
+
+            assert_eq!(
+                recovery.samples(),
+                &[
+                    RecoveredSample {
+                        track: 0,
+                        offset: start,
+                        size: first.len() as u32,
+                        keyframe: true,
+                    },
+                    RecoveredSample {
+                        track: 0,
+                        offset: start + first.len() as u64,
+                        size: second.len() as u32,
+                        keyframe: false,
+                    },
+                ]
+            );
+            assert!(recovery.tracks()[0].error().is_none());
+            assert_eq!(recovery.tracks[0].sps.as_deref(), Some(SPS));
+            assert_eq!(recovery.tracks[0].pps.as_deref(), Some(PPS));
+        }
+    }
+}
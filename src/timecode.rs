@@ -0,0 +1,195 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// SMPTE timecode as stored in the samples of `tmcd` tracks.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Timecode {
+    pub hours: u32,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub frames: u8,
+    /// Frame numbers 0 and 1 (0 to 3 at 60 fps) are skipped every minute
+    /// except every tenth, to follow 29.97 fps material.
+    pub drop_frame: bool,
+}
+
+impl Timecode {
+    // frames skipped at the start of a minute in drop-frame mode
+    fn dropped_frames(fps: u32) -> u32 {
+        fps / 15
+    }
+
+    /// Timecode of a frame counter at `fps` frames per second, the
+    /// `number_of_frames` of the `tmcd` sample entry.
+    pub fn from_frame_number(mut frame: u32, fps: u32, drop_frame: bool) -> Self {
+        let fps = fps.max(1);
+
+        if drop_frame {
+            let dropped = Self::dropped_frames(fps);
+            let per_minute = fps * 60 - dropped;
+            let per_ten_minutes = fps * 600 - dropped * 9;
+
+            let tens = frame / per_ten_minutes;
+            let rest = frame % per_ten_minutes;
+
+            // the first minute of every ten keeps all its frame numbers
+            frame += dropped * 9 * tens;
+            if rest >= dropped {
+                frame += dropped * ((rest - dropped) / per_minute);
+            }
+        }
+
+        Timecode {
+            hours: frame / (fps * 3600),
+            minutes: (frame / (fps * 60) % 60) as u8,
+            seconds: (frame / fps % 60) as u8,
+            frames: (frame % fps) as u8,
+            drop_frame,
+        }
+    }
+
+    /// The frame counter stored in a `tmcd` sample, `None` if it does not fit
+    /// into 32 bits.
+    pub fn to_frame_number(&self, fps: u32) -> Option<u32> {
+        let minutes = self
+            .hours
+            .checked_mul(60)?
+            .checked_add(self.minutes as u32)?;
+
+        let mut frame = minutes
+            .checked_mul(60)?
+            .checked_add(self.seconds as u32)?
+            .checked_mul(fps)?
+            .checked_add(self.frames as u32)?;
+
+        if self.drop_frame {
+            frame = frame.checked_sub(Self::dropped_frames(fps) * (minutes - minutes / 10))?;
+        }
+
+        Some(frame)
+    }
+}
+
+/// `HH:MM:SS:FF`, with a `;` before the frames for drop-frame timecodes.
+impl fmt::Display for Timecode {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let separator = if self.drop_frame { ';' } else { ':' };
+
+        write!(
+            formatter,
+            "{:02}:{:02}:{:02}{}{:02}",
+            self.hours, self.minutes, self.seconds, separator, self.frames
+        )
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("Invalid timecode")]
+pub struct ParseTimecodeError;
+
+impl FromStr for Timecode {
+    type Err = ParseTimecodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let drop_frame = s.contains(';') || s.contains(',');
+
+        let fields = s
+            .split(|c| c == ':' || c == ';' || c == '.' || c == ',')
+            .map(|f| f.trim().parse::<u32>().map_err(|_| ParseTimecodeError))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let (hours, minutes, seconds, frames) = match fields[..] {
+            [h, m, s, f] if m < 60 && s < 60 && f < 256 => (h, m, s, f),
+            _ => return Err(ParseTimecodeError),
+        };
+
+        Ok(Timecode {
+            hours,
+            minutes: minutes as u8,
+            seconds: seconds as u8,
+            frames: frames as u8,
+            drop_frame,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timecode(s: &str) -> Timecode {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn drop_frame_29_97() {
+        for &(frame, label) in &[
+            (1799, "00:00:59;29"),
+            (1800, "00:01:00;02"),
+            (17981, "00:09:59;29"),
+            (17982, "00:10:00;00"),
+            (19781, "00:10:59;29"),
+            (19782, "00:11:00;02"),
+        ] {
+            assert_eq!(
+                Timecode::from_frame_number(frame, 30, true),
+                timecode(label)
+            );
+            assert_eq!(timecode(label).to_frame_number(30), Some(frame));
+        }
+    }
+
+    #[test]
+    fn drop_frame_59_94() {
+        for &(frame, label) in &[
+            (3599, "00:00:59;59"),
+            (3600, "00:01:00;04"),
+            (35963, "00:09:59;59"),
+            (35964, "00:10:00;00"),
+            (39559, "00:10:59;59"),
+            (39560, "00:11:00;04"),
+        ] {
+            assert_eq!(
+                Timecode::from_frame_number(frame, 60, true),
+                timecode(label)
+            );
+            assert_eq!(timecode(label).to_frame_number(60), Some(frame));
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        for &(fps, drop_frame) in &[(30, true), (60, true), (25, false)] {
+            for frame in (0..40_000).chain(1_000_000..1_040_000) {
+                let timecode = Timecode::from_frame_number(frame, fps, drop_frame);
+
+                assert_eq!(timecode.to_frame_number(fps), Some(frame), "{}", timecode);
+            }
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            timecode("01:02:03:04"),
+            Timecode {
+                hours: 1,
+                minutes: 2,
+                seconds: 3,
+                frames: 4,
+                drop_frame: false,
+            }
+        );
+        assert!(timecode("01:02:03;04").drop_frame);
+        assert_eq!(timecode("01:02:03;04").to_string(), "01:02:03;04");
+        assert!("01:60:00:00".parse::<Timecode>().is_err());
+        assert!("01:00:00".parse::<Timecode>().is_err());
+    }
+
+    #[test]
+    fn frame_number_overflow() {
+        let timecode = timecode("4294967295:00:00:00");
+
+        assert_eq!(timecode.to_frame_number(30), None);
+    }
+}
@@ -0,0 +1,740 @@
+//! A generic view of the boxes of a file that keeps every byte, so that files
+//! can be edited and written back without losing boxes this crate does not
+//! model. Modelled boxes can be decoded from and encoded into the tree.
+
+use av_format::buffer::{AccReader, Buffered};
+
+use byteorder::{BigEndian, ByteOrder};
+
+use crate::boxes::codec::{
+    avc1::AvcSampleEntryBox, avcc::AvcConfigurationBox, esds::EsdBox,
+    mp4v::Mpeg4VideoSampleEntryBox, stsd::SampleDescriptionBox, tmcd::TimecodeSampleEntryBox,
+    vpcc::VpCodecConfigurationBox, vpxx::Vp9SampleEntryBox, wave::SoundDecompressionParamBox,
+};
+use crate::boxes::{
+    chpl::ChapterListBox, co64::ChunkLargeOffsetBox, cslg::CompositionToDecodeBox,
+    ctts::CompositionOffsetBox, dinf::DataInformationBox, dref::DataReferenceBox, edts::EditBox,
+    elst::EditListBox, ftyp::FileTypeBox, gmhd::BaseMediaHeaderBox, hdlr::HandlerBox,
+    hmhd::HintMediaHeaderBox, ilst::ItemListBox, mdhd::MediaHeaderBox, mdia::MediaBox,
+    mehd::MovieExtendsHeaderBox, meta::MetaBox, mfhd::MovieFragmentHeaderBox,
+    minf::MediaInformationBox, moof::MovieFragmentBox, moov::MovieBox, mvex::MovieExtendsBox,
+    mvhd::MovieHeaderBox, nmhd::NullMediaHeaderBox, sbgp::SampleToGroupBox,
+    sdtp::SampleDependencyTypeBox, sgpd::SampleGroupDescriptionBox, smhd::SoundMediaHeaderBox,
+    stbl::SampleTableBox, stco::ChunkOffsetBox, sthd::SubtitleMediaHeaderBox,
+    stsc::SampleToChunkBox, stss::SyncSampleBox, stsz::SampleSizeBox, stts::TimeToSampleBox,
+    subs::SubSampleInformationBox, tfdt::TrackFragmentBaseMediaDecodeTimeBox,
+    tfhd::TrackFragmentHeaderBox, tkhd::TrackHeaderBox, traf::TrackFragmentBox, trak::TrackBox,
+    tref::TrackReferenceBox, trex::TrackExtendsBox, trun::TrackFragmentRunBox, udta::UserDataBox,
+    url::DataEntryUrlBox, urn::DataEntryUrnBox, vmhd::VideoMediaHeaderBox,
+};
+use crate::*;
+
+use std::convert::TryInto;
+use std::fmt;
+use std::io::{Cursor, Read, Write};
+
+/// How the size of a box is stored in its header.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SizeForm {
+    /// A 32-bit size, switched to a `largesize` if the box outgrows it.
+    Compact,
+    /// A 32-bit size of 1 followed by a 64-bit `largesize`.
+    Large,
+    /// A size of 0, the box extends to the end of its parent or the file.
+    ToEnd,
+    /// The box is cut off at the end of the file: its header claims `size`
+    /// bytes, in a `largesize` if `large` is set, but its contents are only
+    /// the bytes that were left. The claimed size is written back as it is.
+    Truncated { size: u64, large: bool },
+}
+
+#[derive(Clone, PartialEq)]
+pub enum Payload {
+    /// Contents that are not boxes, including those of unknown boxes and of
+    /// containers whose children could not be parsed.
+    Data(Vec<u8>),
+    /// Child boxes, after the fields some containers have before them, e.g.
+    /// the entry count of `stsd` or the fields of a sample entry.
+    Children {
+        fields: Vec<u8>,
+        children: Vec<BoxNode>,
+    },
+}
+
+#[derive(Clone, PartialEq)]
+pub struct BoxNode {
+    pub name: BoxName,
+    pub size_form: SizeForm,
+    /// Extended type of `uuid` boxes.
+    pub user_type: Option<[u8; 16]>,
+    /// Version and flags of full boxes.
+    pub full: Option<(u8, u32)>,
+    pub payload: Payload,
+}
+
+impl fmt::Debug for BoxNode {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let mut debug = formatter.debug_struct("BoxNode");
+        debug
+            .field("name", &BoxPrint(self.name))
+            .field("size", &self.total_size());
+
+        if let Some(children) = self.children() {
+            debug.field("children", &children);
+        }
+
+        debug.finish()
+    }
+}
+
+impl BoxNode {
+    pub fn new(name: BoxName, payload: Payload) -> Self {
+        BoxNode {
+            name,
+            size_form: SizeForm::Compact,
+            user_type: None,
+            full: None,
+            payload,
+        }
+    }
+
+    /// Reads the box at the current position with all its contents. A box
+    /// running past the end of the input keeps the bytes that are left as
+    /// data.
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let start = pos(reader)?;
+        let boks = Boks::read_raw(reader)?;
+
+        let size_form = match (boks.size, boks.read_size) {
+            (0, 8) => SizeForm::ToEnd,
+            (_, 16) => SizeForm::Large,
+            _ => SizeForm::Compact,
+        };
+
+        let contents = match size_form {
+            SizeForm::ToEnd => {
+                let mut contents = Vec::new();
+                reader.read_to_end(&mut contents)?;
+                contents
+            }
+            _ if boks.size < boks.read_size as u64 => {
+                return Err(Mp4BoxError::InvalidBoxSize(BoxPrint(boks.name), boks.size));
+            }
+            _ => {
+                let mut contents = Vec::new();
+                (&mut *reader)
+                    .take(boks.remaining_size())
+                    .read_to_end(&mut contents)?;
+
+                if (contents.len() as u64) < boks.remaining_size() {
+                    deviation!(
+                        at start,
+                        "{:?} is cut off after {} bytes",
+                        boks,
+                        boks.read_size as u64 + contents.len() as u64
+                    )?;
+
+                    return Ok(BoxNode {
+                        name: boks.name,
+                        size_form: SizeForm::Truncated {
+                            size: boks.size,
+                            large: boks.read_size == 16,
+                        },
+                        user_type: None,
+                        full: None,
+                        payload: Payload::Data(contents),
+                    });
+                }
+
+                contents
+            }
+        };
+
+        Ok(Self::parse(boks.name, size_form, contents, None))
+    }
+
+    // children are parsed where they are known to be boxes, anything that
+    // does not add up stays data
+    fn parse(
+        name: BoxName,
+        size_form: SizeForm,
+        mut contents: Vec<u8>,
+        parent: Option<BoxName>,
+    ) -> Self {
+        let mut user_type = None;
+        let mut full = None;
+
+        if name == *b"uuid" && contents.len() >= 16 {
+            user_type = Some(contents[..16].try_into().unwrap());
+            contents.drain(..16);
+        }
+
+        if is_full_box(name, parent, &contents) && contents.len() >= 4 {
+            full = Some((contents[0], BigEndian::read_u24(&contents[1..])));
+            contents.drain(..4);
+        }
+
+        let payload = match fields_size(name, parent, &contents) {
+            Some(size) if size <= contents.len() => match parse_children(&contents[size..], name) {
+                Some(children) => {
+                    contents.truncate(size);
+
+                    Payload::Children {
+                        fields: contents,
+                        children,
+                    }
+                }
+                None => Payload::Data(contents),
+            },
+            _ => Payload::Data(contents),
+        };
+
+        BoxNode {
+            name,
+            size_form,
+            user_type,
+            full,
+            payload,
+        }
+    }
+
+    pub fn write(&self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        let contents_size = self.contents_size();
+        let header_size = self.header_size(contents_size);
+        let mut header = [0u8; 16];
+
+        match (self.size_form, header_size) {
+            (SizeForm::ToEnd, _) => {}
+            (SizeForm::Truncated { size, large: true }, _) => {
+                BigEndian::write_u32(&mut header[..], 1);
+                BigEndian::write_u64(&mut header[8..], size);
+            }
+            (SizeForm::Truncated { size, .. }, _) => {
+                BigEndian::write_u32(&mut header[..], size as u32);
+            }
+            (_, 16) => {
+                BigEndian::write_u32(&mut header[..], 1);
+                BigEndian::write_u64(&mut header[8..], header_size + contents_size);
+            }
+            _ => BigEndian::write_u32(&mut header[..], (header_size + contents_size) as u32),
+        }
+        header[4..8].copy_from_slice(&self.name);
+
+        writer.write_all(&header[..header_size as usize])?;
+
+        if let Some(user_type) = &self.user_type {
+            writer.write_all(user_type)?;
+        }
+
+        if let Some((version, flags)) = self.full {
+            let mut bytes = [0u8; 4];
+            bytes[0] = version;
+            BigEndian::write_u24(&mut bytes[1..], flags);
+
+            writer.write_all(&bytes)?;
+        }
+
+        match &self.payload {
+            Payload::Data(data) => writer.write_all(data)?,
+            Payload::Children { fields, children } => {
+                writer.write_all(fields)?;
+
+                for child in children {
+                    child.write(writer)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        let contents_size = self.contents_size();
+
+        self.header_size(contents_size) + contents_size
+    }
+
+    fn header_size(&self, contents_size: u64) -> u64 {
+        match self.size_form {
+            SizeForm::Compact if contents_size + 8 > u32::MAX as u64 => 16,
+            SizeForm::Compact | SizeForm::ToEnd | SizeForm::Truncated { large: false, .. } => 8,
+            SizeForm::Large | SizeForm::Truncated { large: true, .. } => 16,
+        }
+    }
+
+    fn contents_size(&self) -> u64 {
+        let payload_size = match &self.payload {
+            Payload::Data(data) => data.len() as u64,
+            Payload::Children { fields, children } => {
+                fields.len() as u64 + children.iter().map(|c| c.total_size()).sum::<u64>()
+            }
+        };
+
+        self.user_type.map_or(0, |_| 16) + self.full.map_or(0, |_| 4) + payload_size
+    }
+
+    pub fn children(&self) -> Option<&[BoxNode]> {
+        match &self.payload {
+            Payload::Children { children, .. } => Some(children),
+            Payload::Data(_) => None,
+        }
+    }
+
+    pub fn children_mut(&mut self) -> Option<&mut Vec<BoxNode>> {
+        match &mut self.payload {
+            Payload::Children { children, .. } => Some(children),
+            Payload::Data(_) => None,
+        }
+    }
+
+    /// Returns the first child named `name`.
+    pub fn child(&self, name: BoxName) -> Option<&BoxNode> {
+        self.children()?.iter().find(|c| c.name == name)
+    }
+
+    pub fn child_mut(&mut self, name: BoxName) -> Option<&mut BoxNode> {
+        self.children_mut()?.iter_mut().find(|c| c.name == name)
+    }
+
+    /// Follows the first child of each name, e.g. `[*b"mdia", *b"minf"]`.
+    pub fn find(&self, path: &[BoxName]) -> Option<&BoxNode> {
+        path.iter().try_fold(self, |node, name| node.child(*name))
+    }
+
+    pub fn find_mut(&mut self, path: &[BoxName]) -> Option<&mut BoxNode> {
+        path.iter()
+            .try_fold(self, |node, name| node.child_mut(*name))
+    }
+
+    /// Parses the box with the reader of a modelled box type.
+    pub fn decode<T: TypedBox>(&self) -> Result<T, Mp4BoxError> {
+        if !T::NAMES.contains(&self.name) {
+            return Err(Mp4BoxError::UnexpectedName(
+                BoxPrint(T::NAMES[0]),
+                BoxPrint(self.name),
+            ));
+        }
+
+        let mut bytes = Vec::new();
+        self.write(&mut bytes)?;
+
+        let mut reader = AccReader::new(Cursor::new(bytes));
+        T::read_typed(&mut reader)
+    }
+
+    /// Builds a node from a modelled box, which is stored the way this crate
+    /// writes it.
+    pub fn encode<T: TypedBox>(boks: T) -> Result<Self, Mp4BoxError> {
+        let mut bytes = Vec::new();
+        boks.write_typed(&mut bytes)?;
+
+        let mut reader = AccReader::new(Cursor::new(bytes));
+        Self::read(&mut reader)
+    }
+}
+
+/// The top-level boxes of a file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BoxTree {
+    pub boxes: Vec<BoxNode>,
+    /// Bytes at the end of the file too few for a box header.
+    pub trailing: Vec<u8>,
+}
+
+impl BoxTree {
+    /// Reads boxes up to the end of the input.
+    pub fn read(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+        let mut boxes = Vec::new();
+        let mut trailing = Vec::new();
+
+        loop {
+            reader.fill_buf()?;
+
+            if reader.data().is_empty() {
+                break;
+            }
+
+            let header_size = match peek(reader, 8) {
+                Ok(header) if BigEndian::read_u32(header) == 1 => 16,
+                Ok(_) => 8,
+                Err(_) => 0,
+            };
+
+            if header_size == 0 || peek(reader, header_size).is_err() {
+                let offset = pos(reader)?;
+                reader.read_to_end(&mut trailing)?;
+
+                deviation!(at offset, "{} trailing bytes", trailing.len())?;
+                break;
+            }
+
+            boxes.push(BoxNode::read(reader)?);
+        }
+
+        Ok(BoxTree { boxes, trailing })
+    }
+
+    pub fn write(&self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+        for node in &self.boxes {
+            node.write(writer)?;
+        }
+
+        writer.write_all(&self.trailing)?;
+
+        Ok(())
+    }
+
+    pub fn total_size(&self) -> u64 {
+        self.boxes.iter().map(|b| b.total_size()).sum::<u64>() + self.trailing.len() as u64
+    }
+
+    /// Returns the first top-level box named `name`.
+    pub fn get(&self, name: BoxName) -> Option<&BoxNode> {
+        self.boxes.iter().find(|b| b.name == name)
+    }
+
+    pub fn get_mut(&mut self, name: BoxName) -> Option<&mut BoxNode> {
+        self.boxes.iter_mut().find(|b| b.name == name)
+    }
+
+    /// Follows the first box of each name from the top level, e.g.
+    /// `[*b"moov", *b"trak", *b"tkhd"]`.
+    pub fn find(&self, path: &[BoxName]) -> Option<&BoxNode> {
+        let (first, rest) = path.split_first()?;
+
+        self.get(*first)?.find(rest)
+    }
+
+    pub fn find_mut(&mut self, path: &[BoxName]) -> Option<&mut BoxNode> {
+        let (first, rest) = path.split_first()?;
+
+        self.get_mut(*first)?.find_mut(rest)
+    }
+}
+
+fn is_full_box(name: BoxName, parent: Option<BoxName>, contents: &[u8]) -> bool {
+    match &name {
+        // QuickTime writes meta as a plain box without version and flags
+        b"meta" => contents.len() >= 8 && contents[4..8] != *b"hdlr",
+        b"mean" | b"name" => parent == Some(*b"----"),
+        b"mvhd" | b"tkhd" | b"mdhd" | b"hdlr" | b"vmhd" | b"smhd" | b"sthd" | b"hmhd" | b"nmhd"
+        | b"elst" | b"dref" | b"url " | b"urn " | b"stsd" | b"stts" | b"ctts" | b"cslg"
+        | b"stsc" | b"stsz" | b"stz2" | b"stco" | b"co64" | b"stss" | b"sdtp" | b"sbgp"
+        | b"sgpd" | b"subs" | b"mehd" | b"trex" | b"mfhd" | b"tfhd" | b"tfdt" | b"trun"
+        | b"chpl" | b"esds" | b"vpcC" => true,
+        _ => false,
+    }
+}
+
+// the size of the fields before the children of containers, `None` for
+// boxes without children
+fn fields_size(name: BoxName, parent: Option<BoxName>, contents: &[u8]) -> Option<usize> {
+    if parent == Some(*b"ilst") {
+        return Some(0);
+    }
+
+    if parent == Some(*b"stsd") {
+        return match &name {
+            b"avc1" | b"avc3" | b"hvc1" | b"hev1" | b"mp4v" | b"vp08" | b"vp09" | b"av01"
+            | b"encv" => Some(78),
+            // QuickTime sound descriptions grow with their version
+            b"mp4a" | b"enca" if contents.len() >= 10 => {
+                match BigEndian::read_u16(&contents[8..]) {
+                    0 => Some(28),
+                    1 => Some(44),
+                    2 => Some(64),
+                    _ => None,
+                }
+            }
+            b"tmcd" => Some(26),
+            _ => None,
+        };
+    }
+
+    match &name {
+        b"moov" | b"trak" | b"mdia" | b"minf" | b"stbl" | b"dinf" | b"edts" | b"udta" | b"mvex"
+        | b"moof" | b"traf" | b"mfra" | b"tref" | b"ilst" | b"gmhd" | b"wave" | b"sinf"
+        | b"schi" | b"meta" => Some(0),
+        b"stsd" | b"dref" => Some(4),
+        _ => None,
+    }
+}
+
+fn parse_children(mut data: &[u8], parent: BoxName) -> Option<Vec<BoxNode>> {
+    let mut children = Vec::new();
+
+    while !data.is_empty() {
+        if data.len() < 8 {
+            return None;
+        }
+
+        let name = data[4..8].try_into().unwrap();
+        let (size_form, header_size, size) = match BigEndian::read_u32(data) {
+            0 => (SizeForm::ToEnd, 8, data.len() as u64),
+            1 if data.len() >= 16 => (SizeForm::Large, 16, BigEndian::read_u64(&data[8..])),
+            1 => return None,
+            size => (SizeForm::Compact, 8, size as u64),
+        };
+
+        if size < header_size || size > data.len() as u64 {
+            return None;
+        }
+
+        let contents = data[header_size as usize..size as usize].to_vec();
+        children.push(BoxNode::parse(name, size_form, contents, Some(parent)));
+
+        data = &data[size as usize..];
+    }
+
+    Some(children)
+}
+
+/// A box this crate models, for decoding and encoding `BoxNode`s.
+pub trait TypedBox: Sized {
+    /// The names the box is stored under.
+    const NAMES: &'static [BoxName];
+
+    fn read_typed(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError>;
+
+    fn write_typed(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError>;
+}
+
+macro_rules! typed_boxes {
+    ($($ty:ty => [$($name:literal),+]),* $(,)?) => {
+        $(
+            impl TypedBox for $ty {
+                const NAMES: &'static [BoxName] = &[$(*$name),+];
+
+                fn read_typed(reader: &mut dyn Buffered) -> Result<Self, Mp4BoxError> {
+                    <$ty>::read(reader)
+                }
+
+                fn write_typed(self, writer: &mut dyn Write) -> Result<(), Mp4BoxError> {
+                    self.write(writer)
+                }
+            }
+        )*
+    };
+}
+
+typed_boxes! {
+    FileTypeBox<'static> => [b"ftyp"],
+    MovieBox => [b"moov"],
+    MovieHeaderBox => [b"mvhd"],
+    TrackBox => [b"trak"],
+    TrackHeaderBox => [b"tkhd"],
+    TrackReferenceBox => [b"tref"],
+    EditBox => [b"edts"],
+    EditListBox => [b"elst"],
+    MediaBox => [b"mdia"],
+    MediaHeaderBox => [b"mdhd"],
+    HandlerBox => [b"hdlr"],
+    MediaInformationBox => [b"minf"],
+    VideoMediaHeaderBox => [b"vmhd"],
+    SoundMediaHeaderBox => [b"smhd"],
+    SubtitleMediaHeaderBox => [b"sthd"],
+    HintMediaHeaderBox => [b"hmhd"],
+    NullMediaHeaderBox => [b"nmhd"],
+    BaseMediaHeaderBox => [b"gmhd"],
+    DataInformationBox => [b"dinf"],
+    DataReferenceBox => [b"dref"],
+    DataEntryUrlBox => [b"url "],
+    DataEntryUrnBox => [b"urn "],
+    SampleTableBox => [b"stbl"],
+    SampleDescriptionBox => [b"stsd"],
+    TimeToSampleBox => [b"stts"],
+    CompositionOffsetBox => [b"ctts"],
+    CompositionToDecodeBox => [b"cslg"],
+    SampleToChunkBox => [b"stsc"],
+    SampleSizeBox => [b"stsz", b"stz2"],
+    ChunkOffsetBox => [b"stco"],
+    ChunkLargeOffsetBox => [b"co64"],
+    SyncSampleBox => [b"stss"],
+    SampleDependencyTypeBox => [b"sdtp"],
+    SampleToGroupBox => [b"sbgp"],
+    SampleGroupDescriptionBox => [b"sgpd"],
+    SubSampleInformationBox => [b"subs"],
+    UserDataBox => [b"udta"],
+    MetaBox => [b"meta"],
+    ItemListBox => [b"ilst"],
+    ChapterListBox => [b"chpl"],
+    MovieExtendsBox => [b"mvex"],
+    MovieExtendsHeaderBox => [b"mehd"],
+    TrackExtendsBox => [b"trex"],
+    MovieFragmentBox => [b"moof"],
+    MovieFragmentHeaderBox => [b"mfhd"],
+    TrackFragmentBox => [b"traf"],
+    TrackFragmentHeaderBox => [b"tfhd"],
+    TrackFragmentBaseMediaDecodeTimeBox => [b"tfdt"],
+    TrackFragmentRunBox => [b"trun"],
+    AvcSampleEntryBox => [b"avc1"],
+    AvcConfigurationBox => [b"avcC"],
+    Mpeg4VideoSampleEntryBox => [b"mp4v"],
+    Vp9SampleEntryBox => [b"vp09"],
+    VpCodecConfigurationBox => [b"vpcC"],
+    EsdBox => [b"esds"],
+    SoundDecompressionParamBox => [b"wave"],
+    TimecodeSampleEntryBox => [b"tmcd"],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::boxes::codec::esds::{DecoderConfigDescriptor, EsDescriptor};
+    use crate::test_box as boks;
+
+    // reads `data` as a tree, which has to be written back unchanged
+    fn round_trip(data: &[u8]) -> BoxTree {
+        let mut reader = AccReader::new(Cursor::new(data.to_vec()));
+        let tree = BoxTree::read(&mut reader).unwrap();
+
+        let mut written = Vec::new();
+        tree.write(&mut written).unwrap();
+
+        assert_eq!(written, data);
+        assert_eq!(tree.total_size(), data.len() as u64);
+
+        tree
+    }
+
+    #[test]
+    fn compact() {
+        let tree = round_trip(&boks(b"free", &[1, 2, 3, 4]));
+
+        assert_eq!(tree.boxes[0].size_form, SizeForm::Compact);
+        assert_eq!(tree.boxes[0].payload, Payload::Data(vec![1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn largesize() {
+        let mut data = vec![0, 0, 0, 1];
+        data.extend(b"mdat");
+        data.extend(&20u64.to_be_bytes());
+        data.extend(&[1, 2, 3, 4]);
+
+        let tree = round_trip(&data);
+
+        assert_eq!(tree.boxes[0].size_form, SizeForm::Large);
+        assert_eq!(tree.boxes[0].payload, Payload::Data(vec![1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn size_to_end() {
+        let mut data = boks(b"free", &[]);
+        data.extend(&[0, 0, 0, 0]);
+        data.extend(b"mdat");
+        data.extend(&[1, 2, 3]);
+
+        let tree = round_trip(&data);
+
+        assert_eq!(tree.boxes[1].size_form, SizeForm::ToEnd);
+        assert_eq!(tree.boxes[1].payload, Payload::Data(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn uuid() {
+        let mut contents = vec![7u8; 16];
+        contents.extend(&[1, 2, 3]);
+
+        let tree = round_trip(&boks(b"uuid", &contents));
+
+        assert_eq!(tree.boxes[0].user_type, Some([7; 16]));
+        assert_eq!(tree.boxes[0].payload, Payload::Data(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn full_box() {
+        let tree = round_trip(&boks(b"mehd", &[1, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 5]));
+
+        assert_eq!(tree.boxes[0].full, Some((1, 2)));
+        assert_eq!(
+            tree.boxes[0]
+                .decode::<MovieExtendsHeaderBox>()
+                .unwrap()
+                .fragment_duration,
+            5
+        );
+    }
+
+    #[test]
+    fn unknown() {
+        let tree = round_trip(&boks(b"zzzz", &boks(b"free", &[])));
+
+        assert_eq!(tree.boxes[0].children(), None);
+    }
+
+    #[test]
+    fn nested_containers() {
+        let mehd = boks(b"mehd", &[0, 0, 0, 0, 0, 0, 0, 5]);
+        let mut moov = boks(b"mvex", &mehd);
+        moov.extend(boks(b"zzzz", &[1, 2]));
+
+        let tree = round_trip(&boks(b"moov", &moov));
+
+        let mehd = tree.find(&[*b"moov", *b"mvex", *b"mehd"]).unwrap();
+        assert_eq!(mehd.full, Some((0, 0)));
+        assert_eq!(mehd.payload, Payload::Data(vec![0, 0, 0, 5]));
+
+        let unknown = tree.find(&[*b"moov", *b"zzzz"]).unwrap();
+        assert_eq!(unknown.payload, Payload::Data(vec![1, 2]));
+    }
+
+    #[test]
+    fn children_that_do_not_add_up() {
+        // the child claims more bytes than its parent has
+        let mut mvex = 16u32.to_be_bytes().to_vec();
+        mvex.extend(b"mehd");
+        mvex.extend(&[0, 0, 0, 0]);
+
+        let tree = round_trip(&boks(b"mvex", &mvex));
+
+        assert_eq!(tree.boxes[0].payload, Payload::Data(mvex));
+    }
+
+    #[test]
+    fn truncated() {
+        let mut data = 100u32.to_be_bytes().to_vec();
+        data.extend(b"mdat");
+        data.extend(&[1, 2, 3]);
+
+        let tree = round_trip(&data);
+
+        assert_eq!(
+            tree.boxes[0].size_form,
+            SizeForm::Truncated {
+                size: 100,
+                large: false
+            }
+        );
+        assert_eq!(tree.boxes[0].payload, Payload::Data(vec![1, 2, 3]));
+    }
+
+    #[test]
+    fn trailing_bytes() {
+        let mut data = boks(b"free", &[]);
+        data.extend(&[0, 0, 1]);
+
+        let tree = round_trip(&data);
+
+        assert_eq!(tree.boxes.len(), 1);
+        assert_eq!(tree.trailing, vec![0, 0, 1]);
+    }
+
+    #[test]
+    fn encode_esds() {
+        let config = DecoderConfigDescriptor::new(0x40, 0x15, vec![0x12, 0x10]);
+        let node = BoxNode::encode(EsdBox::new(EsDescriptor::new(1, config))).unwrap();
+
+        let mut written = Vec::new();
+        node.write(&mut written).unwrap();
+        assert_eq!(node.total_size(), written.len() as u64);
+
+        let esds = node.decode::<EsdBox>().unwrap();
+        let config = &esds.descriptor.decoder_description;
+
+        assert_eq!(esds.descriptor.es_id, 1);
+        assert_eq!(config.object_type_indication, 0x40);
+        assert_eq!(config.decoder_specific, vec![0x12, 0x10]);
+    }
+}